@@ -0,0 +1,398 @@
+#![allow(clippy::needless_return)]
+use crate::errors::AssembleError;
+use crate::instruction::Instruction;
+use std::collections::{BTreeMap, HashMap};
+
+// Address programs are loaded at
+const START_ADDR: usize = 0x200;
+
+/*
+ * A statement left to encode once all the labels are known
+ */
+enum Statement<'a> {
+    Instruction(&'a str, Vec<&'a str>),
+    Bytes(Vec<&'a str>),
+    Words(Vec<&'a str>),
+}
+
+/**
+ * The address each line of source was assembled to, so that a debugger can map between the two.
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    // Line number to address, and address to line number
+    addresses: BTreeMap<usize, usize>,
+    lines: BTreeMap<usize, usize>,
+}
+
+impl SourceMap {
+    /**
+     * Get the address of the first instruction or data at or after the line given.
+     * Lines are numbered from 1.
+     */
+    pub fn get_address(&self, line: usize) -> Option<usize> {
+        return self.addresses.range(line..).next().map(|(_, a)| *a);
+    }
+    /// Get the line number of the instruction or data at the address given
+    pub fn get_line(&self, addr: usize) -> Option<usize> {
+        return self.lines.get(&addr).copied();
+    }
+    fn insert(&mut self, line: usize, addr: usize) {
+        self.addresses.insert(line, addr);
+        self.lines.insert(addr, line);
+    }
+}
+
+/**
+ * Assemble CHIP-8 source into the bytes of a ROM, ready to be given to `Processor::load_program`.
+ *
+ * The source uses the same mnemonics as the disassembler, one instruction per line, i.e. `LD V0, 0x05`.
+ * It also supports
+ * * Comments starting with `;`
+ * * Labels, written as `name:` before an instruction or on their own line
+ * * Constants, written as `name = value`, which can use labels and constants defined later in the source
+ * * Data, written as `db 0x01, 0x02` for bytes or `dw 0x0102` for words
+ * * Numbers in decimal, hexadecimal (`0x`) or binary (`0b`), and adding, subtracting or negating them,
+ *   i.e. `label + 2` or `-offset`
+ *
+ * The XO-CHIP long `I` load is written as `LD I, LONG addr`.
+ */
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    return assemble_with_source_map(source).map(|(rom, _)| rom);
+}
+
+/**
+ * Assemble CHIP-8 source like `assemble`, also returning which line every address was assembled from.
+ */
+pub fn assemble_with_source_map(source: &str) -> Result<(Vec<u8>, SourceMap), AssembleError> {
+    let mut symbols: HashMap<&str, i64> = HashMap::new();
+    // Line, name and value of every constant, evaluated once all the labels are known
+    let mut constants: Vec<(usize, &str, &str)> = Vec::new();
+    let mut statements = Vec::new();
+    let mut addr = START_ADDR;
+    let mut source_map = SourceMap::default();
+    // First pass, find the address of every label
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let error = |message: String| AssembleError::new(line_no, message);
+        let mut line = line.split(';').next().unwrap().trim();
+        // Labels
+        while let Some(pos) = line.find(':') {
+            let label = line[..pos].trim();
+            check_symbol_name(label).map_err(error)?;
+            if is_defined(label, &symbols, &constants) {
+                return Err(error(format!("'{}' is already defined", label)));
+            }
+            symbols.insert(label, addr as i64);
+            line = line[(pos + 1)..].trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        // Constants
+        if let Some(pos) = line.find('=') {
+            let name = line[..pos].trim();
+            check_symbol_name(name).map_err(error)?;
+            if is_defined(name, &symbols, &constants) {
+                return Err(error(format!("'{}' is already defined", name)));
+            }
+            constants.push((line_no, name, line[(pos + 1)..].trim()));
+            continue;
+        }
+        let (mnemonic, rest) = match line.find(char::is_whitespace) {
+            Some(pos) => (&line[..pos], line[pos..].trim()),
+            None => (line, ""),
+        };
+        let operands: Vec<&str> = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',').map(|o| o.trim()).collect()
+        };
+        let statement = match mnemonic.to_uppercase().as_str() {
+            "DB" => Statement::Bytes(operands),
+            "DW" => Statement::Words(operands),
+            _ => Statement::Instruction(mnemonic, operands),
+        };
+        let size = match &statement {
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => 2 * values.len(),
+            Statement::Instruction(m, ops) => {
+                if m.eq_ignore_ascii_case("LD")
+                    && ops.len() == 2
+                    && get_long_target(ops[1]).is_some()
+                {
+                    4
+                } else {
+                    2
+                }
+            }
+        };
+        source_map.insert(line_no, addr);
+        addr += size;
+        statements.push((line_no, statement));
+    }
+    // Constants can use each other in any order, so keep evaluating the ones that can be until none are left
+    while !constants.is_empty() {
+        let remaining = constants.len();
+        constants.retain(|(_, name, value)| match evaluate(value, &symbols) {
+            Ok(v) => {
+                symbols.insert(name, v);
+                false
+            }
+            Err(_) => true,
+        });
+        if constants.len() == remaining {
+            return Err(get_constant_error(&constants, &symbols));
+        }
+    }
+    // Second pass, encode everything
+    let mut out = Vec::new();
+    for (line_no, statement) in statements {
+        let error = |message: String| AssembleError::new(line_no, message);
+        match statement {
+            Statement::Bytes(values) => {
+                for v in values {
+                    out.push(check_range(evaluate(v, &symbols), 0xFF).map_err(error)? as u8);
+                }
+            }
+            Statement::Words(values) => {
+                for v in values {
+                    let w = check_range(evaluate(v, &symbols), 0xFFFF).map_err(error)? as u16;
+                    out.extend_from_slice(&w.to_be_bytes());
+                }
+            }
+            Statement::Instruction(mnemonic, operands) => {
+                for w in encode(mnemonic, &operands, &symbols).map_err(error)? {
+                    out.extend_from_slice(&w.to_be_bytes());
+                }
+            }
+        }
+    }
+    return Ok((out, source_map));
+}
+
+// Encode a single instruction into one or two words
+fn encode(mnemonic: &str, ops: &[&str], symbols: &HashMap<&str, i64>) -> Result<Vec<u16>, String> {
+    use Instruction::*;
+    let m = mnemonic.to_uppercase();
+    let value = |op: &str, max: i64| check_range(evaluate(op, symbols), max);
+    let addr = |op: &str| value(op, 0xFFF).map(|v| v as u16);
+    let byte = |op: &str| value(op, 0xFF).map(|v| v as u8);
+    let nibble = |op: &str| value(op, 0xF).map(|v| v as u8);
+    let vx = |op: &str| register(op).ok_or(format!("Expected a register, found '{}'", op));
+    let ops_upper: Vec<String> = ops.iter().map(|o| o.to_uppercase()).collect();
+    let ops_upper: Vec<&str> = ops_upper.iter().map(|o| o.as_str()).collect();
+    let inst = match (m.as_str(), ops_upper.as_slice()) {
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("SCR", []) => ScrollRight,
+        ("SCL", []) => ScrollLeft,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => Lores,
+        ("HIGH", []) => Hires,
+        ("AUDIO", []) => Audio,
+        ("SYS", [_]) => Sys(addr(ops[0])?),
+        ("SCD", [_]) => ScrollDown(nibble(ops[0])?),
+        ("SCU", [_]) => ScrollUp(nibble(ops[0])?),
+        ("PLANE", [_]) => Plane(nibble(ops[0])?),
+        ("JP", ["V0", _]) => JpV0(addr(ops[1])?),
+        ("JP", [_]) => Jp(addr(ops[0])?),
+        ("CALL", [_]) => Call(addr(ops[0])?),
+        ("SE", [_, _]) => match register(ops[1]) {
+            Some(y) => SeReg(vx(ops[0])?, y),
+            None => SeByte(vx(ops[0])?, byte(ops[1])?),
+        },
+        ("SNE", [_, _]) => match register(ops[1]) {
+            Some(y) => SneReg(vx(ops[0])?, y),
+            None => SneByte(vx(ops[0])?, byte(ops[1])?),
+        },
+        ("SAVE", [_, _]) => SaveRange(vx(ops[0])?, vx(ops[1])?),
+        ("LOAD", [_, _]) => LoadRange(vx(ops[0])?, vx(ops[1])?),
+        ("LD", ["I", _]) => match get_long_target(ops[1]) {
+            Some(target) => {
+                let long = value(target, 0xFFFF)? as u16;
+                return Ok(vec![LdILong.encode(), long]);
+            }
+            None => LdI(addr(ops[1])?),
+        },
+        ("LD", ["DT", _]) => LdDtReg(vx(ops[1])?),
+        ("LD", ["ST", _]) => LdStReg(vx(ops[1])?),
+        ("LD", ["F", _]) => LdFont(vx(ops[1])?),
+        ("LD", ["HF", _]) => LdBigFont(vx(ops[1])?),
+        ("LD", ["B", _]) => Bcd(vx(ops[1])?),
+        ("LD", ["[I]", _]) => StoreRegs(vx(ops[1])?),
+        ("LD", ["R", _]) => StoreRpl(vx(ops[1])?),
+        ("LD", [_, "DT"]) => LdRegDt(vx(ops[0])?),
+        ("LD", [_, "K"]) => LdRegKey(vx(ops[0])?),
+        ("LD", [_, "[I]"]) => LoadRegs(vx(ops[0])?),
+        ("LD", [_, "R"]) => LoadRpl(vx(ops[0])?),
+        ("LD", [_, _]) => match register(ops[1]) {
+            Some(y) => LdReg(vx(ops[0])?, y),
+            None => LdByte(vx(ops[0])?, byte(ops[1])?),
+        },
+        ("ADD", ["I", _]) => AddIReg(vx(ops[1])?),
+        ("ADD", [_, _]) => match register(ops[1]) {
+            Some(y) => AddReg(vx(ops[0])?, y),
+            None => AddByte(vx(ops[0])?, byte(ops[1])?),
+        },
+        ("OR", [_, _]) => Or(vx(ops[0])?, vx(ops[1])?),
+        ("AND", [_, _]) => And(vx(ops[0])?, vx(ops[1])?),
+        ("XOR", [_, _]) => Xor(vx(ops[0])?, vx(ops[1])?),
+        ("SUB", [_, _]) => Sub(vx(ops[0])?, vx(ops[1])?),
+        ("SUBN", [_, _]) => Subn(vx(ops[0])?, vx(ops[1])?),
+        ("SHR", [_]) => Shr(vx(ops[0])?, vx(ops[0])?),
+        ("SHR", [_, _]) => Shr(vx(ops[0])?, vx(ops[1])?),
+        ("SHL", [_]) => Shl(vx(ops[0])?, vx(ops[0])?),
+        ("SHL", [_, _]) => Shl(vx(ops[0])?, vx(ops[1])?),
+        ("RND", [_, _]) => Rnd(vx(ops[0])?, byte(ops[1])?),
+        ("DRW", [_, _, _]) => Drw(vx(ops[0])?, vx(ops[1])?, nibble(ops[2])?),
+        ("SKP", [_]) => Skp(vx(ops[0])?),
+        ("SKNP", [_]) => Sknp(vx(ops[0])?),
+        ("PITCH", [_]) => Pitch(vx(ops[0])?),
+        _ => {
+            return Err(format!(
+                "Invalid instruction '{} {}'",
+                mnemonic,
+                ops.join(", ")
+            ))
+        }
+    };
+    return Ok(vec![inst.encode()]);
+}
+
+// Get the index of the register named, if it is one
+fn register(op: &str) -> Option<u8> {
+    let op = op.trim();
+    if op.len() != 2 || !op.starts_with(['V', 'v']) {
+        return None;
+    }
+    return u8::from_str_radix(&op[1..], 16).ok();
+}
+
+// Get the address of an XO-CHIP long I load, i.e. `LONG 0x1234`, if the operand is one
+fn get_long_target(op: &str) -> Option<&str> {
+    let op = op.trim();
+    if !op.get(..4)?.eq_ignore_ascii_case("LONG") {
+        return None;
+    }
+    let target = op.get(4..)?;
+    if !target.starts_with(char::is_whitespace) {
+        return None;
+    }
+    return Some(target.trim());
+}
+
+// Check that a label or constant name is valid
+fn check_symbol_name(name: &str) -> Result<(), String> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("'{}' is not a valid name", name));
+    }
+    if register(name).is_some() {
+        return Err(format!("'{}' is a register", name));
+    }
+    return Ok(());
+}
+
+// Return whether a label or constant with the name given has already been defined
+fn is_defined(name: &str, symbols: &HashMap<&str, i64>, constants: &[(usize, &str, &str)]) -> bool {
+    return symbols.contains_key(name) || constants.iter().any(|(_, c, _)| *c == name);
+}
+
+// Get the error for constants that can't be evaluated, either because one is invalid on its own or
+// because they are defined in terms of each other
+fn get_constant_error(
+    constants: &[(usize, &str, &str)],
+    symbols: &HashMap<&str, i64>,
+) -> AssembleError {
+    let is_pending = |term: &str| constants.iter().any(|(_, c, _)| *c == term);
+    for (line_no, _, value) in constants {
+        // Constants only waiting on other constants might be fine once those are known
+        let waiting = split_terms(value).is_ok_and(|terms| {
+            terms.iter().any(|(_, t)| is_pending(t))
+                && terms.iter().all(|(_, t)| {
+                    parse_number(t).is_some() || symbols.contains_key(t) || is_pending(t)
+                })
+        });
+        if let (false, Err(e)) = (waiting, evaluate(value, symbols)) {
+            return AssembleError::new(*line_no, e);
+        }
+    }
+    let (line_no, name, _) = constants[0];
+    return AssembleError::new(line_no, format!("'{}' is defined in terms of itself", name));
+}
+
+// Evaluate a sum of numbers and symbols, i.e. `label + 0x10 - 2`
+fn evaluate(expr: &str, symbols: &HashMap<&str, i64>) -> Result<i64, String> {
+    let mut total: i64 = 0;
+    for (sign, term) in split_terms(expr)? {
+        let v = match parse_number(term) {
+            Some(v) => v,
+            None => match symbols.get(term) {
+                Some(v) => *v,
+                None => return Err(format!("Unknown symbol '{}'", term)),
+            },
+        };
+        total = v
+            .checked_mul(sign)
+            .and_then(|v| total.checked_add(v))
+            .ok_or(format!("Value '{}' is out of range", expr))?;
+    }
+    return Ok(total);
+}
+
+// Split a sum into each term and whether it is added or subtracted, i.e. `a - -2` into `(1, a)` and `(1, 2)`.
+// Any number of signs can come before a term, so the first term can be negated.
+fn split_terms(expr: &str) -> Result<Vec<(i64, &str)>, String> {
+    let mut terms = Vec::new();
+    let mut rest = expr.trim();
+    if rest.is_empty() {
+        return Err("Expected a value".to_string());
+    }
+    while !rest.is_empty() {
+        let mut sign = 1;
+        while let Some(op) = rest.chars().next().filter(|c| *c == '+' || *c == '-') {
+            if op == '-' {
+                sign = -sign;
+            }
+            rest = rest[1..].trim_start();
+        }
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let term = rest[..end].trim();
+        if term.is_empty() {
+            return Err(format!("Invalid value '{}'", expr));
+        }
+        terms.push((sign, term));
+        rest = &rest[end..];
+    }
+    return Ok(terms);
+}
+
+fn parse_number(term: &str) -> Option<i64> {
+    let lower = term.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        return i64::from_str_radix(hex, 16).ok();
+    }
+    if let Some(bin) = lower.strip_prefix("0b") {
+        return i64::from_str_radix(bin, 2).ok();
+    }
+    return lower.parse().ok();
+}
+
+fn check_range(value: Result<i64, String>, max: i64) -> Result<i64, String> {
+    let v = value?;
+    if v < 0 {
+        return Err(format!("Value {} is out of range, minimum is 0", v));
+    }
+    if v > max {
+        return Err(format!(
+            "Value {:#X} is out of range, maximum is {:#X}",
+            v, max
+        ));
+    }
+    return Ok(v);
+}
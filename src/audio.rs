@@ -0,0 +1,69 @@
+#![allow(clippy::needless_return)]
+use crate::processor::Processor;
+
+extern crate rodio;
+
+use std::io;
+
+mod file_backend;
+mod rodio_backend;
+mod synth;
+mod wav_backend;
+pub use file_backend::FileBackend;
+pub use rodio_backend::RodioBackend;
+pub use synth::{Synth, Tone, Waveform};
+pub use wav_backend::WavBackend;
+
+/// Sample rate the buzzer and XO-CHIP audio patterns are generated at
+pub const SAMPLE_RATE: u32 = 48000;
+
+/**
+ * Somewhere for the CHIP-8 buzzer to be played, i.e. an audio device or a file.
+ *
+ * Interfaces update their backend at least once per frame, so the backend only has to follow the sound
+ * timer and audio pattern.
+ */
+pub trait AudioBackend {
+    /**
+     * Start or stop the sound depending on the processor's sound timer,
+     * switching to its XO-CHIP audio pattern if it has one.
+     */
+    fn update(&mut self, p: &Processor) -> io::Result<()>;
+    /**
+     * Stop the sound and write out anything still buffered, once the emulator exits.
+     */
+    fn finish(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+/**
+ * A backend that never plays anything, for when there is no audio device or sound isn't wanted.
+ */
+#[derive(Debug, Default, Copy, Clone)]
+pub struct NullBackend;
+
+impl AudioBackend for NullBackend {
+    fn update(&mut self, _p: &Processor) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+/**
+ * Open the default audio device to play the tone given, falling back to silence with a warning on stderr
+ * if it can't be.
+ */
+pub fn open_default(tone: Tone) -> Box<dyn AudioBackend> {
+    return match RodioBackend::new(tone) {
+        Ok(b) => Box::new(b),
+        Err(e) => {
+            eprintln!("{}, running without sound", e);
+            Box::new(NullBackend)
+        }
+    };
+}
+
+// Get the XO-CHIP audio pattern and pitch the processor is playing, if any
+fn get_pattern(p: &Processor) -> Option<([u8; 16], u8)> {
+    return p.get_audio_pattern().map(|pat| (pat, p.get_pitch()));
+}
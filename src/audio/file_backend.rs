@@ -0,0 +1,78 @@
+#![allow(clippy::needless_return)]
+use super::{get_pattern, AudioBackend, Synth, Tone, SAMPLE_RATE};
+use crate::processor::Processor;
+use std::io::{self, Write};
+
+// Samples in each tick of the timers, which happen 60 times a second
+const SAMPLES_PER_TICK: u32 = SAMPLE_RATE / 60;
+
+/**
+ * Writes the buzzer to a file or other output instead of playing it, as raw signed 16 bit little endian
+ * mono samples at `SAMPLE_RATE`, i.e. for `ffmpeg -f s16le -ar 48000 -ac 1 -i - out.wav`.
+ *
+ * A 60th of a second of samples is written for every tick of the processor's timers, playing if the
+ * sound timer was set just before the tick. The output keeps time with the emulated timers rather than
+ * the wall clock, so it is the same however often the backend is updated, and while paused nothing is
+ * written.
+ */
+pub struct FileBackend<W: Write> {
+    out: W,
+    synth: Synth,
+    // Ticks of the processor's timers at the last update
+    ticks: Option<u64>,
+    // Samples written so far
+    samples: u64,
+}
+
+impl<W: Write> FileBackend<W> {
+    /// Write the tone given to the output
+    pub fn new(out: W, tone: Tone) -> FileBackend<W> {
+        return FileBackend {
+            out,
+            synth: Synth::new(tone),
+            ticks: None,
+            samples: 0,
+        };
+    }
+    /// Get the number of samples written so far
+    pub fn get_samples(&self) -> u64 {
+        return self.samples;
+    }
+    pub fn get_mut(&mut self) -> &mut W {
+        return &mut self.out;
+    }
+    /// Flush and return the output
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        return Ok(self.out);
+    }
+    // Write the samples for a tick, using the sound as it was at the last update
+    fn write_tick(&mut self) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(SAMPLES_PER_TICK as usize * 2);
+        for _ in 0..SAMPLES_PER_TICK {
+            let sample = self.synth.next_sample();
+            bytes.extend_from_slice(&((sample * i16::MAX as f32) as i16).to_le_bytes());
+        }
+        self.out.write_all(&bytes)?;
+        self.samples += SAMPLES_PER_TICK as u64;
+        return Ok(());
+    }
+}
+
+impl<W: Write> AudioBackend for FileBackend<W> {
+    fn update(&mut self, p: &Processor) -> io::Result<()> {
+        let ticks = p.get_ticks();
+        // A different processor may have fewer ticks, in which case start counting again from it
+        let last = self.ticks.filter(|t| *t <= ticks).unwrap_or(ticks);
+        for _ in last..ticks {
+            self.write_tick()?;
+        }
+        self.ticks = Some(ticks);
+        self.synth.set_pattern(get_pattern(p));
+        self.synth.set_playing(p.get_st() > 0);
+        return Ok(());
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        return self.out.flush();
+    }
+}
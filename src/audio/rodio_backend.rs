@@ -0,0 +1,54 @@
+#![allow(clippy::needless_return)]
+use super::synth::SharedSynth;
+use super::{get_pattern, AudioBackend, Synth, Tone};
+use crate::errors::AudioError;
+use crate::processor::Processor;
+use rodio::{OutputStream, Sink, StreamError};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/**
+ * Plays the buzzer on the default audio device using rodio.
+ *
+ * The device plays a synth for as long as the backend is open, which is silent until the sound timer
+ * is set. Fading the synth in and out instead of pausing the device avoids clicks.
+ */
+pub struct RodioBackend {
+    synth: Arc<Mutex<Synth>>,
+    sink: Sink,
+    // Stream just needs to be kept in scope for as long as the sink is used
+    _stream: OutputStream,
+}
+
+impl RodioBackend {
+    /**
+     * Open the default audio device to play the tone given.
+     */
+    pub fn new(tone: Tone) -> Result<RodioBackend, AudioError> {
+        let (stream, handle) = OutputStream::try_default().map_err(|e| match e {
+            StreamError::NoDevice => AudioError::NoDevice,
+            e => AudioError::Device(e.to_string()),
+        })?;
+        let sink = Sink::try_new(&handle).map_err(|e| AudioError::Device(e.to_string()))?;
+        let synth = Arc::new(Mutex::new(Synth::new(tone)));
+        sink.append(SharedSynth(synth.clone()));
+        return Ok(RodioBackend {
+            synth,
+            sink,
+            _stream: stream,
+        });
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn update(&mut self, p: &Processor) -> io::Result<()> {
+        let mut synth = self.synth.lock().unwrap();
+        synth.set_pattern(get_pattern(p));
+        synth.set_playing(p.get_st() > 0);
+        return Ok(());
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.sink.pause();
+        return Ok(());
+    }
+}
@@ -0,0 +1,181 @@
+#![allow(clippy::needless_return)]
+use super::SAMPLE_RATE;
+use rodio::Source;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Time taken to fade the sound in, in seconds
+const ATTACK: f32 = 0.002;
+// Time taken to fade the sound out, in seconds
+const RELEASE: f32 = 0.005;
+
+/**
+ * Shape of the wave the buzzer plays.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    /// Random levels, changing twice per period so that the frequency still sets the pitch
+    Noise,
+}
+
+/**
+ * How the buzzer sounds when no XO-CHIP audio pattern is loaded.
+ * Patterns play at the pitch set by the program, but still use the volume.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tone {
+    /// Frequency in Hz
+    pub frequency: f32,
+    /// Volume from 0 (silent) to 1
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    /// The beep played by earlier versions, a quiet 350 Hz sine wave
+    fn default() -> Self {
+        return Tone {
+            frequency: 350.0,
+            volume: 0.1,
+            waveform: Waveform::Sine,
+        };
+    }
+}
+
+/**
+ * Generates the buzzer's samples, fading in and out whenever it is started or stopped
+ * so that there are no clicks.
+ *
+ * The same samples are generated for the same calls on every platform, so that files written from it
+ * can be compared.
+ */
+pub struct Synth {
+    tone: Tone,
+    // XO-CHIP audio pattern and the bits of it played per sample, if one is loaded
+    pattern: Option<([u8; 16], f32)>,
+    playing: bool,
+    // Position in the current period from 0 to 1, or in the pattern in bits
+    phase: f32,
+    // Volume of the envelope from 0 to 1
+    level: f32,
+    // State of the noise generator, and the level it is currently holding
+    noise: u32,
+    noise_level: f32,
+}
+
+impl Synth {
+    pub fn new(tone: Tone) -> Synth {
+        return Synth {
+            tone,
+            pattern: None,
+            playing: false,
+            phase: 0.0,
+            level: 0.0,
+            noise: 0x2545_F491,
+            noise_level: 0.0,
+        };
+    }
+    pub fn get_tone(&self) -> Tone {
+        return self.tone;
+    }
+    /// Start or stop the sound, fading it in or out
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+    pub fn is_playing(&self) -> bool {
+        return self.playing;
+    }
+    /**
+     * Play an XO-CHIP audio pattern at the pitch given instead of the tone, or go back to the tone.
+     * Each of the 128 bits in the pattern is played as a single sample, at a rate set by the pitch.
+     */
+    pub fn set_pattern(&mut self, pattern: Option<([u8; 16], u8)>) {
+        let next = pattern.map(|(pat, pitch)| {
+            let rate = 4000.0 * 2.0_f32.powf((pitch as f32 - 64.0) / 48.0);
+            (pat, rate / SAMPLE_RATE as f32)
+        });
+        if next != self.pattern {
+            self.pattern = next;
+            self.phase = 0.0;
+        }
+    }
+    /// Generate the next sample, from -1 to 1
+    pub fn next_sample(&mut self) -> f32 {
+        let target = if self.playing { 1.0 } else { 0.0 };
+        if self.level < target {
+            self.level = (self.level + 1.0 / (ATTACK * SAMPLE_RATE as f32)).min(1.0);
+        } else if self.level > target {
+            self.level = (self.level - 1.0 / (RELEASE * SAMPLE_RATE as f32)).max(0.0);
+        }
+        if self.level == 0.0 {
+            // Start from the beginning of the wave next time
+            self.phase = 0.0;
+            return 0.0;
+        }
+        let wave = match self.pattern {
+            Some((pattern, step)) => {
+                let bit = self.phase as usize;
+                self.phase = (self.phase + step) % 128.0;
+                if (pattern[bit / 8] >> (7 - bit % 8)) & 0x1 == 1 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            None => self.next_tone_sample(),
+        };
+        return wave * self.level * self.tone.volume;
+    }
+    // Generate the next sample of the tone at full volume
+    fn next_tone_sample(&mut self) -> f32 {
+        let phase = self.phase;
+        let next = phase + self.tone.frequency / SAMPLE_RATE as f32;
+        // Pick a new noise level every half period
+        if phase == 0.0 || (next * 2.0).floor() != (phase * 2.0).floor() {
+            self.noise ^= self.noise << 13;
+            self.noise ^= self.noise >> 17;
+            self.noise ^= self.noise << 5;
+            self.noise_level = self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0;
+        }
+        self.phase = next % 1.0;
+        return match self.tone.waveform {
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Noise => self.noise_level,
+        };
+    }
+}
+
+/**
+ * A synth shared with the audio thread, playing forever as a rodio source.
+ */
+pub(super) struct SharedSynth(pub Arc<Mutex<Synth>>);
+
+impl Iterator for SharedSynth {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        return Some(self.0.lock().unwrap().next_sample());
+    }
+}
+
+impl Source for SharedSynth {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        1
+    }
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,73 @@
+#![allow(clippy::needless_return)]
+use super::{AudioBackend, FileBackend, Tone, SAMPLE_RATE};
+use crate::processor::Processor;
+use std::io::{self, Seek, SeekFrom, Write};
+
+// Size of the header before the samples
+const HEADER_SIZE: u32 = 44;
+
+/**
+ * Writes the buzzer to a WAV file, as 16 bit mono samples at `SAMPLE_RATE`.
+ *
+ * Samples are written the same way as `FileBackend`, in time with the processor's timers, so the same
+ * run always produces the same file. The sizes in the header are filled in by `finish`, which is why
+ * the output has to be seekable.
+ */
+pub struct WavBackend<W: Write + Seek> {
+    inner: FileBackend<W>,
+}
+
+impl<W: Write + Seek> WavBackend<W> {
+    /// Write the header, leaving the sizes empty until finished
+    pub fn new(mut out: W, tone: Tone) -> io::Result<WavBackend<W>> {
+        out.write_all(&get_header(0))?;
+        return Ok(WavBackend {
+            inner: FileBackend::new(out, tone),
+        });
+    }
+    /// Get the number of samples written so far
+    pub fn get_samples(&self) -> u64 {
+        return self.inner.get_samples();
+    }
+    /// Finish the file and return the output
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.finish()?;
+        return self.inner.into_inner();
+    }
+}
+
+impl<W: Write + Seek> AudioBackend for WavBackend<W> {
+    fn update(&mut self, p: &Processor) -> io::Result<()> {
+        return self.inner.update(p);
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        let data_size = (self.inner.get_samples() * 2).min((u32::MAX - HEADER_SIZE) as u64) as u32;
+        let out = self.inner.get_mut();
+        out.seek(SeekFrom::Start(0))?;
+        out.write_all(&get_header(data_size))?;
+        out.seek(SeekFrom::End(0))?;
+        return out.flush();
+    }
+}
+
+// Get the header of a WAV file with the number of bytes of samples given
+fn get_header(data_size: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_SIZE as usize);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(HEADER_SIZE - 8 + data_size).to_le_bytes());
+    out.extend_from_slice(b"WAVE");
+    out.extend_from_slice(b"fmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, 1 channel
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    // Bytes per second and per sample
+    out.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    out.extend_from_slice(&2u16.to_le_bytes());
+    // Bits per sample
+    out.extend_from_slice(&16u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&data_size.to_le_bytes());
+    return out;
+}
@@ -0,0 +1,106 @@
+#![allow(clippy::needless_return)]
+use crate::keymap::{parse_chip8_key, KeyMap};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/**
+ * Settings read from a TOML config file, for the ROM being run.
+ *
+ * ```toml
+ * [keymap]
+ * 5 = ["w", "up"]
+ * 0 = "x"
+ *
+ * # Only for ROMs with this file name
+ * [roms."pong.ch8".keymap]
+ * 1 = ["w", "up"]
+ * 4 = ["s", "down"]
+ * ```
+ *
+ * Each key map entry binds a CHIP-8 key, from `0` to `F`, to a key or list of keys.
+ * Keys that aren't listed keep their default binding, and the ROM's own key map is applied on top of
+ * the shared one.
+ */
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Config {
+    pub keymap: KeyMap,
+}
+
+impl Config {
+    /**
+     * Parse a config file, applying any overrides for the ROM with the file name given.
+     */
+    pub fn parse(text: &str, rom: Option<&str>) -> Result<Config, String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        let mut config = Config::default();
+        for (name, value) in table.iter() {
+            match name.as_str() {
+                "keymap" => apply_keymap(&mut config.keymap, value)?,
+                "roms" => {}
+                _ => return Err(format!("Unknown section '{}'", name)),
+            }
+        }
+        let roms = match table.get("roms") {
+            Some(Value::Table(roms)) => roms,
+            Some(_) => return Err("'roms' should be a table of ROM file names".to_string()),
+            None => return Ok(config),
+        };
+        for (name, value) in roms.iter() {
+            let keymap = match value {
+                Value::Table(t) => t.get("keymap"),
+                _ => return Err(format!("'roms.{}' should be a table", name)),
+            };
+            // Check every ROM's key map, not just the one being run, so mistakes are found straight away
+            let mut rom_keymap = config.keymap.clone();
+            if let Some(keymap) = keymap {
+                apply_keymap(&mut rom_keymap, keymap)?;
+            }
+            if rom.is_some_and(|r| r.eq_ignore_ascii_case(name)) {
+                config.keymap = rom_keymap;
+            }
+        }
+        return Ok(config);
+    }
+    /**
+     * Read and parse the config file at the path given, for the ROM at the path given.
+     */
+    pub fn load(path: &Path, rom: Option<&Path>) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let rom = rom.and_then(|r| r.file_name()).and_then(|r| r.to_str());
+        return Config::parse(&text, rom);
+    }
+}
+
+/**
+ * Get the path of the config file used if none is given,
+ * `$XDG_CONFIG_HOME/rust-chip8-opengl/config.toml` or `~/.config/rust-chip8-opengl/config.toml`.
+ */
+pub fn default_path() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")))?;
+    return Some(dir.join("rust-chip8-opengl").join("config.toml"));
+}
+
+// Apply the bindings in a keymap table, each a key name or list of them
+fn apply_keymap(keymap: &mut KeyMap, value: &Value) -> Result<(), String> {
+    let table = value
+        .as_table()
+        .ok_or("'keymap' should be a table of CHIP-8 keys")?;
+    for (chip8_key, keys) in table.iter() {
+        let chip8_key = parse_chip8_key(chip8_key)?;
+        let keys: Vec<&str> = match keys {
+            Value::String(k) => vec![k.as_str()],
+            Value::Array(ks) => ks
+                .iter()
+                .map(|k| k.as_str().ok_or("Keys should be strings"))
+                .collect::<Result<Vec<&str>, &str>>()?,
+            _ => return Err("Keys should be a string or a list of strings".to_string()),
+        };
+        keymap.set_keys(chip8_key, &keys);
+    }
+    return Ok(());
+}
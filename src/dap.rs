@@ -0,0 +1,583 @@
+#![allow(clippy::needless_return)]
+use crate::assembler::{assemble_with_source_map, SourceMap};
+use crate::debugger::{parse_address, Debugger, StopReason};
+use crate::emulator::{Emulator, DEFAULT_INSTRUCTIONS_PER_FRAME};
+use crate::instruction::Instruction;
+use crate::interfaces::HeadlessInterface;
+use crate::processor::{Processor, Variant};
+use crate::quirks::Quirks;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+
+// There is only ever one thread, the processor
+const THREAD_ID: i64 = 1;
+// Frames run each second, the same as when running normally
+const FRAME_RATE: u64 = 60;
+// Variable references of each scope
+const REGISTERS_REFERENCE: i64 = 1;
+const STACK_REFERENCE: i64 = 2;
+const MEMORY_REFERENCE: i64 = 3;
+// Bytes of memory shown in each variable of the memory scope
+const MEMORY_ROW_SIZE: usize = 16;
+
+/**
+ * Read a single message of the Debug Adapter Protocol, returning `None` once the input has ended.
+ */
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or(io::Error::new(
+        ErrorKind::InvalidData,
+        "Missing Content-Length header",
+    ))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    return serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e));
+}
+
+/**
+ * Write a single message of the Debug Adapter Protocol.
+ */
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    return writer.flush();
+}
+
+/**
+ * Serve the Debug Adapter Protocol, reading requests from the input and writing responses and events
+ * to the output, until the client disconnects.
+ * The program is run at the normal speed, but without any display or input.
+ */
+pub fn serve<R: BufRead + Send + 'static, W: Write>(input: R, output: W) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    // Read on another thread so that the program can keep running while waiting for requests
+    thread::spawn(move || {
+        let mut input = input;
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    let mut session = DapSession::new(output);
+    while !session.is_terminated() {
+        // Nothing happens until the next request while paused, so just wait for it
+        let message = if session.is_running() {
+            match receiver.try_recv() {
+                Ok(m) => Some(m),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(m) => Some(m),
+                Err(_) => break,
+            }
+        };
+        match message {
+            Some(m) => session.handle(&m)?,
+            None => {
+                session.run_frame()?;
+                session.wait_for_next_frame();
+            }
+        }
+    }
+    return Ok(());
+}
+
+/**
+ * A Debug Adapter Protocol session debugging a single program.
+ *
+ * Programs are launched from a ROM, or from assembler source ending in `.asm`, in which case breakpoints
+ * can be set on source lines. Breakpoints can also be set on addresses with instruction breakpoints.
+ * The registers, stack and memory are exposed as variable scopes, and errors such as invalid opcodes are
+ * reported as exceptions.
+ *
+ * Requests are given to `handle`, and `run_frame` followed by `wait_for_next_frame` should be called
+ * while running. The program runs in an `Emulator`, so frames and timers work the same as in normal runs.
+ */
+pub struct DapSession<W: Write> {
+    out: W,
+    seq: i64,
+    emulator: Option<Emulator>,
+    // Path and source map of the program, if it was assembled from source
+    source: Option<(String, SourceMap)>,
+    // Breakpoints set on source lines and on addresses, which are both given to the debugger
+    source_breakpoints: Vec<usize>,
+    instruction_breakpoints: Vec<usize>,
+    stop_on_entry: bool,
+    configured: bool,
+    terminated: bool,
+}
+
+impl<W: Write> DapSession<W> {
+    pub fn new(out: W) -> DapSession<W> {
+        return DapSession {
+            out,
+            seq: 0,
+            emulator: None,
+            source: None,
+            source_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            configured: false,
+            terminated: false,
+        };
+    }
+    /// Get the processor running the program, once it has been launched
+    pub fn get_processor(&self) -> Option<&Processor> {
+        return self.emulator.as_ref().map(|e| e.get_processor());
+    }
+    /// Return whether the program is running, and `run_frame` should be called
+    pub fn is_running(&self) -> bool {
+        return self
+            .emulator
+            .as_ref()
+            .is_some_and(|e| !e.get_debugger().is_paused())
+            && !self.terminated;
+    }
+    /// Return whether the session has ended
+    pub fn is_terminated(&self) -> bool {
+        return self.terminated;
+    }
+    /**
+     * Run the rest of the current frame, if the program is running.
+     * Sends a stopped event if the program pauses.
+     */
+    pub fn run_frame(&mut self) -> io::Result<()> {
+        if !self.is_running() {
+            return Ok(());
+        }
+        let emulator = self.emulator.as_mut().unwrap();
+        let stopped = emulator.run_frame();
+        if emulator.is_finished() {
+            self.send_event("exited", json!({ "exitCode": 0 }))?;
+            self.terminated = true;
+            return self.send_event("terminated", json!({}));
+        }
+        return match stopped {
+            Some(reason) => self.on_stop(&reason),
+            None => Ok(()),
+        };
+    }
+    /// Wait until the next frame is due, once a program has been launched
+    pub fn wait_for_next_frame(&mut self) {
+        if let Some(e) = self.emulator.as_mut() {
+            e.wait_for_next_frame();
+        }
+    }
+    // Get the debugger, once a program has been launched
+    fn get_debugger_mut(&mut self) -> Option<&mut Debugger> {
+        return self.emulator.as_mut().map(|e| e.get_debugger_mut());
+    }
+    /**
+     * Handle a request from the client, sending the response and any events.
+     */
+    pub fn handle(&mut self, message: &Value) -> io::Result<()> {
+        if message["type"] != "request" {
+            return Ok(());
+        }
+        let command = message["command"].as_str().unwrap_or("");
+        let args = &message["arguments"];
+        let body = match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "configurationDone" => {
+                self.configured = true;
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                    { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+                    { "name": "Memory", "variablesReference": MEMORY_REFERENCE, "expensive": true },
+                ]
+            })),
+            "variables" => self.variables(args),
+            "continue" => {
+                if let Some(d) = self.get_debugger_mut() {
+                    d.resume();
+                }
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => match self.emulator.as_mut() {
+                Some(e) => {
+                    let (p, debugger, _) = e.split_mut();
+                    match command {
+                        "next" => debugger.step_over(p),
+                        "stepIn" => debugger.step_into(),
+                        _ => debugger.step_out(p),
+                    }
+                    Ok(json!({}))
+                }
+                None => Err("No program has been launched".to_string()),
+            },
+            "pause" => {
+                if let Some(d) = self.get_debugger_mut() {
+                    d.pause();
+                }
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.terminated = true;
+                Ok(json!({}))
+            }
+            _ => Err(format!("Unsupported request '{}'", command)),
+        };
+        self.send_response(message, command, body)?;
+        // Events that have to come after the response
+        match command {
+            // Breakpoints can only be set once the program is loaded
+            "launch" if self.emulator.is_some() => self.send_event("initialized", json!({}))?,
+            "pause" => self.on_stop(&StopReason::Pause)?,
+            "disconnect" | "terminate" => self.send_event("terminated", json!({}))?,
+            _ => {}
+        }
+        if self.configured && self.emulator.is_some() {
+            self.configured = false;
+            if self.stop_on_entry {
+                self.send_stopped("entry", "Stopped on entry".to_string())?;
+            } else if let Some(d) = self.get_debugger_mut() {
+                d.resume();
+            }
+        }
+        return Ok(());
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["program"]
+            .as_str()
+            .ok_or("No program given to launch".to_string())?;
+        let rom = if Path::new(path).extension().is_some_and(|e| e == "asm") {
+            let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            let (rom, map) =
+                assemble_with_source_map(&source).map_err(|e| format!("{}: {}", path, e))?;
+            self.source = Some((path.to_string(), map));
+            rom
+        } else {
+            fs::read(path).map_err(|e| format!("{}: {}", path, e))?
+        };
+        let variant = match args["variant"].as_str() {
+            Some("chip8") | None => Variant::Chip8,
+            Some("schip") => Variant::SuperChip,
+            Some("xochip") => Variant::XoChip,
+            Some(v) => return Err(format!("Unknown variant '{}'", v)),
+        };
+        let mut p = Processor::new_with_quirks(variant, parse_quirks(&args["quirks"], variant)?);
+        if let Some(seed) = args["seed"].as_u64() {
+            p.seed_rng(seed);
+        }
+        p.load_program(&rom);
+        let mut emulator = Emulator::new(p, Box::new(HeadlessInterface::new()));
+        emulator.set_frame_rate(FRAME_RATE);
+        match args["ips"].as_u64() {
+            Some(ips) => emulator.set_instructions_per_second(ips),
+            None => emulator.set_instructions_per_frame(
+                args["ipf"]
+                    .as_u64()
+                    .unwrap_or(DEFAULT_INSTRUCTIONS_PER_FRAME),
+            ),
+        }
+        // Nothing runs until the client has finished setting breakpoints
+        emulator.get_debugger_mut().pause();
+        self.emulator = Some(emulator);
+        self.update_breakpoints();
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        return Ok(json!({}));
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"].as_str().unwrap_or("");
+        let lines: Vec<usize> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|b| b["line"].as_u64())
+            .map(|l| l as usize)
+            .collect();
+        let map = match &self.source {
+            Some((source, map)) if same_file(source, path) => map,
+            _ => {
+                let breakpoints: Vec<Value> = lines
+                    .iter()
+                    .map(|l| json!({ "verified": false, "line": l, "message": "Not part of the program" }))
+                    .collect();
+                return Ok(json!({ "breakpoints": breakpoints }));
+            }
+        };
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            // Breakpoints on lines without any code move to the next line with some
+            match map.get_address(line) {
+                Some(addr) => {
+                    addresses.push(addr);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": map.get_line(addr),
+                        "instructionReference": format!("{:#05X}", addr),
+                    }));
+                }
+                None => breakpoints.push(json!({ "verified": false, "line": line })),
+            }
+        }
+        self.source_breakpoints = addresses;
+        self.update_breakpoints();
+        return Ok(json!({ "breakpoints": breakpoints }));
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let mut addresses = Vec::new();
+        let mut breakpoints = Vec::new();
+        for b in args["breakpoints"].as_array().into_iter().flatten() {
+            let addr = b["instructionReference"]
+                .as_str()
+                .and_then(parse_address)
+                .map(|a| a as i64 + b["offset"].as_i64().unwrap_or(0));
+            match addr {
+                Some(addr) if addr >= 0 => {
+                    addresses.push(addr as usize);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format!("{:#05X}", addr),
+                    }));
+                }
+                _ => breakpoints.push(json!({ "verified": false, "message": "Invalid address" })),
+            }
+        }
+        self.instruction_breakpoints = addresses;
+        self.update_breakpoints();
+        return Ok(json!({ "breakpoints": breakpoints }));
+    }
+
+    // Give the debugger both the source and instruction breakpoints
+    fn update_breakpoints(&mut self) {
+        let debugger = match self.emulator.as_mut() {
+            Some(e) => e.get_debugger_mut(),
+            None => return,
+        };
+        let old: Vec<usize> = debugger.get_breakpoints().copied().collect();
+        old.iter().for_each(|a| debugger.remove_breakpoint(*a));
+        self.source_breakpoints
+            .iter()
+            .chain(self.instruction_breakpoints.iter())
+            .for_each(|a| debugger.add_breakpoint(*a));
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let p = self
+            .get_processor()
+            .ok_or("No program has been launched".to_string())?;
+        // The stack holds the address of each call, and the current instruction is at the top
+        let mut addresses = vec![p.get_program_counter()];
+        addresses.extend(p.get_stack().iter().rev().map(|a| *a as usize));
+        let frames: Vec<Value> = addresses
+            .iter()
+            .enumerate()
+            .map(|(id, addr)| {
+                let name = match Instruction::decode(p.get_word_at(*addr)) {
+                    Ok(i) => format!("{:#05X}  {}", addr, i),
+                    Err(_) => format!("{:#05X}", addr),
+                };
+                let mut frame = json!({
+                    "id": id,
+                    "name": name,
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{:#05X}", addr),
+                });
+                if let Some((path, map)) = &self.source {
+                    if let Some(line) = map.get_line(*addr) {
+                        frame["line"] = json!(line);
+                        frame["source"] = json!({ "path": path });
+                    }
+                }
+                return frame;
+            })
+            .collect();
+        return Ok(json!({ "stackFrames": frames, "totalFrames": addresses.len() }));
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let p = self
+            .get_processor()
+            .ok_or("No program has been launched".to_string())?;
+        let variables: Vec<(String, String)> = match args["variablesReference"].as_i64() {
+            Some(REGISTERS_REFERENCE) => {
+                let mut registers: Vec<(String, String)> = (0..0x10)
+                    .map(|r| {
+                        (
+                            format!("V{:X}", r),
+                            format!("{:#04X}", p.get_register_value(r)),
+                        )
+                    })
+                    .collect();
+                registers.extend([
+                    ("I".to_string(), format!("{:#05X}", p.get_i())),
+                    (
+                        "PC".to_string(),
+                        format!("{:#05X}", p.get_program_counter()),
+                    ),
+                    ("SP".to_string(), format!("{}", p.get_stack_pointer())),
+                    ("DT".to_string(), format!("{:#04X}", p.get_dt())),
+                    ("ST".to_string(), format!("{:#04X}", p.get_st())),
+                ]);
+                registers
+            }
+            Some(STACK_REFERENCE) => p
+                .get_stack()
+                .iter()
+                .enumerate()
+                .map(|(i, a)| (i.to_string(), format!("{:#05X}", a)))
+                .collect(),
+            Some(MEMORY_REFERENCE) => (0..p.get_mem_size())
+                .step_by(MEMORY_ROW_SIZE)
+                .map(|start| {
+                    let end = (start + MEMORY_ROW_SIZE).min(p.get_mem_size());
+                    let bytes: Vec<String> = (start..end)
+                        .map(|a| format!("{:02X}", p.get_mem_at(a)))
+                        .collect();
+                    (format!("{:#05X}", start), bytes.join(" "))
+                })
+                .collect(),
+            _ => return Err("Unknown variables reference".to_string()),
+        };
+        let start = args["start"].as_u64().unwrap_or(0) as usize;
+        let count = match args["count"].as_u64() {
+            Some(c) if c > 0 => c as usize,
+            _ => variables.len(),
+        };
+        let variables: Vec<Value> = variables
+            .into_iter()
+            .skip(start)
+            .take(count)
+            .map(|(name, value)| json!({ "name": name, "value": value, "variablesReference": 0 }))
+            .collect();
+        return Ok(json!({ "variables": variables }));
+    }
+
+    fn on_stop(&mut self, reason: &StopReason) -> io::Result<()> {
+        let kind = match reason {
+            StopReason::Pause => "pause",
+            StopReason::Step => "step",
+            StopReason::Breakpoint(_) | StopReason::Condition(_) => "breakpoint",
+            StopReason::Watchpoint { .. } => "data breakpoint",
+            StopReason::Error(_) => "exception",
+        };
+        return self.send_stopped(kind, reason.to_string());
+    }
+
+    fn send_stopped(&mut self, reason: &str, text: String) -> io::Result<()> {
+        return self.send_event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "description": text,
+                "text": text,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        );
+    }
+
+    fn send_response(
+        &mut self,
+        request: &Value,
+        command: &str,
+        body: Result<Value, String>,
+    ) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        return self.send(response);
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        return self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        return write_message(&mut self.out, &message);
+    }
+}
+
+// Whether two paths are the same file, even if one is relative
+fn same_file(a: &str, b: &str) -> bool {
+    return match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    };
+}
+
+// Parse the quirks given to launch, either the name of a preset or an object of individual quirks to
+// change from the ones for the variant, i.e. `{ "display_wait": false }`
+fn parse_quirks(value: &Value, variant: Variant) -> Result<Quirks, String> {
+    let overrides = match value {
+        Value::Null => return Ok(Quirks::for_variant(variant)),
+        Value::String(preset) => {
+            return match preset.as_str() {
+                "vip" => Ok(Quirks::cosmac_vip()),
+                "chip48" => Ok(Quirks::chip48()),
+                "schip" => Ok(Quirks::schip()),
+                "xochip" => Ok(Quirks::xo_chip()),
+                _ => Err(format!("Unknown quirks preset '{}'", preset)),
+            }
+        }
+        Value::Object(overrides) => overrides,
+        _ => return Err("Quirks should be a preset name or an object".to_string()),
+    };
+    let mut quirks = Quirks::for_variant(variant);
+    for (name, value) in overrides.iter() {
+        let quirk = match name.as_str() {
+            "shift_uses_vy" => &mut quirks.shift_uses_vy,
+            "load_store_increments_i" => &mut quirks.load_store_increments_i,
+            "logic_resets_vf" => &mut quirks.logic_resets_vf,
+            "clip_sprites" => &mut quirks.clip_sprites,
+            "display_wait" => &mut quirks.display_wait,
+            "jump_uses_vx" => &mut quirks.jump_uses_vx,
+            _ => return Err(format!("Unknown quirk '{}'", name)),
+        };
+        *quirk = value
+            .as_bool()
+            .ok_or(format!("Quirk '{}' should be true or false", name))?;
+    }
+    return Ok(quirks);
+}
@@ -0,0 +1,386 @@
+#![allow(clippy::needless_return)]
+use crate::errors::EmulatorError;
+use crate::instruction::Instruction;
+use crate::processor::Processor;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/**
+ * A register of the processor that can be used in a breakpoint condition.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Register {
+    /// One of V0 through VF
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+impl Register {
+    /// Get the value of the register in the processor given
+    pub fn get_value(&self, p: &Processor) -> usize {
+        return match *self {
+            Register::V(x) => p.get_register_value(x) as usize,
+            Register::I => p.get_i() as usize,
+            Register::Pc => p.get_program_counter(),
+            Register::Sp => p.get_stack_pointer(),
+            Register::Dt => p.get_dt() as usize,
+            Register::St => p.get_st() as usize,
+        };
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+/**
+ * A condition on a register, i.e. `V0 == 0x05`.
+ * Used to break when the condition becomes true.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Equal),
+    ("!=", Comparison::NotEqual),
+    ("<=", Comparison::LessOrEqual),
+    (">=", Comparison::GreaterOrEqual),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+];
+
+impl Condition {
+    /// Return whether the condition is true for the processor given
+    pub fn is_met(&self, p: &Processor) -> bool {
+        let v = self.register.get_value(p);
+        return match self.comparison {
+            Comparison::Equal => v == self.value,
+            Comparison::NotEqual => v != self.value,
+            Comparison::Less => v < self.value,
+            Comparison::LessOrEqual => v <= self.value,
+            Comparison::Greater => v > self.value,
+            Comparison::GreaterOrEqual => v >= self.value,
+        };
+    }
+}
+
+/**
+ * Parse a condition such as `V0 == 0x05`, `I >= 0x400` or `DT != 0`.
+ */
+impl FromStr for Condition {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Condition, String> {
+        let (op, comparison) = COMPARISONS
+            .iter()
+            .find(|(op, _)| s.contains(op))
+            .ok_or(format!("No comparison in condition '{}'", s))?;
+        let (register, value) = s.split_once(op).unwrap();
+        let register = match register.trim().to_uppercase().as_str() {
+            "I" => Register::I,
+            "PC" => Register::Pc,
+            "SP" => Register::Sp,
+            "DT" => Register::Dt,
+            "ST" => Register::St,
+            r if r.len() == 2 && r.starts_with('V') => match u8::from_str_radix(&r[1..], 16) {
+                Ok(x) => Register::V(x),
+                Err(_) => return Err(format!("Unknown register '{}'", r)),
+            },
+            r => return Err(format!("Unknown register '{}'", r)),
+        };
+        let value = parse_address(value).ok_or(format!("Invalid value '{}'", value.trim()))?;
+        return Ok(Condition {
+            register,
+            comparison: *comparison,
+            value,
+        });
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (op, _) = COMPARISONS
+            .iter()
+            .find(|(_, c)| *c == self.comparison)
+            .unwrap();
+        write!(f, "{} {} {:#X}", self.register, op, self.value)
+    }
+}
+
+/**
+ * Parse an address or value, either in hexadecimal starting with `0x` or in decimal.
+ */
+pub fn parse_address(s: &str) -> Option<usize> {
+    let s = s.trim();
+    return match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    };
+}
+
+/**
+ * Why the debugger paused the program.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// The user paused the program
+    Pause,
+    /// A step, step over or step out finished
+    Step,
+    /// The program counter reached a breakpoint
+    Breakpoint(usize),
+    /// A watched memory address was written to
+    Watchpoint { address: usize, old: u8, new: u8 },
+    /// A register condition became true
+    Condition(Condition),
+    /// The processor could not run the instruction
+    Error(EmulatorError),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Pause => write!(f, "Paused"),
+            StopReason::Step => write!(f, "Stepped"),
+            StopReason::Breakpoint(addr) => write!(f, "Breakpoint at {:#05X}", addr),
+            StopReason::Watchpoint { address, old, new } => write!(
+                f,
+                "Memory at {:#05X} changed from {:#04X} to {:#04X}",
+                address, old, new
+            ),
+            StopReason::Condition(c) => write!(f, "Condition {} is true", c),
+            StopReason::Error(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Paused,
+    // Run a single instruction
+    Step,
+    // Run until the subroutine being called returns
+    StepOver { sp: usize, return_pc: usize },
+    // Run until the current subroutine returns
+    StepOut { sp: usize },
+}
+
+/**
+ * Debugger that runs a processor one instruction at a time, and pauses it on breakpoints,
+ * memory watchpoints and register conditions.
+ * Also supports stepping, stepping over subroutine calls and stepping out of subroutines.
+ */
+pub struct Debugger {
+    mode: Mode,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    // Conditions, and whether they were true after the last step
+    conditions: Vec<(Condition, bool)>,
+    // Address the last instruction ran at, or that the program paused at. Breakpoints there are skipped,
+    // so that resuming doesn't stop straight away and instructions waiting for a key don't stop every time.
+    last_pc: Option<usize>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        return Debugger {
+            mode: Mode::Running,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            conditions: Vec::new(),
+            last_pc: None,
+        };
+    }
+    /// Pause before running the instruction at the address given
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+    pub fn remove_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.remove(&addr);
+    }
+    pub fn get_breakpoints(&self) -> impl Iterator<Item = &usize> {
+        return self.breakpoints.iter();
+    }
+    /// Pause after an instruction changes the byte of memory at the address given
+    pub fn add_watchpoint(&mut self, addr: usize) {
+        self.watchpoints.insert(addr);
+    }
+    pub fn remove_watchpoint(&mut self, addr: usize) {
+        self.watchpoints.remove(&addr);
+    }
+    /// Pause after an instruction makes the condition given true
+    pub fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push((condition, false));
+    }
+    pub fn remove_condition(&mut self, condition: Condition) {
+        self.conditions.retain(|(c, _)| *c != condition);
+    }
+    /// Remove every breakpoint, watchpoint and condition
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.conditions.clear();
+    }
+    /// Return whether the program is paused
+    pub fn is_paused(&self) -> bool {
+        return self.mode == Mode::Paused;
+    }
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+    /// Resume running until the next breakpoint
+    pub fn resume(&mut self) {
+        self.mode = Mode::Running;
+    }
+    /// Run a single instruction, then pause
+    pub fn step_into(&mut self) {
+        self.mode = Mode::Step;
+    }
+    /// Run a single instruction, or a whole subroutine if the instruction calls one, then pause
+    pub fn step_over(&mut self, p: &Processor) {
+        let pc = p.get_program_counter();
+        self.mode = match Instruction::decode(p.get_word_at(pc)) {
+            Ok(Instruction::Call(_)) => Mode::StepOver {
+                sp: p.get_stack_pointer(),
+                return_pc: pc + 2,
+            },
+            _ => Mode::Step,
+        };
+    }
+    /// Run until the current subroutine returns, then pause
+    pub fn step_out(&mut self, p: &Processor) {
+        self.mode = Mode::StepOut {
+            sp: p.get_stack_pointer(),
+        };
+    }
+    /**
+     * Run the next instruction, unless paused.
+     * Returns the reason the program was paused, if it was.
+     */
+    pub fn step(&mut self, p: &mut Processor) -> Option<StopReason> {
+        if self.mode == Mode::Paused {
+            return None;
+        }
+        let start_pc = p.get_program_counter();
+        if self.last_pc != Some(start_pc) && self.breakpoints.contains(&start_pc) {
+            self.mode = Mode::Paused;
+            self.last_pc = Some(start_pc);
+            return Some(StopReason::Breakpoint(start_pc));
+        }
+        let watched: Vec<(usize, u8)> = self
+            .watchpoints
+            .iter()
+            .filter(|a| **a < p.get_mem_size())
+            .map(|a| (*a, p.get_mem_at(*a)))
+            .collect();
+        let reason = match p.step() {
+            Err(e) => Some(StopReason::Error(e)),
+            Ok(()) => self.check(p, &watched),
+        };
+        self.last_pc = Some(start_pc);
+        if reason.is_some() {
+            self.mode = Mode::Paused;
+            self.last_pc = Some(p.get_program_counter());
+        }
+        return reason;
+    }
+    // Check whether the processor should pause after running an instruction
+    fn check(&mut self, p: &Processor, watched: &[(usize, u8)]) -> Option<StopReason> {
+        let mut reason = None;
+        for (address, old) in watched {
+            let new = p.get_mem_at(*address);
+            if new != *old && reason.is_none() {
+                reason = Some(StopReason::Watchpoint {
+                    address: *address,
+                    old: *old,
+                    new,
+                });
+            }
+        }
+        // Conditions only trigger when they become true, otherwise it would be impossible to resume
+        for (condition, was_met) in self.conditions.iter_mut() {
+            let met = condition.is_met(p);
+            if met && !*was_met && reason.is_none() {
+                reason = Some(StopReason::Condition(*condition));
+            }
+            *was_met = met;
+        }
+        if reason.is_some() {
+            return reason;
+        }
+        let pc = p.get_program_counter();
+        let sp = p.get_stack_pointer();
+        return match self.mode {
+            Mode::Step => Some(StopReason::Step),
+            Mode::StepOver { sp: s, return_pc } if pc == return_pc && sp == s => {
+                Some(StopReason::Step)
+            }
+            Mode::StepOut { sp: s } if sp < s => Some(StopReason::Step),
+            _ => None,
+        };
+    }
+}
+
+/**
+ * Describe the state of the processor over a few lines, for showing while paused.
+ */
+pub fn format_state(p: &Processor) -> String {
+    let pc = p.get_program_counter();
+    let opcode = p.get_word_at(pc);
+    let inst = match Instruction::decode(opcode) {
+        Ok(i) => i.to_string(),
+        Err(_) => "???".to_string(),
+    };
+    let registers: Vec<String> = (0..0x10)
+        .map(|r| format!("V{:X}={:02X}", r, p.get_register_value(r)))
+        .collect();
+    let stack: Vec<String> = p.get_stack().iter().map(|a| format!("{:03X}", a)).collect();
+    return format!(
+        "PC={:03X} I={:03X} SP={:X} DT={:02X} ST={:02X}\n{}\n{}\nStack: [{}]\n> {:03X}  {:04X}  {}",
+        pc,
+        p.get_i(),
+        p.get_stack_pointer(),
+        p.get_dt(),
+        p.get_st(),
+        registers[..8].join(" "),
+        registers[8..].join(" "),
+        stack.join(" "),
+        pc,
+        opcode,
+        inst
+    );
+}
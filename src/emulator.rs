@@ -0,0 +1,237 @@
+#![allow(clippy::needless_return)]
+use crate::debugger::{Debugger, StopReason};
+use crate::errors::EmulatorError;
+use crate::interfaces::Interface;
+use crate::processor::Processor;
+use crate::trace::Tracer;
+use std::io::Write;
+use std::time::Duration;
+
+mod scheduler;
+pub use scheduler::{Scheduler, Speed};
+
+/// Instructions run each frame unless set otherwise, around 500 a second
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u64 = 8;
+
+/**
+ * Runs a processor and an interface together, frame by frame.
+ *
+ * Each frame runs exactly the same number of instructions, then updates the interface and timers,
+ * renders the screen and signals the vertical blank. Instructions go through a debugger, so the run
+ * can be paused and stepped through.
+ *
+ * ```no_run
+ * use rust_chip8_opengl::interfaces::HeadlessInterface;
+ * use rust_chip8_opengl::{Emulator, Processor};
+ *
+ * let mut p = Processor::new();
+ * p.load_program(&std::fs::read("my_game.ch8").unwrap());
+ * let mut emulator = Emulator::new(p, Box::new(HeadlessInterface::new()));
+ * emulator.set_instructions_per_frame(12);
+ * emulator.run().unwrap();
+ * ```
+ */
+pub struct Emulator {
+    p: Processor,
+    interface: Box<dyn Interface>,
+    debugger: Debugger,
+    tracer: Option<Tracer<Box<dyn Write>>>,
+    scheduler: Scheduler,
+    instructions_per_frame: u64,
+    // Frames finished so far
+    frames: u64,
+    // Instructions run so far in the current frame
+    frame_instructions: u64,
+    // Set once the interface has asked to quit or the program has exited
+    finished: bool,
+}
+
+impl Emulator {
+    /**
+     * Create an emulator running the processor given at 8 instructions per frame and 60 frames
+     * per second, in real time.
+     */
+    pub fn new(p: Processor, interface: Box<dyn Interface>) -> Emulator {
+        return Emulator {
+            p,
+            interface,
+            debugger: Debugger::new(),
+            tracer: None,
+            scheduler: Scheduler::new(60),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            frames: 0,
+            frame_instructions: 0,
+            finished: false,
+        };
+    }
+    /// Set how many instructions are run each frame
+    pub fn set_instructions_per_frame(&mut self, instructions: u64) {
+        self.instructions_per_frame = instructions.max(1);
+    }
+    /**
+     * Set how many instructions are run each frame from how many should run each second,
+     * rounded to the nearest whole number per frame at the current frame rate.
+     */
+    pub fn set_instructions_per_second(&mut self, instructions: u64) {
+        let frame_rate = self.scheduler.get_frame_rate();
+        self.set_instructions_per_frame((instructions + frame_rate / 2) / frame_rate);
+    }
+    /// Set how many frames are run each second, which is also how often the timers are updated
+    pub fn set_frame_rate(&mut self, frame_rate: u64) {
+        self.scheduler.set_frame_rate(frame_rate);
+    }
+    /**
+     * Set whether to wait between frames so that the program runs in real time.
+     * Without pacing frames are run as fast as possible, i.e. for headless runs.
+     */
+    pub fn set_paced(&mut self, paced: bool) {
+        self.scheduler.set_paced(paced);
+    }
+    /// Trace every instruction run
+    pub fn set_tracer(&mut self, tracer: Tracer<Box<dyn Write>>) {
+        self.tracer = Some(tracer);
+    }
+    pub fn get_processor(&self) -> &Processor {
+        return &self.p;
+    }
+    pub fn get_processor_mut(&mut self) -> &mut Processor {
+        return &mut self.p;
+    }
+    pub fn get_interface_mut(&mut self) -> &mut dyn Interface {
+        return self.interface.as_mut();
+    }
+    pub fn get_debugger(&self) -> &Debugger {
+        return &self.debugger;
+    }
+    pub fn get_debugger_mut(&mut self) -> &mut Debugger {
+        return &mut self.debugger;
+    }
+    /**
+     * Get the processor, debugger and interface at once, for when more than one is needed,
+     * i.e. to let a GDB server control the processor through the debugger.
+     */
+    pub fn split_mut(&mut self) -> (&mut Processor, &mut Debugger, &mut dyn Interface) {
+        return (&mut self.p, &mut self.debugger, self.interface.as_mut());
+    }
+    pub fn get_instructions_per_frame(&self) -> u64 {
+        return self.instructions_per_frame;
+    }
+    pub fn get_frame_rate(&self) -> u64 {
+        return self.scheduler.get_frame_rate();
+    }
+    /// Get the scheduler, i.e. to check the speed it measured
+    pub fn get_scheduler(&self) -> &Scheduler {
+        return &self.scheduler;
+    }
+    /// Get the number of frames finished so far
+    pub fn get_frames(&self) -> u64 {
+        return self.frames;
+    }
+    /// Return whether the interface has asked to quit, or the program has exited
+    pub fn is_finished(&self) -> bool {
+        return self.finished;
+    }
+    /**
+     * Run the rest of the current frame and render it.
+     *
+     * Returns early if the debugger stops the program, i.e. at a breakpoint or because of an error,
+     * leaving the rest of the frame to the next call. While the debugger is paused, only the interface
+     * is updated. The timers and vertical blank are only updated once every instruction of the frame
+     * has run, so a frame always runs the same instructions however often it is interrupted.
+     * The interface is updated once, after the instructions, so inputs only change between frames.
+     */
+    pub fn run_frame(&mut self) -> Option<StopReason> {
+        let mut reason = None;
+        // The debugger runs nothing while paused, apart from single steps
+        while self.frame_instructions < self.instructions_per_frame && !self.debugger.is_paused() {
+            if let Some(t) = self.tracer.as_mut() {
+                t.before_step(&self.p);
+            }
+            reason = self.debugger.step(&mut self.p);
+            self.frame_instructions += 1;
+            if let Some(t) = self.tracer.as_mut() {
+                if let Err(e) = t.after_step(&self.p) {
+                    self.interface
+                        .show_message(&format!("Could not write trace: {}", e));
+                }
+            }
+            if reason.is_some() || self.p.has_exited() {
+                break;
+            }
+        }
+        if self.interface.update(&mut self.p) || self.p.has_exited() {
+            self.finished = true;
+            return reason;
+        }
+        if self.frame_instructions >= self.instructions_per_frame {
+            self.scheduler.on_frame(self.frame_instructions);
+            self.frame_instructions = 0;
+            self.frames += 1;
+            self.p.on_tick();
+            self.interface.render(&self.p);
+            self.p.on_v_blank();
+        } else {
+            self.interface.render(&self.p);
+        }
+        return reason;
+    }
+    /**
+     * Update the interface and render the screen without running the processor,
+     * i.e. while rewinding.
+     */
+    pub fn idle_frame(&mut self) {
+        if self.interface.update(&mut self.p) {
+            self.finished = true;
+        }
+        self.interface.render(&self.p);
+    }
+    /**
+     * Wait until the next frame is due, if paced.
+     * Returns straight away to catch up if frames are running late.
+     */
+    pub fn wait_for_next_frame(&mut self) {
+        self.scheduler.wait_for_next_frame();
+    }
+    /**
+     * Run for the length of time given, as measured by the frame rate, or until finished.
+     * Hotkeys are ignored. Stops with the error if the processor hits one.
+     */
+    pub fn run_for(&mut self, duration: Duration) -> Result<(), EmulatorError> {
+        let frames = duration.as_nanos() / self.scheduler.get_frame_time().as_nanos();
+        for _ in 0..frames {
+            if self.finished {
+                break;
+            }
+            self.run_paced_frame()?;
+        }
+        return Ok(());
+    }
+    /**
+     * Run until the interface asks to quit or the program exits.
+     * Hotkeys are ignored. Stops with the error if the processor hits one.
+     */
+    pub fn run(&mut self) -> Result<(), EmulatorError> {
+        while !self.finished {
+            self.run_paced_frame()?;
+        }
+        return Ok(());
+    }
+    /**
+     * Clean up the interface and flush the trace, once finished.
+     */
+    pub fn exit(&mut self) {
+        self.interface.exit();
+        if let Some(t) = self.tracer.as_mut() {
+            t.flush().ok();
+        }
+    }
+    // Run a frame and wait for the next one, ignoring hotkeys
+    fn run_paced_frame(&mut self) -> Result<(), EmulatorError> {
+        if let Some(StopReason::Error(e)) = self.run_frame() {
+            return Err(e);
+        }
+        self.interface.take_hotkeys();
+        self.wait_for_next_frame();
+        return Ok(());
+    }
+}
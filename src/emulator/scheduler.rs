@@ -0,0 +1,128 @@
+#![allow(clippy::needless_return)]
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How far behind a frame can fall before the scheduler stops trying to catch up
+const MAX_LAG_FRAMES: u32 = 5;
+// How long speeds are measured over
+const MEASURE_WINDOW: Duration = Duration::from_secs(1);
+
+/**
+ * The speed the emulator actually ran at, measured over about a second.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Speed {
+    pub frames_per_second: f64,
+    pub instructions_per_second: f64,
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.1} fps, {:.0} instructions per second",
+            self.frames_per_second, self.instructions_per_second
+        )
+    }
+}
+
+/**
+ * Paces frames to a fixed frame rate, and measures the speed actually achieved.
+ *
+ * Each frame is due a fixed time after the one before it, rather than after the previous one
+ * finished, so that small delays don't add up. Frames that are late are run straight away to
+ * catch up, unless they are so far behind that catching up would only make it worse, i.e. after
+ * the window was dragged. Those frames are dropped instead.
+ */
+pub struct Scheduler {
+    frame_rate: u64,
+    paced: bool,
+    // When the next frame is due
+    next_frame: Option<Instant>,
+    // Frames given up on after falling too far behind
+    dropped_frames: u64,
+    // Frames and instructions since the current measurement started
+    window_start: Option<Instant>,
+    window_frames: u64,
+    window_instructions: u64,
+    speed: Option<Speed>,
+}
+
+impl Scheduler {
+    pub fn new(frame_rate: u64) -> Scheduler {
+        return Scheduler {
+            frame_rate: frame_rate.max(1),
+            paced: true,
+            next_frame: None,
+            dropped_frames: 0,
+            window_start: None,
+            window_frames: 0,
+            window_instructions: 0,
+            speed: None,
+        };
+    }
+    pub fn set_frame_rate(&mut self, frame_rate: u64) {
+        self.frame_rate = frame_rate.max(1);
+    }
+    pub fn get_frame_rate(&self) -> u64 {
+        return self.frame_rate;
+    }
+    /// Set whether to wait for each frame to be due, or to run them as fast as possible
+    pub fn set_paced(&mut self, paced: bool) {
+        self.paced = paced;
+    }
+    /// Get the time each frame should take
+    pub fn get_frame_time(&self) -> Duration {
+        return Duration::from_secs(1) / self.frame_rate as u32;
+    }
+    /**
+     * Record that a frame running the number of instructions given has finished,
+     * for measuring the speed.
+     */
+    pub fn on_frame(&mut self, instructions: u64) {
+        let now = Instant::now();
+        let start = *self.window_start.get_or_insert(now);
+        self.window_frames += 1;
+        self.window_instructions += instructions;
+        let elapsed = now - start;
+        if elapsed >= MEASURE_WINDOW {
+            let seconds = elapsed.as_secs_f64();
+            self.speed = Some(Speed {
+                frames_per_second: self.window_frames as f64 / seconds,
+                instructions_per_second: self.window_instructions as f64 / seconds,
+            });
+            self.window_start = Some(now);
+            self.window_frames = 0;
+            self.window_instructions = 0;
+        }
+    }
+    /**
+     * Wait until the next frame is due, if paced.
+     * Returns straight away if it is already due.
+     */
+    pub fn wait_for_next_frame(&mut self) {
+        if !self.paced {
+            return;
+        }
+        let now = Instant::now();
+        let frame_time = self.get_frame_time();
+        let mut next = self.next_frame.map_or(now, |n| n + frame_time);
+        if now > next + frame_time * MAX_LAG_FRAMES {
+            self.dropped_frames += ((now - next).as_nanos() / frame_time.as_nanos()) as u64;
+            next = now;
+        }
+        if next > now {
+            thread::sleep(next - now);
+        }
+        self.next_frame = Some(next);
+    }
+    /// Get the speed measured over the last second, once a second has passed
+    pub fn get_speed(&self) -> Option<Speed> {
+        return self.speed;
+    }
+    /// Get the number of frames given up on after falling too far behind
+    pub fn get_dropped_frames(&self) -> u64 {
+        return self.dropped_frames;
+    }
+}
@@ -1,26 +1,231 @@
+#![allow(clippy::needless_return)]
 use std::fmt;
 
-/// Error that is returned when the processor encounters an invalid opcode
-#[derive(Debug, Clone)]
-pub struct OpcodeError {
-    /// The invalid opcode that was encountered
+/**
+ * Error that is returned when the processor cannot continue running the program.
+ * Every variant records the program counter, and the opcode being executed if there is one.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// The opcode is not a valid instruction for the variant being emulated
+    InvalidOpcode { opcode: u16, pc: usize },
+    /// A subroutine was called when the stack was already full
+    StackOverflow { opcode: u16, pc: usize },
+    /// A subroutine returned when the stack was empty
+    StackUnderflow { opcode: u16, pc: usize },
+    /// An instruction tried to access memory past the end of memory
+    MemoryOutOfBounds {
+        opcode: u16,
+        pc: usize,
+        /// The first address accessed that is out of bounds
+        address: usize,
+    },
+    /// The program counter ran off the end of memory, so there is no opcode to read
+    PcOutOfBounds { pc: usize },
+}
+
+/// Error that used to be returned when the processor encountered an invalid opcode
+#[deprecated(note = "Use EmulatorError instead")]
+pub type OpcodeError = EmulatorError;
+
+impl EmulatorError {
+    /// Get the program counter at the moment the error happened
+    pub fn get_pc(&self) -> usize {
+        return match *self {
+            EmulatorError::InvalidOpcode { pc, .. }
+            | EmulatorError::StackOverflow { pc, .. }
+            | EmulatorError::StackUnderflow { pc, .. }
+            | EmulatorError::MemoryOutOfBounds { pc, .. }
+            | EmulatorError::PcOutOfBounds { pc } => pc,
+        };
+    }
+    /// Get the opcode being executed when the error happened, if there was one
+    pub fn get_opcode(&self) -> Option<u16> {
+        return match *self {
+            EmulatorError::InvalidOpcode { opcode, .. }
+            | EmulatorError::StackOverflow { opcode, .. }
+            | EmulatorError::StackUnderflow { opcode, .. }
+            | EmulatorError::MemoryOutOfBounds { opcode, .. } => Some(opcode),
+            EmulatorError::PcOutOfBounds { .. } => None,
+        };
+    }
+    /// Get the memory address that was out of bounds, if the error was caused by a memory access
+    pub fn get_address(&self) -> Option<usize> {
+        return match *self {
+            EmulatorError::MemoryOutOfBounds { address, .. } => Some(address),
+            _ => None,
+        };
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EmulatorError::InvalidOpcode { opcode, pc } => write!(
+                f,
+                "Invalid opcode {:04X} encountered at PC = {:03X}",
+                opcode, pc
+            ),
+            EmulatorError::StackOverflow { opcode, pc } => write!(
+                f,
+                "Stack overflow running {:04X} at PC = {:03X}",
+                opcode, pc
+            ),
+            EmulatorError::StackUnderflow { opcode, pc } => write!(
+                f,
+                "Stack underflow running {:04X} at PC = {:03X}",
+                opcode, pc
+            ),
+            EmulatorError::MemoryOutOfBounds {
+                opcode,
+                pc,
+                address,
+            } => write!(
+                f,
+                "Memory access out of bounds at {:X} running {:04X} at PC = {:03X}",
+                address, opcode, pc
+            ),
+            EmulatorError::PcOutOfBounds { pc } => {
+                write!(f, "Program counter ran off the end of memory at {:X}", pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+/// Error that is returned when an opcode cannot be decoded into an instruction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecodeError {
     opcode: u16,
-    /// The program conuter at the moment the invalid opcode was encountered
-    pc: u8,
 }
 
-impl OpcodeError {
-    pub fn new(opcode: u16, pc: u8) -> OpcodeError {
-        OpcodeError { opcode, pc }
+impl DecodeError {
+    pub fn new(opcode: u16) -> DecodeError {
+        DecodeError { opcode }
+    }
+    /// Get the opcode that could not be decoded
+    pub fn get_opcode(&self) -> u16 {
+        return self.opcode;
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X} is not a valid instruction", self.opcode)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Error that is returned when assembly source cannot be assembled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssembleError {
+    line: usize,
+    message: String,
+}
+
+impl AssembleError {
+    pub fn new(line: usize, message: String) -> AssembleError {
+        AssembleError { line, message }
     }
+    /// Get the line the error is on, starting at 1
+    pub fn get_line(&self) -> usize {
+        return self.line;
+    }
+    /// Get the description of the error
+    pub fn get_message(&self) -> &str {
+        return &self.message;
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// Error that is returned when a save state cannot be loaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state header, so is not a save state
+    InvalidHeader,
+    /// The save state was made by a version of the emulator using an unsupported format
+    UnsupportedVersion(u8),
+    /// The data ended before the whole save state was read
+    Truncated,
+    /// The save state contains a value that is out of range
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::InvalidHeader => write!(f, "Data is not a save state"),
+            StateError::UnsupportedVersion(v) => {
+                write!(f, "Unsupported save state version {}", v)
+            }
+            StateError::Truncated => write!(f, "Save state is truncated"),
+            StateError::Corrupt(what) => write!(f, "Save state is corrupt: invalid {}", what),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Error that is returned when a movie cannot be loaded or replayed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data does not start with the movie header, so is not a movie
+    InvalidHeader,
+    /// The movie was made by a version of the emulator using an unsupported format
+    UnsupportedVersion(u8),
+    /// The data ended before the whole movie was read
+    Truncated,
+    /// The movie contains a value that is out of range
+    Corrupt(&'static str),
+    /// The movie was recorded with a different ROM, whose hash is given
+    RomMismatch(u64),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::InvalidHeader => write!(f, "Data is not a movie"),
+            MovieError::UnsupportedVersion(v) => write!(f, "Unsupported movie version {}", v),
+            MovieError::Truncated => write!(f, "Movie is truncated"),
+            MovieError::Corrupt(what) => write!(f, "Movie is corrupt: invalid {}", what),
+            MovieError::RomMismatch(hash) => {
+                write!(
+                    f,
+                    "Movie was recorded with a different ROM (hash {:016X})",
+                    hash
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+/// Error that is returned when an audio device cannot be opened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioError {
+    /// There is no audio output device
+    NoDevice,
+    /// The audio device exists but could not be opened, with the reason why
+    Device(String),
 }
 
-impl fmt::Display for OpcodeError {
+impl fmt::Display for AudioError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Invalid opcode {:x} encountered at PC = {:x}",
-            self.opcode, self.pc
-        )
+        match self {
+            AudioError::NoDevice => write!(f, "No audio device found"),
+            AudioError::Device(e) => write!(f, "Could not open audio device: {}", e),
+        }
     }
 }
+
+impl std::error::Error for AudioError {}
@@ -0,0 +1,474 @@
+#![allow(clippy::needless_return)]
+use crate::debugger::{Debugger, StopReason};
+use crate::processor::Processor;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Range;
+
+// Size in bytes and name of every register exposed to GDB, in the order GDB numbers them
+const REGISTERS: [(usize, &str); 21] = [
+    (1, "v0"),
+    (1, "v1"),
+    (1, "v2"),
+    (1, "v3"),
+    (1, "v4"),
+    (1, "v5"),
+    (1, "v6"),
+    (1, "v7"),
+    (1, "v8"),
+    (1, "v9"),
+    (1, "va"),
+    (1, "vb"),
+    (1, "vc"),
+    (1, "vd"),
+    (1, "ve"),
+    (1, "vf"),
+    (2, "i"),
+    (2, "pc"),
+    (1, "sp"),
+    (1, "dt"),
+    (1, "st"),
+];
+
+// Signals reported to GDB
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/**
+ * Server for the GDB remote serial protocol, so that a debugger front end can be attached over TCP.
+ *
+ * Exposes V0 through VF, I, PC, SP, DT and ST as registers and the processor's memory as the target's
+ * memory, and supports breakpoints, write watchpoints, stepping and continuing.
+ * Execution is controlled through a `Debugger`, and the server never blocks, so `poll` should be
+ * called regularly from the emulation loop.
+ */
+pub struct GdbServer {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    // Bytes received that haven't been handled yet
+    buffer: Vec<u8>,
+    // Whether GDB is waiting for the processor to stop after a continue or step
+    waiting_for_stop: bool,
+    // Whether GDB has turned off acknowledgements
+    no_ack: bool,
+}
+
+impl GdbServer {
+    /**
+     * Listen for GDB on the local port given.
+     * Port 0 picks any free port, which can be found with `get_port`.
+     */
+    pub fn bind(port: u16) -> io::Result<GdbServer> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        return Ok(GdbServer {
+            listener,
+            stream: None,
+            buffer: Vec::new(),
+            waiting_for_stop: false,
+            no_ack: false,
+        });
+    }
+    /// Get the port the server is listening on
+    pub fn get_port(&self) -> u16 {
+        return self.listener.local_addr().unwrap().port();
+    }
+    /// Return whether GDB is connected
+    pub fn is_connected(&self) -> bool {
+        return self.stream.is_some();
+    }
+    /**
+     * Accept a connection from GDB and handle any packets it has sent.
+     * The processor is paused when GDB connects, and resumed when it disconnects.
+     */
+    pub fn poll(&mut self, p: &mut Processor, d: &mut Debugger) -> io::Result<()> {
+        if self.stream.is_none() {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(true)?;
+                    stream.set_nodelay(true)?;
+                    self.stream = Some(stream);
+                    self.buffer.clear();
+                    self.no_ack = false;
+                    self.waiting_for_stop = false;
+                    d.pause();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut data = [0; 4096];
+        loop {
+            let read = match self.stream.as_mut().unwrap().read(&mut data) {
+                Ok(0) => {
+                    self.disconnect(d);
+                    return Ok(());
+                }
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.disconnect(d);
+                    return Err(e);
+                }
+            };
+            self.buffer.extend_from_slice(&data[..read]);
+        }
+        while let Some(packet) = self.next_packet() {
+            let reply = match packet {
+                // Ctrl-C
+                None => {
+                    d.pause();
+                    // GDB always expects a stop reply to an interrupt
+                    self.waiting_for_stop = true;
+                    self.on_stop(&StopReason::Pause)?;
+                    continue;
+                }
+                Some(packet) => self.handle(&packet, p, d),
+            };
+            if let Some(reply) = reply {
+                self.send(&reply)?;
+            }
+            if self.stream.is_none() {
+                break;
+            }
+        }
+        return Ok(());
+    }
+    /**
+     * Tell GDB that the processor stopped, if it is waiting for it to.
+     * Should be called with every reason returned by `Debugger::step`.
+     */
+    pub fn on_stop(&mut self, reason: &StopReason) -> io::Result<()> {
+        if !self.waiting_for_stop || self.stream.is_none() {
+            return Ok(());
+        }
+        self.waiting_for_stop = false;
+        let reply = match reason {
+            StopReason::Pause => format!("S{:02x}", SIGINT),
+            StopReason::Error(_) => format!("S{:02x}", SIGILL),
+            StopReason::Watchpoint { address, .. } => {
+                format!("T{:02x}watch:{:x};", SIGTRAP, address)
+            }
+            _ => format!("S{:02x}", SIGTRAP),
+        };
+        return self.send(&reply);
+    }
+
+    fn disconnect(&mut self, d: &mut Debugger) {
+        self.stream = None;
+        self.waiting_for_stop = false;
+        d.resume();
+    }
+
+    /*
+     * Take the next packet out of the buffer.
+     * Returns Some(None) for an interrupt, and acknowledges packets unless that has been turned off.
+     */
+    fn next_packet(&mut self) -> Option<Option<String>> {
+        loop {
+            match self.buffer.first()? {
+                0x03 => {
+                    self.buffer.remove(0);
+                    return Some(None);
+                }
+                b'$' => break,
+                // Acknowledgements and noise
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        let end = self.buffer.iter().position(|b| *b == b'#')?;
+        // Wait for the checksum too
+        if self.buffer.len() < end + 3 {
+            return None;
+        }
+        let packet = String::from_utf8_lossy(&self.buffer[1..end]).to_string();
+        self.buffer.drain(..(end + 3));
+        if !self.no_ack {
+            let _ = self.write(b"+");
+        }
+        return Some(Some(packet));
+    }
+
+    // Handle a packet, returning the reply, if any should be sent now
+    fn handle(&mut self, packet: &str, p: &mut Processor, d: &mut Debugger) -> Option<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => REGISTERS
+                .iter()
+                .enumerate()
+                .map(|(n, (size, _))| to_hex_le(get_register(p, n), *size))
+                .collect(),
+            "G" => {
+                let mut values = Vec::new();
+                let mut pos = 0;
+                for (size, _) in REGISTERS.iter() {
+                    match args
+                        .get(pos..(pos + 2 * size))
+                        .and_then(|v| from_hex_le(v, *size))
+                    {
+                        Some(v) => values.push(v),
+                        None => return Some("E01".to_string()),
+                    }
+                    pos += 2 * size;
+                }
+                if pos != args.len() {
+                    return Some("E01".to_string());
+                }
+                values
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(n, v)| set_register(p, n, v));
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(n) if n < REGISTERS.len() => to_hex_le(get_register(p, n), REGISTERS[n].0),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(n, v)| {
+                    let n = usize::from_str_radix(n, 16).ok()?;
+                    return Some((n, from_hex_le(v, REGISTERS.get(n)?.0)?));
+                });
+                match parsed {
+                    Some((n, v)) => {
+                        set_register(p, n, v);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            "m" => match parse_range(args, p) {
+                Some(range) => range.map(|a| format!("{:02x}", p.get_mem_at(a))).collect(),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let range = parse_range(range, p)?;
+                    let bytes = from_hex(data)?;
+                    if bytes.len() != range.len() {
+                        return None;
+                    }
+                    bytes
+                        .iter()
+                        .enumerate()
+                        .for_each(|(i, b)| p.set_mem_at(range.start + i, *b));
+                    return Some(());
+                });
+                match written {
+                    Some(()) => "OK".to_string(),
+                    None => "E01".to_string(),
+                }
+            }
+            "c" => {
+                // Resume from an address isn't supported, just resume
+                d.resume();
+                self.waiting_for_stop = true;
+                return None;
+            }
+            "s" => {
+                d.step_into();
+                self.waiting_for_stop = true;
+                return None;
+            }
+            "Z" | "z" => {
+                let insert = command == "Z";
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                let addr = parts.next().and_then(|a| usize::from_str_radix(a, 16).ok());
+                match (kind, addr) {
+                    // Software and hardware breakpoints are the same thing here
+                    (Some("0") | Some("1"), Some(addr)) => {
+                        if insert {
+                            d.add_breakpoint(addr);
+                        } else {
+                            d.remove_breakpoint(addr);
+                        }
+                        "OK".to_string()
+                    }
+                    // Write watchpoints
+                    (Some("2"), Some(addr)) => {
+                        let len = match parts.next() {
+                            Some(l) => usize::from_str_radix(l, 16).ok(),
+                            None => Some(1),
+                        };
+                        match len.and_then(|len| check_range(addr, len, p)) {
+                            Some(range) => {
+                                for a in range {
+                                    if insert {
+                                        d.add_watchpoint(a);
+                                    } else {
+                                        d.remove_watchpoint(a);
+                                    }
+                                }
+                                "OK".to_string()
+                            }
+                            None => "E01".to_string(),
+                        }
+                    }
+                    _ => String::new(),
+                }
+            }
+            "H" => "OK".to_string(),
+            "k" => {
+                self.disconnect(d);
+                return None;
+            }
+            "D" => {
+                self.send("OK").ok()?;
+                self.disconnect(d);
+                return None;
+            }
+            _ => self.handle_query(packet),
+        };
+        return Some(reply);
+    }
+
+    // Handle the general query packets
+    fn handle_query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string();
+        }
+        if packet == "QStartNoAckMode" {
+            // The OK is still acknowledged
+            self.no_ack = true;
+            return "OK".to_string();
+        }
+        if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml();
+            let (offset, len) = match args.split_once(',') {
+                Some((o, l)) => (
+                    usize::from_str_radix(o, 16).unwrap_or(0),
+                    usize::from_str_radix(l, 16).unwrap_or(0),
+                ),
+                None => return "E01".to_string(),
+            };
+            let start = offset.min(xml.len());
+            let end = (offset + len).min(xml.len());
+            let prefix = if end == xml.len() { "l" } else { "m" };
+            return format!("{}{}", prefix, &xml[start..end]);
+        }
+        return match packet {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            // Empty reply means the packet isn't supported
+            _ => String::new(),
+        };
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |a, b| a.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, checksum);
+        return self.write(packet.as_bytes());
+    }
+
+    // Write everything to the non-blocking stream
+    fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        while !data.is_empty() {
+            match stream.write(data) {
+                Ok(n) => data = &data[n..],
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+                Err(e) => return Err(e),
+            }
+        }
+        return Ok(());
+    }
+}
+
+// Describe the registers to GDB, since it doesn't know about CHIP-8
+fn target_xml() -> String {
+    let regs: String = REGISTERS
+        .iter()
+        .map(|(size, name)| {
+            let kind = match *name {
+                "pc" => " type=\"code_ptr\"",
+                "i" => " type=\"data_ptr\"",
+                _ => "",
+            };
+            format!("<reg name=\"{}\" bitsize=\"{}\"{}/>", name, size * 8, kind)
+        })
+        .collect();
+    return format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target version=\"1.0\"><feature name=\"org.chip8.core\">{}</feature></target>",
+        regs
+    );
+}
+
+fn get_register(p: &Processor, n: usize) -> usize {
+    return match n {
+        0..=15 => p.get_register_value(n as u8) as usize,
+        16 => p.get_i() as usize,
+        17 => p.get_program_counter(),
+        18 => p.get_stack_pointer(),
+        19 => p.get_dt() as usize,
+        _ => p.get_st() as usize,
+    };
+}
+
+fn set_register(p: &mut Processor, n: usize, v: usize) {
+    match n {
+        0..=15 => p.set_register_value(n as u8, v as u8),
+        16 => p.set_i(v as u16),
+        17 => p.set_program_counter(v),
+        // The stack pointer can't be changed without corrupting the stack
+        18 => {}
+        19 => p.set_dt(v as u8),
+        _ => p.set_st(v as u8),
+    }
+}
+
+// Parse `addr,length` and check that it is in memory
+fn parse_range(s: &str, p: &Processor) -> Option<Range<usize>> {
+    let (addr, len) = s.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    return check_range(addr, len, p);
+}
+
+// Get the addresses from `addr` for `len` bytes, if they are all in memory.
+// Both come from the client, so the end can overflow.
+fn check_range(addr: usize, len: usize, p: &Processor) -> Option<Range<usize>> {
+    let end = addr.checked_add(len)?;
+    if end > p.get_mem_size() {
+        return None;
+    }
+    return Some(addr..end);
+}
+
+fn to_hex_le(v: usize, size: usize) -> String {
+    return (0..size)
+        .map(|i| format!("{:02x}", (v >> (8 * i)) & 0xFF))
+        .collect();
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    return (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..(i + 2))?, 16).ok())
+        .collect();
+}
+
+// Parse a little endian value, which must be exactly `size` bytes
+fn from_hex_le(s: &str, size: usize) -> Option<usize> {
+    if s.len() != 2 * size {
+        return None;
+    }
+    return Some(
+        from_hex(s)?
+            .iter()
+            .rev()
+            .fold(0, |v, b| (v << 8) | *b as usize),
+    );
+}
@@ -0,0 +1,22 @@
+#![allow(clippy::needless_return)]
+// 64 bit FNV-1a, used wherever a hash has to stay the same across versions and platforms.
+// The standard library's hashers make no such promise.
+pub(crate) struct Fnv64 {
+    hash: u64,
+}
+
+impl Fnv64 {
+    pub(crate) fn new() -> Fnv64 {
+        return Fnv64 {
+            hash: 0xCBF29CE484222325,
+        };
+    }
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.hash = (self.hash ^ *b as u64).wrapping_mul(0x100000001B3);
+        }
+    }
+    pub(crate) fn finish(&self) -> u64 {
+        return self.hash;
+    }
+}
@@ -0,0 +1,388 @@
+#![allow(clippy::needless_return)]
+use crate::errors::DecodeError;
+use crate::processor::Variant;
+use std::fmt;
+
+/**
+ * A single decoded CHIP-8 instruction.
+ * Covers the original CHIP-8 instructions as well as the SUPER-CHIP and XO-CHIP extensions.
+ * `x` and `y` are register indices, `kk` is a byte constant and `addr` is a 12 bit address.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    /// `0NNN`: Call a machine code routine, ignored
+    Sys(u16),
+    /// `00E0`: Clear the screen
+    Cls,
+    /// `00EE`: Return from a subroutine
+    Ret,
+    /// `00CN`: Scroll the screen down N pixels (SUPER-CHIP)
+    ScrollDown(u8),
+    /// `00DN`: Scroll the screen up N pixels (XO-CHIP)
+    ScrollUp(u8),
+    /// `00FB`: Scroll the screen right 4 pixels (SUPER-CHIP)
+    ScrollRight,
+    /// `00FC`: Scroll the screen left 4 pixels (SUPER-CHIP)
+    ScrollLeft,
+    /// `00FD`: Exit the program (SUPER-CHIP)
+    Exit,
+    /// `00FE`: Switch to low resolution mode (SUPER-CHIP)
+    Lores,
+    /// `00FF`: Switch to high resolution mode (SUPER-CHIP)
+    Hires,
+    /// `1NNN`: Jump to NNN
+    Jp(u16),
+    /// `2NNN`: Call the subroutine at NNN
+    Call(u16),
+    /// `3XKK`: Skip the next instruction if VX == KK
+    SeByte(u8, u8),
+    /// `4XKK`: Skip the next instruction if VX != KK
+    SneByte(u8, u8),
+    /// `5XY0`: Skip the next instruction if VX == VY
+    SeReg(u8, u8),
+    /// `5XY2`: Save VX through VY at I (XO-CHIP)
+    SaveRange(u8, u8),
+    /// `5XY3`: Load VX through VY from I (XO-CHIP)
+    LoadRange(u8, u8),
+    /// `6XKK`: Set VX to KK
+    LdByte(u8, u8),
+    /// `7XKK`: Add KK to VX
+    AddByte(u8, u8),
+    /// `8XY0`: Set VX to VY
+    LdReg(u8, u8),
+    /// `8XY1`: Set VX to VX OR VY
+    Or(u8, u8),
+    /// `8XY2`: Set VX to VX AND VY
+    And(u8, u8),
+    /// `8XY3`: Set VX to VX XOR VY
+    Xor(u8, u8),
+    /// `8XY4`: Add VY to VX, setting VF on carry
+    AddReg(u8, u8),
+    /// `8XY5`: Subtract VY from VX, setting VF on no borrow
+    Sub(u8, u8),
+    /// `8XY6`: Shift right
+    Shr(u8, u8),
+    /// `8XY7`: Set VX to VY - VX, setting VF on no borrow
+    Subn(u8, u8),
+    /// `8XYE`: Shift left
+    Shl(u8, u8),
+    /// `9XY0`: Skip the next instruction if VX != VY
+    SneReg(u8, u8),
+    /// `ANNN`: Set I to NNN
+    LdI(u16),
+    /// `BNNN`: Jump to NNN + V0, or XNN + VX depending on the quirks
+    JpV0(u16),
+    /// `CXKK`: Set VX to a random byte AND KK
+    Rnd(u8, u8),
+    /// `DXYN`: Draw an N byte sprite at (VX, VY)
+    Drw(u8, u8, u8),
+    /// `EX9E`: Skip the next instruction if the key VX is pressed
+    Skp(u8),
+    /// `EXA1`: Skip the next instruction if the key VX is not pressed
+    Sknp(u8),
+    /// `F000 NNNN`: Set I to the 16 bit address in the next word (XO-CHIP)
+    LdILong,
+    /// `FN01`: Select the bitplanes N to draw to (XO-CHIP)
+    Plane(u8),
+    /// `F002`: Load the audio pattern buffer from I (XO-CHIP)
+    Audio,
+    /// `FX07`: Set VX to DT
+    LdRegDt(u8),
+    /// `FX0A`: Wait for a key to be released and store it in VX
+    LdRegKey(u8),
+    /// `FX15`: Set DT to VX
+    LdDtReg(u8),
+    /// `FX18`: Set ST to VX
+    LdStReg(u8),
+    /// `FX1E`: Add VX to I
+    AddIReg(u8),
+    /// `FX29`: Set I to the small font sprite for VX
+    LdFont(u8),
+    /// `FX30`: Set I to the big font sprite for VX (SUPER-CHIP)
+    LdBigFont(u8),
+    /// `FX33`: Store the BCD representation of VX at I
+    Bcd(u8),
+    /// `FX3A`: Set the audio pitch to VX (XO-CHIP)
+    Pitch(u8),
+    /// `FX55`: Store V0 through VX at I
+    StoreRegs(u8),
+    /// `FX65`: Load V0 through VX from I
+    LoadRegs(u8),
+    /// `FX75`: Store V0 through VX in the RPL flags (SUPER-CHIP)
+    StoreRpl(u8),
+    /// `FX85`: Load V0 through VX from the RPL flags (SUPER-CHIP)
+    LoadRpl(u8),
+}
+
+impl Instruction {
+    /**
+     * Decode an opcode into an instruction.
+     * Decodes the instructions of every variant, use `is_supported_by` to check if
+     * the instruction can be run on a certain variant.
+     */
+    pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+        use Instruction::*;
+        let x = ((opcode >> 8) & 0xF) as u8;
+        let y = ((opcode >> 4) & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let kk = (opcode & 0xFF) as u8;
+        let addr = opcode & 0xFFF;
+        let inst = match opcode & 0xF000 {
+            0x0000 => match addr {
+                0x0E0 => Cls,
+                0x0EE => Ret,
+                0x0C0..=0x0CF => ScrollDown(n),
+                0x0D0..=0x0DF => ScrollUp(n),
+                0x0FB => ScrollRight,
+                0x0FC => ScrollLeft,
+                0x0FD => Exit,
+                0x0FE => Lores,
+                0x0FF => Hires,
+                _ => Sys(addr),
+            },
+            0x1000 => Jp(addr),
+            0x2000 => Call(addr),
+            0x3000 => SeByte(x, kk),
+            0x4000 => SneByte(x, kk),
+            0x5000 => match n {
+                0x0 => SeReg(x, y),
+                0x2 => SaveRange(x, y),
+                0x3 => LoadRange(x, y),
+                _ => return Err(DecodeError::new(opcode)),
+            },
+            0x6000 => LdByte(x, kk),
+            0x7000 => AddByte(x, kk),
+            0x8000 => match n {
+                0x0 => LdReg(x, y),
+                0x1 => Or(x, y),
+                0x2 => And(x, y),
+                0x3 => Xor(x, y),
+                0x4 => AddReg(x, y),
+                0x5 => Sub(x, y),
+                0x6 => Shr(x, y),
+                0x7 => Subn(x, y),
+                0xE => Shl(x, y),
+                _ => return Err(DecodeError::new(opcode)),
+            },
+            0x9000 if n == 0 => SneReg(x, y),
+            0xA000 => LdI(addr),
+            0xB000 => JpV0(addr),
+            0xC000 => Rnd(x, kk),
+            0xD000 => Drw(x, y, n),
+            0xE000 => match kk {
+                0x9E => Skp(x),
+                0xA1 => Sknp(x),
+                _ => return Err(DecodeError::new(opcode)),
+            },
+            0xF000 => match kk {
+                0x00 if x == 0 => LdILong,
+                0x01 => Plane(x),
+                0x02 if x == 0 => Audio,
+                0x07 => LdRegDt(x),
+                0x0A => LdRegKey(x),
+                0x15 => LdDtReg(x),
+                0x18 => LdStReg(x),
+                0x1E => AddIReg(x),
+                0x29 => LdFont(x),
+                0x30 => LdBigFont(x),
+                0x33 => Bcd(x),
+                0x3A => Pitch(x),
+                0x55 => StoreRegs(x),
+                0x65 => LoadRegs(x),
+                0x75 => StoreRpl(x),
+                0x85 => LoadRpl(x),
+                _ => return Err(DecodeError::new(opcode)),
+            },
+            _ => return Err(DecodeError::new(opcode)),
+        };
+        return Ok(inst);
+    }
+    /**
+     * Encode the instruction back into its opcode.
+     */
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+        // Build an opcode from its prefix and arguments
+        let xy = |prefix: u16, x: u8, y: u8, n: u16| -> u16 {
+            return prefix | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4) | n;
+        };
+        let xkk = |prefix: u16, x: u8, kk: u8| -> u16 {
+            return prefix | ((x as u16 & 0xF) << 8) | kk as u16;
+        };
+        return match *self {
+            Sys(addr) => addr & 0xFFF,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            Jp(addr) => 0x1000 | (addr & 0xFFF),
+            Call(addr) => 0x2000 | (addr & 0xFFF),
+            SeByte(x, kk) => xkk(0x3000, x, kk),
+            SneByte(x, kk) => xkk(0x4000, x, kk),
+            SeReg(x, y) => xy(0x5000, x, y, 0x0),
+            SaveRange(x, y) => xy(0x5000, x, y, 0x2),
+            LoadRange(x, y) => xy(0x5000, x, y, 0x3),
+            LdByte(x, kk) => xkk(0x6000, x, kk),
+            AddByte(x, kk) => xkk(0x7000, x, kk),
+            LdReg(x, y) => xy(0x8000, x, y, 0x0),
+            Or(x, y) => xy(0x8000, x, y, 0x1),
+            And(x, y) => xy(0x8000, x, y, 0x2),
+            Xor(x, y) => xy(0x8000, x, y, 0x3),
+            AddReg(x, y) => xy(0x8000, x, y, 0x4),
+            Sub(x, y) => xy(0x8000, x, y, 0x5),
+            Shr(x, y) => xy(0x8000, x, y, 0x6),
+            Subn(x, y) => xy(0x8000, x, y, 0x7),
+            Shl(x, y) => xy(0x8000, x, y, 0xE),
+            SneReg(x, y) => xy(0x9000, x, y, 0x0),
+            LdI(addr) => 0xA000 | (addr & 0xFFF),
+            JpV0(addr) => 0xB000 | (addr & 0xFFF),
+            Rnd(x, kk) => xkk(0xC000, x, kk),
+            Drw(x, y, n) => xy(0xD000, x, y, n as u16 & 0xF),
+            Skp(x) => xkk(0xE000, x, 0x9E),
+            Sknp(x) => xkk(0xE000, x, 0xA1),
+            LdILong => 0xF000,
+            Plane(n) => xkk(0xF000, n, 0x01),
+            Audio => 0xF002,
+            LdRegDt(x) => xkk(0xF000, x, 0x07),
+            LdRegKey(x) => xkk(0xF000, x, 0x0A),
+            LdDtReg(x) => xkk(0xF000, x, 0x15),
+            LdStReg(x) => xkk(0xF000, x, 0x18),
+            AddIReg(x) => xkk(0xF000, x, 0x1E),
+            LdFont(x) => xkk(0xF000, x, 0x29),
+            LdBigFont(x) => xkk(0xF000, x, 0x30),
+            Bcd(x) => xkk(0xF000, x, 0x33),
+            Pitch(x) => xkk(0xF000, x, 0x3A),
+            StoreRegs(x) => xkk(0xF000, x, 0x55),
+            LoadRegs(x) => xkk(0xF000, x, 0x65),
+            StoreRpl(x) => xkk(0xF000, x, 0x75),
+            LoadRpl(x) => xkk(0xF000, x, 0x85),
+        };
+    }
+    /**
+     * Return whether the instruction can be run when emulating the variant given.
+     */
+    pub fn is_supported_by(&self, variant: Variant) -> bool {
+        use Instruction::*;
+        let schip = variant != Variant::Chip8;
+        let xo = variant == Variant::XoChip;
+        return match *self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Lores | Hires | LdBigFont(_) => schip,
+            // SUPER-CHIP only has 8 RPL flags
+            StoreRpl(x) | LoadRpl(x) => xo || (schip && x < 8),
+            ScrollUp(_)
+            | SaveRange(_, _)
+            | LoadRange(_, _)
+            | LdILong
+            | Plane(_)
+            | Audio
+            | Pitch(_) => xo,
+            _ => true,
+        };
+    }
+    /**
+     * Get the size of the instruction in bytes.
+     * This is 2 for every instruction except the 4 byte long XO-CHIP `F000 NNNN`.
+     */
+    pub fn size(&self) -> usize {
+        return if *self == Instruction::LdILong { 4 } else { 2 };
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            Sys(addr) => write!(f, "SYS 0x{:03X}", addr),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Lores => write!(f, "LOW"),
+            Hires => write!(f, "HIGH"),
+            Jp(addr) => write!(f, "JP 0x{:03X}", addr),
+            Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            SeByte(x, kk) => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            SneByte(x, kk) => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            LdByte(x, kk) => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            AddByte(x, kk) => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            JpV0(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Rnd(x, kk) => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdILong => write!(f, "LD I, LONG"),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            LdRegDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdRegKey(x) => write!(f, "LD V{:X}, K", x),
+            LdDtReg(x) => write!(f, "LD DT, V{:X}", x),
+            LdStReg(x) => write!(f, "LD ST, V{:X}", x),
+            AddIReg(x) => write!(f, "ADD I, V{:X}", x),
+            LdFont(x) => write!(f, "LD F, V{:X}", x),
+            LdBigFont(x) => write!(f, "LD HF, V{:X}", x),
+            Bcd(x) => write!(f, "LD B, V{:X}", x),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+            StoreRegs(x) => write!(f, "LD [I], V{:X}", x),
+            LoadRegs(x) => write!(f, "LD V{:X}, [I]", x),
+            StoreRpl(x) => write!(f, "LD R, V{:X}", x),
+            LoadRpl(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
+
+/**
+ * Disassemble a program into a listing with one instruction per line.
+ * Each line contains the address, the opcode and the mnemonic, i.e. `0x200  6005       LD V0, 0x05`.
+ * `start` is the address the program is loaded at, usually `0x200`.
+ * Words that aren't valid instructions are listed as data.
+ */
+pub fn disassemble(program: &[u8], start: usize) -> String {
+    let mut out = String::new();
+    let mut pos = 0;
+    while pos < program.len() {
+        let addr = start + pos;
+        if pos + 1 == program.len() {
+            out += &format!(
+                "0x{:03X}  {:02X}         DB 0x{:02X}\n",
+                addr, program[pos], program[pos]
+            );
+            break;
+        }
+        let opcode = ((program[pos] as u16) << 8) | program[pos + 1] as u16;
+        let line = match Instruction::decode(opcode) {
+            // Show the address loaded for F000 NNNN, if it is there
+            Ok(Instruction::LdILong) if pos + 3 < program.len() => {
+                let long = ((program[pos + 2] as u16) << 8) | program[pos + 3] as u16;
+                pos += 2;
+                format!("{:04X} {:04X}  LD I, LONG 0x{:04X}", opcode, long, long)
+            }
+            Ok(inst) => format!("{:04X}       {}", opcode, inst),
+            Err(_) => format!("{:04X}       DW 0x{:04X}", opcode, opcode),
+        };
+        out += &format!("0x{:03X}  {}\n", addr, line);
+        pos += 2;
+    }
+    return out;
+}
@@ -0,0 +1,229 @@
+#![allow(clippy::needless_return)]
+use crate::audio::{AudioBackend, NullBackend};
+use crate::debugger::Condition;
+use crate::instruction::Instruction;
+use crate::interfaces::Interface;
+use crate::processor::Processor;
+use crate::screenshot::{Screenshot, ScreenshotFormat};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/**
+ * Why a headless run finished.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Finish {
+    /// The maximum number of frames was rendered
+    Frames,
+    /// A register condition became true
+    Condition(Condition),
+    /// The program jumped to the instruction it was already at, and so will never do anything else
+    Halted,
+    /// The program exited using the SUPER-CHIP `00FD` instruction
+    Exited,
+}
+
+/**
+ * Hashes of the processor taken after a frame was rendered.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameHash {
+    /// Number of frames rendered, counting from 1
+    pub frame: u64,
+    /// Hash of the screen, from `Processor::screen_hash`
+    pub screen: u64,
+    /// Hash of the full state, from `Processor::state_hash`
+    pub state: u64,
+}
+
+/**
+ * An interface that needs no terminal, window or audio device, for running ROMs in CI and batch jobs.
+ *
+ * Runs for a maximum number of frames, or until a register condition is met or the program halts,
+ * then writes the final screen to a file if one was given.
+ * Only frames where the timers ticked are counted, so frames cut short by the debugger and frames
+ * rendered while paused don't change which frame is which.
+ * No keys are ever pressed, and the buzzer is silent unless an audio backend is given.
+ */
+pub struct HeadlessInterface {
+    frames: u64,
+    // Ticks of the processor's timers when the last frame was counted
+    ticks: Option<u64>,
+    max_frames: Option<u64>,
+    conditions: Vec<Condition>,
+    stop_on_halt: bool,
+    // File to write the final screen to, or `-` for stdout
+    screen_output: Option<String>,
+    screenshot: Screenshot,
+    // Frames to hash, and the hashes taken so far
+    hash_frames: BTreeSet<u64>,
+    hashes: Vec<FrameHash>,
+    // Whether to print the state hash along with the screen hash
+    print_state_hash: bool,
+    audio: Box<dyn AudioBackend>,
+    finish: Option<Finish>,
+}
+
+impl Default for HeadlessInterface {
+    fn default() -> Self {
+        HeadlessInterface::new()
+    }
+}
+
+impl HeadlessInterface {
+    pub fn new() -> HeadlessInterface {
+        return HeadlessInterface {
+            frames: 0,
+            ticks: None,
+            max_frames: None,
+            conditions: Vec::new(),
+            stop_on_halt: false,
+            screen_output: None,
+            screenshot: Screenshot::default(),
+            hash_frames: BTreeSet::new(),
+            hashes: Vec::new(),
+            print_state_hash: false,
+            audio: Box::new(NullBackend),
+            finish: None,
+        };
+    }
+    /// Stop after rendering the number of frames given
+    pub fn set_max_frames(&mut self, frames: u64) {
+        self.max_frames = Some(frames);
+    }
+    /// Stop once the condition given becomes true
+    pub fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push(condition);
+    }
+    /// Stop once the program jumps to the instruction it is already at, as many test ROMs do when finished
+    pub fn set_stop_on_halt(&mut self, stop: bool) {
+        self.stop_on_halt = stop;
+    }
+    /**
+     * Write the final screen to the file given when finished, in the screenshot format for the file's
+     * extension, or as text if there isn't one. `-` writes the text to stdout.
+     */
+    pub fn set_screen_output(&mut self, path: &str) {
+        self.screen_output = Some(path.to_string());
+    }
+    /// Set the colors and scale the final screen is written with
+    pub fn set_screenshot(&mut self, screenshot: Screenshot) {
+        self.screenshot = screenshot;
+    }
+    /**
+     * Hash the processor once the frame given has been rendered, counting from 1,
+     * and print the frame number and screen hash to stdout.
+     * Renders of unfinished frames don't count, so the hashes are the same when the debugger stops the program.
+     */
+    pub fn add_hash_frame(&mut self, frame: u64) {
+        self.hash_frames.insert(frame);
+    }
+    /// Print the state hash after the screen hash
+    pub fn set_print_state_hash(&mut self, print: bool) {
+        self.print_state_hash = print;
+    }
+    /// Play the buzzer through the audio backend given, i.e. to write it to a file
+    pub fn set_audio(&mut self, audio: Box<dyn AudioBackend>) {
+        self.audio = audio;
+    }
+    /// Get the hashes taken so far
+    pub fn get_hashes(&self) -> &[FrameHash] {
+        return &self.hashes;
+    }
+    /// Get the number of frames rendered so far
+    pub fn get_frames(&self) -> u64 {
+        return self.frames;
+    }
+    /// Get why the run finished, once it has
+    pub fn get_finish(&self) -> Option<Finish> {
+        return self.finish;
+    }
+    // Check whether the run should finish
+    fn check_finish(&self, p: &Processor) -> Option<Finish> {
+        if p.has_exited() {
+            return Some(Finish::Exited);
+        }
+        if let Some(c) = self.conditions.iter().find(|c| c.is_met(p)) {
+            return Some(Finish::Condition(*c));
+        }
+        let pc = p.get_program_counter();
+        if self.stop_on_halt
+            && pc + 1 < p.get_mem_size()
+            && matches!(Instruction::decode(p.get_word_at(pc)), Ok(Instruction::Jp(a)) if a as usize == pc)
+        {
+            return Some(Finish::Halted);
+        }
+        if self.max_frames.is_some_and(|m| self.frames >= m) {
+            return Some(Finish::Frames);
+        }
+        return None;
+    }
+    fn write_screen(&self, p: &Processor) {
+        let path = match &self.screen_output {
+            Some(path) => path,
+            None => return,
+        };
+        if path == "-" {
+            print!("{}", self.screenshot.to_text(p));
+            return;
+        }
+        let format = ScreenshotFormat::from_path(Path::new(path)).unwrap_or(ScreenshotFormat::Text);
+        if let Err(e) = fs::write(path, self.screenshot.encode(p, format)) {
+            eprintln!("Could not write screen to {}: {}", path, e);
+        }
+    }
+}
+
+impl Interface for HeadlessInterface {
+    fn update(&mut self, p: &mut Processor) -> bool {
+        self.ticks.get_or_insert(p.get_ticks());
+        if let Err(e) = self.audio.update(p) {
+            eprintln!("Stopped audio: {}", e);
+            self.audio = Box::new(NullBackend);
+        }
+        if self.finish.is_none() {
+            self.finish = self.check_finish(p);
+            if self.finish.is_some() {
+                self.write_screen(p);
+            }
+        }
+        return self.finish.is_some();
+    }
+    fn render(&mut self, p: &Processor) {
+        if self.ticks == Some(p.get_ticks()) {
+            return;
+        }
+        self.ticks = Some(p.get_ticks());
+        self.frames += 1;
+        if self.hash_frames.contains(&self.frames) {
+            let hash = FrameHash {
+                frame: self.frames,
+                screen: p.screen_hash(),
+                state: p.state_hash(),
+            };
+            if self.print_state_hash {
+                println!("{} {:016x} {:016x}", hash.frame, hash.screen, hash.state);
+            } else {
+                println!("{} {:016x}", hash.frame, hash.screen);
+            }
+            self.hashes.push(hash);
+        }
+    }
+    fn exit(&mut self) {
+        if let Err(e) = self.audio.finish() {
+            eprintln!("Could not finish audio: {}", e);
+        }
+    }
+    /**
+     * Succeeds unless conditions were given or halting was checked for, and the frames ran out before
+     * any of them happened.
+     */
+    fn get_exit_code(&self) -> i32 {
+        let waiting = !self.conditions.is_empty() || self.stop_on_halt;
+        return match self.finish {
+            Some(Finish::Frames) if waiting => 2,
+            _ => 0,
+        };
+    }
+}
@@ -1,5 +1,34 @@
+#![allow(clippy::needless_return)]
 use crate::processor::Processor;
 
+/**
+ * Actions the user can trigger through an interface, outside of the CHIP-8 keypad.
+ * Handled by the main program.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Save the processor's state to the current slot
+    SaveState,
+    /// Load the processor's state from the current slot
+    LoadState,
+    /// Select the next save state slot
+    NextSlot,
+    /// Start rewinding gameplay
+    StartRewind,
+    /// Stop rewinding gameplay and resume from where it was rewound to
+    StopRewind,
+    /// Pause the program, or resume it if it is paused
+    TogglePause,
+    /// Run a single instruction while paused
+    StepInto,
+    /// Run a single instruction while paused, running the whole subroutine if it calls one
+    StepOver,
+    /// Run until the current subroutine returns while paused
+    StepOut,
+    /// Save a screenshot of the screen
+    Screenshot,
+}
+
 /**
  * An interface between the user and the CHIP-8 Processor.
  * Can be implemented for different interfaces (i.e. terminal, open-gl) and used
@@ -19,4 +48,25 @@ pub trait Interface {
      * Cleanup function that should be called on exit before the program quits.
      */
     fn exit(&mut self);
+    /**
+     * Return the hotkeys the user has pressed since the last call.
+     */
+    fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        return Vec::new();
+    }
+    /**
+     * Show a short status message to the user, i.e. after saving a state.
+     */
+    fn show_message(&mut self, _message: &str) {}
+    /**
+     * Show the state of the processor while it is paused in the debugger.
+     * Called with an empty string when it resumes.
+     */
+    fn show_state(&mut self, _state: &str) {}
+    /**
+     * Return the status the program should exit with once the interface asks it to.
+     */
+    fn get_exit_code(&self) -> i32 {
+        return 0;
+    }
 }
@@ -1,10 +1,12 @@
+mod headless_interface;
 mod interface;
 #[cfg(feature = "open-gl")]
 mod opengl_interface;
 #[cfg(feature = "terminal")]
 mod terminal_interface;
 
-pub use interface::Interface;
+pub use headless_interface::{Finish, FrameHash, HeadlessInterface};
+pub use interface::{Hotkey, Interface};
 #[cfg(feature = "open-gl")]
 pub use opengl_interface::OpenGlInterface;
 #[cfg(feature = "terminal")]
@@ -1,13 +1,36 @@
-use crate::interfaces::Interface;
+#![allow(clippy::needless_return)]
+use crate::audio::{AudioBackend, NullBackend};
+use crate::interfaces::{Hotkey, Interface};
+use crate::keymap::KeyMap;
 use crate::processor::Processor;
 
-extern crate rodio;
-
-use rodio::{source::SineWave, OutputStream, Sink};
-
 use gl::types::{GLchar, GLfloat, GLint, GLsizei, GLuint};
 use glfw::{Context, Glfw, GlfwReceiver, PWindow, WindowEvent};
-use std::{array, ffi::CString, mem, os::raw::c_void, ptr};
+use std::{ffi::CString, mem, os::raw::c_void, ptr};
+
+// Size of the pixel grid drawn, big enough for the SUPER-CHIP high resolution mode
+// In low resolution mode each CHIP-8 pixel is drawn as 2x2 grid pixels
+const GRID_WIDTH: usize = 128;
+const GRID_HEIGHT: usize = 64;
+// Colors to draw each pixel with, one for each combination of XO-CHIP bitplanes
+const PALETTE: [[f32; 3]; 4] = [
+    [0.0, 0.0, 0.0],
+    [1.0, 1.0, 1.0],
+    [0.67, 0.67, 0.67],
+    [0.33, 0.33, 0.33],
+];
+
+// Keys for each hotkey
+const HOTKEY_MAP: [(glfw::Key, Hotkey); 8] = [
+    (glfw::Key::F5, Hotkey::SaveState),
+    (glfw::Key::F6, Hotkey::NextSlot),
+    (glfw::Key::F7, Hotkey::TogglePause),
+    (glfw::Key::F8, Hotkey::StepInto),
+    (glfw::Key::F9, Hotkey::LoadState),
+    (glfw::Key::F10, Hotkey::StepOver),
+    (glfw::Key::F11, Hotkey::StepOut),
+    (glfw::Key::F12, Hotkey::Screenshot),
+];
 
 pub struct OpenGlInterface {
     glfw: Glfw,
@@ -15,10 +38,12 @@ pub struct OpenGlInterface {
     window: PWindow,
     cbo: GLuint,
     input_states: [bool; 0x10],
-    sink: Option<Sink>,
-    // Stream just needs to be kept in scope
-    #[allow(dead_code)]
-    stream: Option<OutputStream>,
+    keymap: KeyMap,
+    // Number of keys held down for each CHIP-8 key, since it can be bound to more than one
+    keys_held: [u8; 0x10],
+    // Hotkeys pressed since they were last taken
+    hotkeys: Vec<Hotkey>,
+    audio: Box<dyn AudioBackend>,
 }
 const VERTEX_SHADER: &str = r#"
 #version 330 core
@@ -48,7 +73,11 @@ void main() {
  * Interface that uses OpenGL to render the processor.
  */
 impl OpenGlInterface {
-    pub fn new() -> OpenGlInterface {
+    /**
+     * Open a window, playing the buzzer through the audio backend given
+     * and reading the CHIP-8 keys using the key map given.
+     */
+    pub fn new(audio: Box<dyn AudioBackend>, keymap: KeyMap) -> OpenGlInterface {
         // glfw: initialize and configure
         // ------------------------------
         let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
@@ -79,35 +108,36 @@ impl OpenGlInterface {
         const Y_MIN: f32 = -1.0;
         const Y_MAX: f32 = 1.0;
         // Generate 4 vertices for each pixel (2 triangles)
-        let vertices: [f32; 3 * 4 * 64 * 32] = array::from_fn(|i| {
-            const PIXEL: [[f32; 3]; 4] = [
-                [0.0, 0.0, 0.0],
-                [1.0, 0.0, 0.0],
-                [1.0, 1.0, 0.0],
-                [0.0, 1.0, 0.0],
-            ];
-            let x = (i / (3 * 4)) % 64;
-            let y = (i / (3 * 4)) / 64;
-            let pixel_i = i % 12;
-            let vertex = PIXEL[pixel_i / 3];
-            return match i % 3 {
-                0 => X_MIN + (x as f32 + vertex[0]) / 64.0 * (X_MAX - X_MIN),
-                1 => Y_MIN + (y as f32 + vertex[1]) / 32.0 * (Y_MAX - Y_MIN),
-                2 => 0 as f32,
-                _ => 0 as f32,
-            };
-        });
+        let vertices: Vec<f32> = (0..3 * 4 * GRID_WIDTH * GRID_HEIGHT)
+            .map(|i| {
+                const PIXEL: [[f32; 3]; 4] = [
+                    [0.0, 0.0, 0.0],
+                    [1.0, 0.0, 0.0],
+                    [1.0, 1.0, 0.0],
+                    [0.0, 1.0, 0.0],
+                ];
+                let x = (i / (3 * 4)) % GRID_WIDTH;
+                let y = (i / (3 * 4)) / GRID_WIDTH;
+                let pixel_i = i % 12;
+                let vertex = PIXEL[pixel_i / 3];
+                return match i % 3 {
+                    0 => X_MIN + (x as f32 + vertex[0]) / GRID_WIDTH as f32 * (X_MAX - X_MIN),
+                    1 => Y_MIN + (y as f32 + vertex[1]) / GRID_HEIGHT as f32 * (Y_MAX - Y_MIN),
+                    _ => 0.0,
+                };
+            })
+            .collect();
 
         // Generate indices linking those 4 vertices into 2 triangles
-        let indices: [i32; 6 * 64 * 32] = array::from_fn(|i| {
-            let pixel = i / 6;
-            return ((4 * pixel) + [0, 1, 2, 0, 2, 3][i % 6]) as i32;
-        });
+        let indices: Vec<i32> = (0..6 * GRID_WIDTH * GRID_HEIGHT)
+            .map(|i| {
+                let pixel = i / 6;
+                return ((4 * pixel) + [0, 1, 2, 0, 2, 3][i % 6]) as i32;
+            })
+            .collect();
 
         // Default color is just black
-        let colors: [f32; 3 * 4 * 64 * 32] = array::from_fn(|_| {
-            return 0.0 as f32;
-        });
+        let colors: Vec<f32> = vec![0.0; 3 * 4 * GRID_WIDTH * GRID_HEIGHT];
 
         let mut vao = 0;
         let mut vbo = 0;
@@ -178,52 +208,27 @@ impl OpenGlInterface {
             gl::Hint(gl::POLYGON_SMOOTH_HINT, gl::DONT_CARE);
             gl::Hint(gl::LINE_SMOOTH_HINT, gl::DONT_CARE);
         }
-        let device = OutputStream::try_default().ok();
-        let sink = match &device {
-            Some(d) => Sink::try_new(&d.1)
-                .and_then(|s| {
-                    s.set_volume(0.1);
-                    s.append(SineWave::new(350.0));
-                    s.pause();
-                    return Ok(s);
-                })
-                .ok(),
-            None => panic!("No sound!"),
-        };
-
         OpenGlInterface {
             glfw,
             events,
             window,
             cbo: cbo,
             input_states: [false; 0x10],
-            sink,
-            stream: device.and_then(|d| Some(d.0)),
+            keymap,
+            keys_held: [0; 0x10],
+            hotkeys: Vec::new(),
+            audio,
         }
     }
 }
 
 impl Interface for OpenGlInterface {
-    fn exit(&mut self) {}
+    fn exit(&mut self) {
+        if let Err(e) = self.audio.finish() {
+            eprintln!("Could not finish audio: {}", e);
+        }
+    }
     fn update(&mut self, p: &mut Processor) -> bool {
-        let key_map = [
-            glfw::Key::X,
-            glfw::Key::Num1,
-            glfw::Key::Num2,
-            glfw::Key::Num3,
-            glfw::Key::Q,
-            glfw::Key::W,
-            glfw::Key::E,
-            glfw::Key::A,
-            glfw::Key::S,
-            glfw::Key::D,
-            glfw::Key::Z,
-            glfw::Key::C,
-            glfw::Key::Num4,
-            glfw::Key::R,
-            glfw::Key::F,
-            glfw::Key::V,
-        ];
         // Check for events
         self.glfw.poll_events();
         for (_, event) in glfw::flush_messages(&self.events) {
@@ -236,31 +241,40 @@ impl Interface for OpenGlInterface {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     self.window.set_should_close(true);
                 }
-                glfw::WindowEvent::Key(key, _, action, _) => {
-                    match key_map.iter().position(|k| *k == key) {
-                        Some(i) => {
-                            self.input_states[i] = match action {
-                                glfw::Action::Press => true,
-                                glfw::Action::Release => false,
-                                _ => self.input_states[i],
-                            };
-                        }
-                        _ => {}
+                // Rewind while backspace is held
+                glfw::WindowEvent::Key(glfw::Key::Backspace, _, glfw::Action::Press, _) => {
+                    self.hotkeys.push(Hotkey::StartRewind);
+                }
+                glfw::WindowEvent::Key(glfw::Key::Backspace, _, glfw::Action::Release, _) => {
+                    self.hotkeys.push(Hotkey::StopRewind);
+                }
+                glfw::WindowEvent::Key(key, _, glfw::Action::Press, _)
+                    if HOTKEY_MAP.iter().any(|(k, _)| *k == key) =>
+                {
+                    let (_, h) = HOTKEY_MAP.iter().find(|(k, _)| *k == key).unwrap();
+                    self.hotkeys.push(*h);
+                }
+                glfw::WindowEvent::Key(key, scancode, action, _) => {
+                    let name = match get_key_name(key, scancode) {
+                        Some(n) => n,
+                        None => continue,
+                    };
+                    for i in self.keymap.get_chip8_keys(&name) {
+                        self.keys_held[i] = match action {
+                            glfw::Action::Press => self.keys_held[i].saturating_add(1),
+                            glfw::Action::Release => self.keys_held[i].saturating_sub(1),
+                            glfw::Action::Repeat => self.keys_held[i],
+                        };
+                        self.input_states[i] = self.keys_held[i] > 0;
                     }
                 }
                 _ => {}
             }
         }
         p.update_inputs(self.input_states);
-        match &self.sink {
-            Some(s) => {
-                if s.is_paused() && p.get_st() > 0 {
-                    s.play();
-                } else if !s.is_paused() && p.get_st() == 0 {
-                    s.pause();
-                }
-            }
-            None => {}
+        if let Err(e) = self.audio.update(p) {
+            self.show_message(&format!("Stopped audio: {}", e));
+            self.audio = Box::new(NullBackend);
         }
         return self.window.should_close();
     }
@@ -270,15 +284,18 @@ impl Interface for OpenGlInterface {
         unsafe {
             // Calculate colors of each vertex
             // 3 floats per color * 4 colors/vertices per pixel
-            let mut colors: [f32; 12 * 32 * 64] = [0.0; 12 * 32 * 64];
-            for y in 0..32 as usize {
-                for x in 0..64 as usize {
-                    let c = if p.get_pixel_at(x as u8, 63 - y as u8) {
-                        1
-                    } else {
-                        0
-                    } as f32;
-                    colors[(12 * (x + 64 * y))..(12 * (x + 64 * y + 1))].copy_from_slice(&[c; 12]);
+            let mut colors: Vec<f32> = vec![0.0; 12 * GRID_WIDTH * GRID_HEIGHT];
+            // How many grid pixels each CHIP-8 pixel takes up
+            let scale = GRID_WIDTH / p.get_screen_width();
+            let height = p.get_screen_height();
+            for y in 0..GRID_HEIGHT {
+                for x in 0..GRID_WIDTH {
+                    // Flip vertically, since OpenGL's origin is at the bottom
+                    let c = PALETTE[p
+                        .get_pixel_color_at((x / scale) as u8, (height - 1 - y / scale) as u8)
+                        as usize];
+                    colors[(12 * (x + GRID_WIDTH * y))..(12 * (x + GRID_WIDTH * y + 1))]
+                        .copy_from_slice(&[c; 4].concat());
                 }
             }
 
@@ -294,7 +311,7 @@ impl Interface for OpenGlInterface {
             // Draw pixels
             gl::DrawElements(
                 gl::TRIANGLES,
-                2 * 3 * 64 * 32,
+                (2 * 3 * GRID_WIDTH * GRID_HEIGHT) as GLsizei,
                 gl::UNSIGNED_INT,
                 ptr::null(),
             );
@@ -303,6 +320,18 @@ impl Interface for OpenGlInterface {
         // Refresh page
         self.window.swap_buffers();
     }
+    fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        return std::mem::take(&mut self.hotkeys);
+    }
+    fn show_message(&mut self, message: &str) {
+        self.window.set_title(&format!("CHIP-8 - {}", message));
+    }
+    fn show_state(&mut self, state: &str) {
+        // The window only has room for the title, so print the state to the console instead
+        if !state.is_empty() {
+            println!("{}", state);
+        }
+    }
 }
 
 unsafe fn compile_shader(shader_src: &str, shader_type: u32) -> u32 {
@@ -359,3 +388,30 @@ unsafe fn create_program(shaders: Vec<u32>) -> u32 {
 
     return program;
 }
+
+// Get the name of a key as used in key maps, which for keys that type something depends on the layout
+fn get_key_name(key: glfw::Key, scancode: glfw::Scancode) -> Option<String> {
+    use glfw::Key;
+    let name = match key {
+        Key::Up => "up",
+        Key::Down => "down",
+        Key::Left => "left",
+        Key::Right => "right",
+        Key::Space => "space",
+        Key::Enter | Key::KpEnter => "enter",
+        Key::Tab => "tab",
+        // The keypad types the same digits as the number row, so needs its own names
+        Key::Kp0 => "kp0",
+        Key::Kp1 => "kp1",
+        Key::Kp2 => "kp2",
+        Key::Kp3 => "kp3",
+        Key::Kp4 => "kp4",
+        Key::Kp5 => "kp5",
+        Key::Kp6 => "kp6",
+        Key::Kp7 => "kp7",
+        Key::Kp8 => "kp8",
+        Key::Kp9 => "kp9",
+        _ => return glfw::get_key_name(Some(key), Some(scancode)).map(|n| n.to_lowercase()),
+    };
+    return Some(name.to_string());
+}
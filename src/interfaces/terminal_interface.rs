@@ -1,24 +1,38 @@
-use crate::interfaces::Interface;
+#![allow(clippy::needless_return)]
+use crate::audio::{AudioBackend, NullBackend};
+use crate::interfaces::{Hotkey, Interface};
+use crate::keymap::KeyMap;
 use crate::processor::Processor;
 extern crate crossterm;
-extern crate rodio;
 
 use crossterm::{
     cursor::{Hide, MoveTo, Show},
     event::{poll, read, Event, KeyCode, KeyModifiers},
+    style::{Color, Stylize},
     terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType},
     ExecutableCommand,
 };
-use gl::types::GLsync;
-use rodio::{source::SineWave, OutputStream, Sink};
 
 use std::{
     io::{stdout, Stdout, Write},
     time::Duration,
 };
 
-const KEY_MAP: [char; 16] = [
-    'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+// Characters to draw each pixel color with, one for each combination of XO-CHIP bitplanes
+const GLYPHS: [&str; 4] = ["  ", "[]", "()", "##"];
+// Colors to draw each pixel color with in high resolution mode
+const PALETTE: [Color; 4] = [Color::Black, Color::White, Color::Grey, Color::DarkGrey];
+
+// Function keys for each hotkey
+const HOTKEY_MAP: [(u8, Hotkey); 8] = [
+    (5, Hotkey::SaveState),
+    (6, Hotkey::NextSlot),
+    (7, Hotkey::TogglePause),
+    (8, Hotkey::StepInto),
+    (9, Hotkey::LoadState),
+    (10, Hotkey::StepOver),
+    (11, Hotkey::StepOut),
+    (12, Hotkey::Screenshot),
 ];
 
 /**
@@ -27,33 +41,35 @@ const KEY_MAP: [char; 16] = [
  */
 pub struct TerminalInterface {
     stdout: Stdout,
-    sink: Option<rodio::Sink>,
-    // Stream just needs to be kept in scope
-    #[allow(dead_code)]
-    stream: Option<rodio::OutputStream>,
+    // Hotkeys pressed since they were last taken
+    hotkeys: Vec<Hotkey>,
+    // Status message shown under the debug information
+    message: String,
+    // Processor state shown under the message while paused
+    state: String,
+    // Whether the rewind key has been toggled on
+    rewinding: bool,
+    audio: Box<dyn AudioBackend>,
+    keymap: KeyMap,
 }
 
 impl TerminalInterface {
-    pub fn new() -> TerminalInterface {
+    /**
+     * Take over the terminal, playing the buzzer through the audio backend given
+     * and reading the CHIP-8 keys using the key map given.
+     */
+    pub fn new(audio: Box<dyn AudioBackend>, keymap: KeyMap) -> TerminalInterface {
         let mut stdout = stdout();
         enable_raw_mode().unwrap();
         stdout.execute(Hide).unwrap();
-        let device = OutputStream::try_default().ok();
-        let sink = match &device {
-            Some(d) => Sink::try_new(&d.1)
-                .and_then(|s| {
-                    s.set_volume(0.1);
-                    s.append(SineWave::new(350.0));
-                    s.pause();
-                    return Ok(s);
-                })
-                .ok(),
-            None => panic!("No sound!"),
-        };
         return TerminalInterface {
             stdout,
-            sink,
-            stream: device.and_then(|d| Some(d.0)),
+            hotkeys: Vec::new(),
+            message: String::new(),
+            state: String::new(),
+            rewinding: false,
+            audio,
+            keymap,
         };
     }
 }
@@ -70,32 +86,42 @@ impl Interface for TerminalInterface {
                     }
                     // For Terminal, we toggle the keys instead of detecting key up/key down
                     match evt.code {
-                        KeyCode::Char(c) => match KEY_MAP.iter().position(|ch| *ch == c) {
-                            Some(i) => {
-                                inputs[i] = !inputs[i];
+                        KeyCode::Backspace => {
+                            self.rewinding = !self.rewinding;
+                            self.hotkeys.push(if self.rewinding {
+                                Hotkey::StartRewind
+                            } else {
+                                Hotkey::StopRewind
+                            });
+                        }
+                        KeyCode::F(n) => {
+                            if let Some((_, h)) = HOTKEY_MAP.iter().find(|(k, _)| *k == n) {
+                                self.hotkeys.push(*h);
                             }
-                            None => {}
-                        },
-                        _ => {}
+                        }
+                        code => {
+                            if let Some(name) = get_key_name(code) {
+                                for i in self.keymap.get_chip8_keys(&name) {
+                                    inputs[i] = !inputs[i];
+                                }
+                            }
+                        }
                     }
                 }
                 _ => {}
             }
             p.update_inputs(inputs);
         }
-        match &self.sink {
-            Some(s) => {
-                if s.is_paused() && p.get_st() > 0 {
-                    s.play();
-                } else if !s.is_paused() && p.get_st() == 0 {
-                    s.pause();
-                }
-            }
-            None => {}
+        if let Err(e) = self.audio.update(p) {
+            self.show_message(&format!("Stopped audio: {}", e));
+            self.audio = Box::new(NullBackend);
         }
         return false;
     }
     fn exit(&mut self) {
+        if let Err(e) = self.audio.finish() {
+            eprintln!("Could not finish audio: {}", e);
+        }
         self.stdout.execute(Show).unwrap();
         disable_raw_mode().unwrap();
     }
@@ -104,19 +130,37 @@ impl Interface for TerminalInterface {
         self.stdout.execute(Clear(ClearType::All)).unwrap();
         self.stdout.execute(MoveTo(0, 0)).unwrap();
         // Create a buffer for the actual screen for speed reasons
-        let mut buf = [[' ' as u8; 2 * 64]; 32];
-        for y in 0..32 {
-            for x in 0..64 {
-                if p.get_pixel_at(x as u8, y as u8) {
-                    buf[y][2 * x] = '[' as u8;
-                    buf[y][2 * x + 1] = ']' as u8;
-                };
-            }
-        }
-        let eol = ['\r' as u8, '\n' as u8];
+        let width = p.get_screen_width();
+        let height = p.get_screen_height();
+        let buf: Vec<String> = if p.is_hires() {
+            // Too big to draw 2 characters per pixel, so draw 2 rows per line using half blocks instead
+            // The top pixel is the foreground color and the bottom one is the background
+            (0..height / 2)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| {
+                            let top = p.get_pixel_color_at(x as u8, (2 * y) as u8);
+                            let bottom = p.get_pixel_color_at(x as u8, (2 * y + 1) as u8);
+                            '\u{2580}'
+                                .with(PALETTE[top as usize])
+                                .on(PALETTE[bottom as usize])
+                                .to_string()
+                        })
+                        .collect()
+                })
+                .collect()
+        } else {
+            (0..height)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| GLYPHS[p.get_pixel_color_at(x as u8, y as u8) as usize])
+                        .collect()
+                })
+                .collect()
+        };
         for row in buf {
-            self.stdout.write(&row).unwrap();
-            self.stdout.write(&eol).unwrap();
+            self.stdout.write_all(row.as_bytes()).unwrap();
+            self.stdout.write_all(b"\r\n").unwrap();
         }
         // Print debug information
         self.stdout.execute(MoveTo(0, 33)).unwrap();
@@ -132,6 +176,36 @@ impl Interface for TerminalInterface {
         print!(" {:#4X?} | {:#4X?} ", p.get_dt(), p.get_st());
         (0..=0xF).for_each(|i| print!("|  {}   ", if p.get_input_state(i) { 'T' } else { 'F' }));
         self.stdout.execute(MoveTo(0, 37)).unwrap();
+        print!("{}", self.message);
+        // Raw mode needs each line to be moved to
+        for (i, line) in self.state.lines().enumerate() {
+            self.stdout.execute(MoveTo(0, 38 + i as u16)).unwrap();
+            print!("{}", line);
+        }
         self.stdout.flush().unwrap();
     }
+    fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        return std::mem::take(&mut self.hotkeys);
+    }
+    fn show_message(&mut self, message: &str) {
+        self.message = message.to_string();
+    }
+    fn show_state(&mut self, state: &str) {
+        self.state = state.to_string();
+    }
+}
+
+// Get the name of a key as used in key maps
+fn get_key_name(code: KeyCode) -> Option<String> {
+    return match code {
+        KeyCode::Char(' ') => Some("space".to_string()),
+        KeyCode::Char(c) => Some(c.to_lowercase().to_string()),
+        KeyCode::Up => Some("up".to_string()),
+        KeyCode::Down => Some("down".to_string()),
+        KeyCode::Left => Some("left".to_string()),
+        KeyCode::Right => Some("right".to_string()),
+        KeyCode::Enter => Some("enter".to_string()),
+        KeyCode::Tab => Some("tab".to_string()),
+        _ => None,
+    };
 }
@@ -0,0 +1,76 @@
+#![allow(clippy::needless_return)]
+// Keys for each CHIP-8 key on a QWERTY keyboard, laid out like the COSMAC VIP keypad
+const DEFAULT_KEYS: [&str; 0x10] = [
+    "x", "1", "2", "3", "q", "w", "e", "a", "s", "d", "z", "c", "4", "r", "f", "v",
+];
+
+/**
+ * Which keys on the keyboard press each CHIP-8 key.
+ *
+ * Keys are named by what they type on the user's layout, in lowercase, i.e. `a` or `;`, or by name for
+ * keys that don't type anything: `up`, `down`, `left`, `right`, `space`, `enter`, `tab` and `kp0`-`kp9`
+ * for the keypad. Each CHIP-8 key can be bound to any number of keys, and a key can press more than one
+ * CHIP-8 key.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyMap {
+    keys: [Vec<String>; 0x10],
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap::new()
+    }
+}
+
+impl KeyMap {
+    /// Create the default key map, for a QWERTY keyboard
+    pub fn new() -> KeyMap {
+        return KeyMap {
+            keys: std::array::from_fn(|i| vec![DEFAULT_KEYS[i].to_string()]),
+        };
+    }
+    /// Bind the CHIP-8 key to the keys given, replacing the keys it was bound to
+    pub fn set_keys(&mut self, chip8_key: usize, keys: &[&str]) {
+        self.keys[chip8_key] = keys.iter().map(|k| normalize_key_name(k)).collect();
+    }
+    /// Get the names of the keys bound to the CHIP-8 key
+    pub fn get_keys(&self, chip8_key: usize) -> &[String] {
+        return &self.keys[chip8_key];
+    }
+    /// Get every CHIP-8 key the key with the name given is bound to
+    pub fn get_chip8_keys(&self, key: &str) -> Vec<usize> {
+        let key = normalize_key_name(key);
+        return (0..0x10).filter(|i| self.keys[*i].contains(&key)).collect();
+    }
+    /**
+     * Apply a binding of the form `5=w,up`, binding the CHIP-8 key before the `=` to the keys after it.
+     */
+    pub fn apply(&mut self, binding: &str) -> Result<(), String> {
+        let (chip8_key, keys) = binding
+            .split_once('=')
+            .ok_or(format!("Expected CHIP-8 key=keys, got '{}'", binding))?;
+        let keys: Vec<&str> = keys.split(',').filter(|k| !k.trim().is_empty()).collect();
+        self.set_keys(parse_chip8_key(chip8_key)?, &keys);
+        return Ok(());
+    }
+}
+
+/**
+ * Parse the name of a CHIP-8 key, a single hexadecimal digit.
+ */
+pub fn parse_chip8_key(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    return match usize::from_str_radix(s, 16) {
+        Ok(k) if s.len() == 1 => Ok(k),
+        _ => Err(format!("Invalid CHIP-8 key '{}', expected 0-F", s)),
+    };
+}
+
+// Keys are matched ignoring case and surrounding whitespace, apart from the space key itself
+fn normalize_key_name(name: &str) -> String {
+    if name == " " {
+        return "space".to_string();
+    }
+    return name.trim().to_lowercase();
+}
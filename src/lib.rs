@@ -25,11 +25,41 @@
 //!   assert_eq!(p.get_register_value(0x0), 0xA);
 //! }
 //! ```
+pub mod assembler;
+pub mod audio;
+pub mod config;
+pub mod dap;
+pub mod debugger;
+pub mod emulator;
 mod errors;
+pub mod gdb;
+mod hash;
+pub mod instruction;
 #[doc(hidden)]
 pub mod interfaces;
+pub mod keymap;
+pub mod movie;
 #[doc(hidden)]
 pub mod processor;
+pub mod quirks;
+pub mod recording;
+pub mod rewind;
+pub mod screenshot;
+pub mod trace;
 
+pub use self::assembler::assemble;
+pub use self::debugger::Debugger;
+pub use self::emulator::Emulator;
+#[allow(deprecated)]
 pub use self::errors::OpcodeError;
-pub use self::processor::Processor;
+pub use self::errors::{
+    AssembleError, AudioError, DecodeError, EmulatorError, MovieError, StateError,
+};
+pub use self::instruction::Instruction;
+pub use self::movie::Movie;
+pub use self::processor::{Processor, Variant};
+pub use self::quirks::Quirks;
+pub use self::recording::Recorder;
+pub use self::rewind::RewindBuffer;
+pub use self::screenshot::Screenshot;
+pub use self::trace::Tracer;
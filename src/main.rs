@@ -1,137 +1,1032 @@
-mod errors;
-mod interfaces;
-mod processor;
+extern crate rust_chip8_opengl;
 
-use interfaces::Interface;
 #[cfg(feature = "open-gl")]
-use interfaces::OpenGlInterface;
+use rust_chip8_opengl::interfaces::OpenGlInterface;
 #[cfg(feature = "terminal")]
-use interfaces::TerminalInterface;
+use rust_chip8_opengl::interfaces::TerminalInterface;
+use rust_chip8_opengl::interfaces::{HeadlessInterface, Hotkey, Interface};
 
-use clap::{Parser, ValueEnum};
-use processor::Processor;
+use clap::{Parser, Subcommand, ValueEnum};
+use rust_chip8_opengl::audio::{
+    self, AudioBackend, FileBackend, NullBackend, Tone, WavBackend, Waveform,
+};
+#[cfg(any(feature = "terminal", feature = "open-gl"))]
+use rust_chip8_opengl::config::{self, Config};
+use rust_chip8_opengl::dap;
+use rust_chip8_opengl::debugger::{format_state, parse_address, Condition, StopReason};
+use rust_chip8_opengl::emulator::DEFAULT_INSTRUCTIONS_PER_FRAME;
+use rust_chip8_opengl::gdb::GdbServer;
+use rust_chip8_opengl::instruction::disassemble;
+#[cfg(any(feature = "terminal", feature = "open-gl"))]
+use rust_chip8_opengl::keymap::KeyMap;
+use rust_chip8_opengl::recording::RecordingFormat;
+use rust_chip8_opengl::screenshot::{parse_color, ScreenshotFormat};
+use rust_chip8_opengl::trace::{diff_processors, diff_traces, parse_trace, TraceFormat};
+use rust_chip8_opengl::{
+    assemble, Emulator, Movie, Processor, Quirks, Recorder, RewindBuffer, Screenshot, Tracer,
+    Variant,
+};
 use std::boxed::Box;
-use std::thread;
-use std::time::{Duration, Instant};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::{
     fs,
-    fs::{File, OpenOptions},
-    io::Write,
+    fs::File,
+    io,
+    io::{BufReader, BufWriter, Write},
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Mode {
     Terminal,
     OpenGl,
+    Headless,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Mode::Terminal => "terminal",
+                Mode::OpenGl => "open_gl",
+                Mode::Headless => "headless",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum VariantArg {
+    Chip8,
+    Schip,
+    Xochip,
+}
+
+impl fmt::Display for VariantArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                VariantArg::Chip8 => "chip8",
+                VariantArg::Schip => "schip",
+                VariantArg::Xochip => "xochip",
+            }
+        )
+    }
+}
+
+impl From<VariantArg> for Variant {
+    #[allow(clippy::needless_return)]
+    fn from(v: VariantArg) -> Variant {
+        return match v {
+            VariantArg::Chip8 => Variant::Chip8,
+            VariantArg::Schip => Variant::SuperChip,
+            VariantArg::Xochip => Variant::XoChip,
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum TraceFormatArg {
+    Text,
+    Json,
+}
+
+impl fmt::Display for TraceFormatArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TraceFormatArg::Text => "text",
+                TraceFormatArg::Json => "json",
+            }
+        )
+    }
 }
 
-impl ToString for Mode {
-    fn to_string(&self) -> String {
-        return String::from(match self {
-            Mode::Terminal => "terminal",
-            Mode::OpenGl => "open_gl",
-        });
+impl From<TraceFormatArg> for TraceFormat {
+    #[allow(clippy::needless_return)]
+    fn from(t: TraceFormatArg) -> TraceFormat {
+        return match t {
+            TraceFormatArg::Text => TraceFormat::Text,
+            TraceFormatArg::Json => TraceFormat::Json,
+        };
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum ScreenshotFormatArg {
+    Png,
+    Ppm,
+    Pbm,
+    Text,
+}
+
+impl fmt::Display for ScreenshotFormatArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ScreenshotFormatArg::Png => "png",
+                ScreenshotFormatArg::Ppm => "ppm",
+                ScreenshotFormatArg::Pbm => "pbm",
+                ScreenshotFormatArg::Text => "text",
+            }
+        )
+    }
+}
+
+impl From<ScreenshotFormatArg> for ScreenshotFormat {
+    #[allow(clippy::needless_return)]
+    fn from(s: ScreenshotFormatArg) -> ScreenshotFormat {
+        return match s {
+            ScreenshotFormatArg::Png => ScreenshotFormat::Png,
+            ScreenshotFormatArg::Ppm => ScreenshotFormat::Ppm,
+            ScreenshotFormatArg::Pbm => ScreenshotFormat::Pbm,
+            ScreenshotFormatArg::Text => ScreenshotFormat::Text,
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum WaveformArg {
+    Square,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+impl fmt::Display for WaveformArg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                WaveformArg::Square => "square",
+                WaveformArg::Sine => "sine",
+                WaveformArg::Triangle => "triangle",
+                WaveformArg::Noise => "noise",
+            }
+        )
+    }
+}
+
+impl From<WaveformArg> for Waveform {
+    #[allow(clippy::needless_return)]
+    fn from(w: WaveformArg) -> Waveform {
+        return match w {
+            WaveformArg::Square => Waveform::Square,
+            WaveformArg::Sine => Waveform::Sine,
+            WaveformArg::Triangle => Waveform::Triangle,
+            WaveformArg::Noise => Waveform::Noise,
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum QuirksPreset {
+    Vip,
+    Chip48,
+    Schip,
+    Xochip,
+}
+
+impl From<QuirksPreset> for Quirks {
+    #[allow(clippy::needless_return)]
+    fn from(q: QuirksPreset) -> Quirks {
+        return match q {
+            QuirksPreset::Vip => Quirks::cosmac_vip(),
+            QuirksPreset::Chip48 => Quirks::chip48(),
+            QuirksPreset::Schip => Quirks::schip(),
+            QuirksPreset::Xochip => Quirks::xo_chip(),
+        };
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    // Print a listing of every instruction in a ROM
+    Disasm {
+        // ROM to disassemble
+        file: String,
+    },
+    // Assemble a source file into a ROM
+    Asm {
+        // Source file to assemble
+        file: String,
+        // ROM to write, defaults to the source file with a .ch8 extension
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    // Serve the Debug Adapter Protocol over stdin and stdout, for debugging from an editor
+    Dap,
+    // Find the first instruction where two runs differ
+    // Either compares two traces written with --trace, or runs a ROM twice with different settings
+    TraceDiff {
+        // Traces to compare
+        #[arg(num_args = 2, required_unless_present = "rom", conflicts_with = "rom")]
+        traces: Vec<String>,
+        // ROM to run side by side instead
+        #[arg(long)]
+        rom: Option<String>,
+        // Variant and quirks of each side, defaulting to chip8 and the quirks for the variant
+        #[arg(long, default_value_t = VariantArg::Chip8)]
+        left_variant: VariantArg,
+        #[arg(long, default_value_t = VariantArg::Chip8)]
+        right_variant: VariantArg,
+        #[arg(long)]
+        left_quirks: Option<QuirksPreset>,
+        #[arg(long)]
+        right_quirks: Option<QuirksPreset>,
+        // Seed for the random number generator of both sides
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        // Maximum number of instructions to run
+        #[arg(long, default_value_t = 1_000_000)]
+        steps: u64,
+        // Instructions to run between each timer tick, the same as `--ipf` when running a ROM
+        #[arg(long, default_value_t = DEFAULT_INSTRUCTIONS_PER_FRAME)]
+        ipf: u64,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "Rust CHIP-8 OpenGl")]
 #[command(version = "1.1.6")]
 #[command(about = "Simulate running CHIP-8 programs", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     // UI to use
-    // Either terminal (default) or opengl
+    // Either terminal (default), opengl or headless
     #[arg(short, long, default_value_t = Mode::Terminal)]
     mode: Mode,
 
     // File to read
-    #[arg(short, long)]
-    file: String,
+    #[arg(short, long, required = true)]
+    file: Option<String>,
+
+    // Variant of CHIP-8 to emulate
+    // Either chip8 (default), schip or xochip
+    #[arg(long, default_value_t = VariantArg::Chip8)]
+    variant: VariantArg,
+
+    // Quirks preset to use, either vip, chip48, schip or xochip
+    // Defaults to the one usually expected for the variant
+    #[arg(long)]
+    quirks: Option<QuirksPreset>,
+
+    // Override individual quirks of the preset
+    #[arg(long)]
+    shift_uses_vy: Option<bool>,
+    #[arg(long)]
+    load_store_increments_i: Option<bool>,
+    #[arg(long)]
+    logic_resets_vf: Option<bool>,
+    #[arg(long)]
+    clip_sprites: Option<bool>,
+    #[arg(long)]
+    display_wait: Option<bool>,
+    #[arg(long)]
+    jump_uses_vx: Option<bool>,
+
+    // Seed for the random number generator, so that runs can be reproduced
+    // Defaults to a random seed
+    #[arg(long)]
+    seed: Option<u64>,
+
+    // Instructions to run each frame, at 60 frames per second
+    #[arg(long, default_value_t = DEFAULT_INSTRUCTIONS_PER_FRAME, conflicts_with = "ips")]
+    ipf: u64,
+
+    // Instructions to run each second instead, rounded to a whole number per frame
+    #[arg(long)]
+    ips: Option<u64>,
+
+    // Show the speed the emulator actually runs at, once a second
+    #[arg(long, default_value_t = false)]
+    show_speed: bool,
+
+    // How many seconds of gameplay can be rewound, 0 to disable rewinding
+    #[arg(long, default_value_t = 10)]
+    rewind_seconds: u32,
+
+    // Number of frames between each rewind snapshot
+    #[arg(long, default_value_t = 2)]
+    rewind_interval: u32,
+
+    // Maximum memory used by the rewind snapshots, in KiB
+    #[arg(long, default_value_t = 16 * 1024)]
+    rewind_memory: usize,
+
+    // Addresses to pause at before running the instruction there
+    #[arg(long = "break", value_parser = parse_address_arg)]
+    breakpoints: Vec<usize>,
+
+    // Addresses of memory to pause at after they are written to
+    #[arg(long = "watch", value_parser = parse_address_arg)]
+    watchpoints: Vec<usize>,
+
+    // Register conditions to pause at when they become true, i.e. "V0 == 0x05"
+    #[arg(long = "break-if")]
+    conditions: Vec<Condition>,
+
+    // Listen for GDB on this local port, starting paused until it connects
+    #[arg(long)]
+    gdb: Option<u16>,
+
+    // Start the program paused in the debugger
+    #[arg(long, default_value_t = false)]
+    paused: bool,
+
+    // Headless mode: stop after this many frames
+    #[arg(long)]
+    frames: Option<u64>,
+
+    // Headless mode: stop when a register condition becomes true, i.e. "V0 == 0x05"
+    #[arg(long)]
+    until: Vec<Condition>,
+
+    // Headless mode: stop when the program jumps to the instruction it is already at
+    #[arg(long, default_value_t = false)]
+    until_halt: bool,
+
+    // Headless mode: file to write the final screen to, as text or a .png, .ppm or .pbm image, or - for stdout
+    #[arg(long)]
+    screen_output: Option<String>,
+
+    // Headless mode: print the frame number and screen hash once each of these frames has been rendered,
+    // i.e. "60,120,600". Stops after the last one unless --frames is given
+    #[arg(long, value_delimiter = ',')]
+    hash_frames: Vec<u64>,
+
+    // Headless mode: also print the hash of the full state with --hash-frames
+    #[arg(long, default_value_t = false)]
+    hash_state: bool,
+
+    // Format of screenshots taken with F12, either png (default), ppm, pbm or text
+    #[arg(long, default_value_t = ScreenshotFormatArg::Png)]
+    screenshot_format: ScreenshotFormatArg,
+
+    // Foreground and background colors of screenshots, i.e. "#FFFFFF"
+    #[arg(long, value_parser = parse_color_arg)]
+    screenshot_fg: Option<[u8; 3]>,
+    #[arg(long, value_parser = parse_color_arg)]
+    screenshot_bg: Option<[u8; 3]>,
+
+    // Size of each CHIP-8 pixel in screenshots
+    #[arg(long, default_value_t = 1)]
+    screenshot_scale: usize,
+
+    // Record the screen to the file, as an animated GIF if it ends in .gif or raw RGB frames otherwise,
+    // or - for raw frames on stdout. Uses the screenshot colors and scale
+    #[arg(long)]
+    record_video: Option<String>,
+
+    // Record the keys pressed on every frame to a movie file, which can be replayed exactly with --replay
+    #[arg(long)]
+    record: Option<String>,
+
+    // Replay a movie file recorded with --record, instead of reading the keyboard
+    #[arg(long, conflicts_with = "record")]
+    replay: Option<String>,
+
+    // Write the sound to the file instead of playing it, as a WAV file if it ends in .wav
+    // or raw 16 bit samples otherwise, or - for raw samples on stdout
+    #[arg(long)]
+    audio_output: Option<String>,
+
+    // Frequency of the buzzer in Hz
+    #[arg(long, default_value_t = 350.0)]
+    tone: f32,
+
+    // Volume of the buzzer in percent
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(0..=100))]
+    volume: u8,
+
+    // Shape of the buzzer's wave, either square, sine (default), triangle or noise
+    #[arg(long, default_value_t = WaveformArg::Sine)]
+    waveform: WaveformArg,
 
-    // Optional debug output file, to write all the instructions the processor runs through
-    #[arg(long, default_value_t = String::new())]
-    debug_file: String,
+    // Don't play any sound. Audio written with --audio-output is silent
+    #[arg(long, default_value_t = false)]
+    mute: bool,
+
+    // Config file to read the key map from
+    // Defaults to ~/.config/rust-chip8-opengl/config.toml, if it exists
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    // Bind a CHIP-8 key to keys on the keyboard, i.e. "5=w,up", overriding the config file
+    #[arg(long = "key")]
+    keys: Vec<String>,
+
+    // Optional file to write a trace of every instruction the processor runs through
+    #[arg(long, alias = "debug-file")]
+    trace: Option<String>,
+
+    // Format of the trace, either text (default) or json
+    #[arg(long, default_value_t = TraceFormatArg::Text)]
+    trace_format: TraceFormatArg,
+
+    // Only trace instructions in this range of addresses, i.e. "0x200-0x2FF"
+    #[arg(long = "trace-range", value_parser = parse_range_arg)]
+    trace_ranges: Vec<(usize, usize)>,
+
+    // Maximum number of instructions to trace
+    #[arg(long)]
+    trace_limit: Option<u64>,
 }
 
-#[allow(unreachable_code)]
-#[allow(unused_variables)]
+#[allow(clippy::needless_return)]
 fn main() {
     let args = Args::parse();
-    let mut p = Processor::new();
-    #[cfg(all(not(feature = "terminal"), not(feature = "open-gl")))]
-    panic!("No features enabled, enable one during compilation to use an interface");
-    let mut interface: Box<dyn Interface> = match args.mode {
+    match &args.command {
+        Some(Command::Disasm { file }) => {
+            let data = fs::read(file).unwrap();
+            print!("{}", disassemble(&data, 0x200));
+            return;
+        }
+        Some(Command::Asm { file, output }) => {
+            let source = fs::read_to_string(file).unwrap();
+            let rom = match assemble(&source) {
+                Ok(rom) => rom,
+                Err(e) => {
+                    eprintln!("{}: {}", file, e);
+                    std::process::exit(1);
+                }
+            };
+            let output = match output {
+                Some(o) => PathBuf::from(o),
+                None => Path::new(file).with_extension("ch8"),
+            };
+            fs::write(&output, rom).unwrap();
+            return;
+        }
+        Some(Command::TraceDiff {
+            traces,
+            rom,
+            left_variant,
+            right_variant,
+            left_quirks,
+            right_quirks,
+            seed,
+            steps,
+            ipf,
+        }) => {
+            let divergence = match rom {
+                Some(rom) => {
+                    let data = fs::read(rom).unwrap();
+                    let mut sides = [(left_variant, left_quirks), (right_variant, right_quirks)]
+                        .map(|(variant, quirks)| {
+                            let variant: Variant = (*variant).into();
+                            let quirks = match quirks {
+                                Some(q) => (*q).into(),
+                                None => Quirks::for_variant(variant),
+                            };
+                            let mut p = Processor::new_with_seed(variant, quirks, *seed);
+                            p.load_program(&data);
+                            return p;
+                        });
+                    let [left, right] = &mut sides;
+                    diff_processors(left, right, *steps, *ipf)
+                }
+                None => {
+                    let [left, right] = [&traces[0], &traces[1]].map(|path| {
+                        let text = fs::read_to_string(path).unwrap();
+                        return match parse_trace(&text) {
+                            Ok(t) => t,
+                            Err(e) => {
+                                eprintln!("{}: {}", path, e);
+                                std::process::exit(1);
+                            }
+                        };
+                    });
+                    diff_traces(&left, &right)
+                }
+            };
+            match divergence {
+                Some(d) => {
+                    print!("{}", d);
+                    std::process::exit(1);
+                }
+                None => println!("No differences found"),
+            }
+            return;
+        }
+        Some(Command::Dap) => {
+            let input = BufReader::new(io::stdin());
+            if let Err(e) = dap::serve(input, io::stdout()) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
+    // Required when there is no subcommand
+    let rom = args.file.clone().unwrap();
+    let data: Vec<u8> = fs::read(&rom).unwrap();
+    // FPS to render at
+    const FPS: u64 = 60;
+    let (mut p, mut movie) = match (&args.replay, &args.record) {
+        (Some(path), _) => {
+            let movie = match fs::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|d| Movie::from_bytes(&d).map_err(|e| e.to_string()))
+            {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("Could not load movie {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            };
+            match movie.create_processor(&data) {
+                Ok(p) => (p, Some(movie)),
+                Err(e) => {
+                    eprintln!("Could not replay movie {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        (None, record) => {
+            // Movies need to know the seed, so pick one if none was given
+            let seed = args.seed.or(record.as_ref().map(|_| rand::random()));
+            let quirks = build_quirks(&args);
+            let mut p = Processor::new_with_quirks(args.variant.into(), quirks);
+            if let Some(seed) = seed {
+                p.seed_rng(seed);
+            }
+            p.load_program(data.as_slice());
+            let movie = record.as_ref().map(|_| {
+                Movie::new(
+                    args.variant.into(),
+                    quirks,
+                    // Set from the emulator's speed once it is created
+                    args.ipf as u32,
+                    seed.unwrap(),
+                    &data,
+                )
+            });
+            (p, movie)
+        }
+    };
+    let replaying = args.replay.is_some();
+    let screenshot = build_screenshot(&args);
+
+    let tracer = args.trace.as_ref().map(|path| {
+        let file = match File::create(path) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("Could not create trace file {}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        let out: Box<dyn Write> = Box::new(BufWriter::new(file));
+        let mut tracer = Tracer::new(out, args.trace_format.into());
+        args.trace_ranges
+            .iter()
+            .for_each(|(start, end)| tracer.add_range(*start, *end));
+        if let Some(limit) = args.trace_limit {
+            tracer.set_limit(limit);
+        }
+        return tracer;
+    });
+    let mut recorder = args.record_video.as_ref().map(|path| {
+        let (out, format): (Box<dyn Write>, RecordingFormat) = if path == "-" {
+            (Box::new(io::stdout()), RecordingFormat::Raw)
+        } else {
+            match File::create(path) {
+                Ok(f) => (
+                    Box::new(BufWriter::new(f)),
+                    RecordingFormat::from_path(Path::new(path)),
+                ),
+                Err(e) => {
+                    eprintln!("Could not create recording {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        };
+        return match Recorder::for_processor(out, format, screenshot, &p) {
+            Ok(r) => r,
+            Err(e) => {
+                eprintln!("Could not start recording {}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+    });
+    let mut gdb = args.gdb.map(|port| match GdbServer::bind(port) {
+        Ok(g) => g,
+        Err(e) => {
+            eprintln!("Could not listen for GDB on port {}: {}", port, e);
+            std::process::exit(1);
+        }
+    });
+    if let Some(m) = movie.as_mut() {
+        next_movie_frame(m, &mut p, 0, replaying);
+    }
+
+    let tone = Tone {
+        frequency: args.tone.max(1.0),
+        volume: if args.mute {
+            0.0
+        } else {
+            args.volume as f32 / 100.0
+        },
+        waveform: args.waveform.into(),
+    };
+    let audio: Box<dyn AudioBackend> = match &args.audio_output {
+        Some(path) if path == "-" => Box::new(FileBackend::new(io::stdout(), tone)),
+        Some(path) => {
+            let is_wav = Path::new(path)
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+            let audio = File::create(path).and_then(|f| -> io::Result<Box<dyn AudioBackend>> {
+                let out = BufWriter::new(f);
+                return match is_wav {
+                    true => Ok(Box::new(WavBackend::new(out, tone)?)),
+                    false => Ok(Box::new(FileBackend::new(out, tone))),
+                };
+            });
+            match audio {
+                Ok(a) => a,
+                Err(e) => {
+                    eprintln!("Could not create audio output {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        // Headless runs are silent unless asked otherwise
+        None if args.mode == Mode::Headless || args.mute => Box::new(NullBackend),
+        None => audio::open_default(tone),
+    };
+    let interface: Box<dyn Interface> = match args.mode {
         #[cfg(not(feature = "terminal"))]
         Mode::Terminal => panic!("'terminal' feature needs to be enabled to use TerminalInterface"),
         #[cfg(feature = "terminal")]
-        Mode::Terminal => Box::new(TerminalInterface::new()),
+        Mode::Terminal => Box::new(TerminalInterface::new(audio, build_keymap(&args))),
         #[cfg(not(feature = "open-gl"))]
         Mode::OpenGl => panic!("'open-gl' feature needs to be enabled to use OpenGlInterface"),
         #[cfg(feature = "open-gl")]
-        Mode::OpenGl => Box::new(OpenGlInterface::new()),
+        Mode::OpenGl => Box::new(OpenGlInterface::new(audio, build_keymap(&args))),
+        Mode::Headless => {
+            let mut headless = HeadlessInterface::new();
+            if let Some(frames) = args.frames.or(args.hash_frames.iter().max().copied()) {
+                headless.set_max_frames(frames);
+            }
+            args.hash_frames
+                .iter()
+                .for_each(|f| headless.add_hash_frame(*f));
+            headless.set_print_state_hash(args.hash_state);
+            args.until.iter().for_each(|c| headless.add_condition(*c));
+            headless.set_stop_on_halt(args.until_halt);
+            if let Some(path) = &args.screen_output {
+                headless.set_screen_output(path);
+            }
+            headless.set_screenshot(screenshot);
+            headless.set_audio(audio);
+            Box::new(headless)
+        }
     };
+    let mut emulator = Emulator::new(p, interface);
+    // Headless runs as fast as possible
+    emulator.set_paced(args.mode != Mode::Headless);
+    emulator.set_frame_rate(FPS);
+    match (&mut movie, replaying) {
+        // Movies replay at the speed they were recorded at
+        (Some(m), true) => emulator.set_instructions_per_frame(m.instructions_per_frame as u64),
+        (m, _) => {
+            match args.ips {
+                Some(ips) => emulator.set_instructions_per_second(ips),
+                None => emulator.set_instructions_per_frame(args.ipf),
+            }
+            if let Some(m) = m {
+                m.instructions_per_frame = emulator.get_instructions_per_frame() as u32;
+            }
+        }
+    }
+    if let Some(t) = tracer {
+        emulator.set_tracer(t);
+    }
+    let debugger = emulator.get_debugger_mut();
+    args.breakpoints
+        .iter()
+        .for_each(|a| debugger.add_breakpoint(*a));
+    args.watchpoints
+        .iter()
+        .for_each(|a| debugger.add_watchpoint(*a));
+    args.conditions
+        .iter()
+        .for_each(|c| debugger.add_condition(*c));
+    if let Some(g) = &gdb {
+        emulator.get_debugger_mut().pause();
+        emulator
+            .get_interface_mut()
+            .show_message(&format!("Waiting for GDB on port {}", g.get_port()));
+    } else if args.paused {
+        let (p, debugger, interface) = emulator.split_mut();
+        debugger.pause();
+        interface.show_message(&StopReason::Pause.to_string());
+        interface.show_state(&format_state(p));
+    }
 
-    let mut file: Option<File> = None;
-    if !args.debug_file.is_empty() {
-        file = Some(
-            OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .open(args.debug_file.clone())
-                .unwrap(),
-        );
-        writeln!(file.as_ref().unwrap(), "BEGINNING OF OPCODE RECORD:").unwrap();
-    }
-    let data: Vec<u8> = fs::read(args.file.clone()).unwrap();
-    p.load_program(data.as_slice());
-    let mut dt = Instant::now();
-    let mut rt = Instant::now();
-    let mut ct: Instant = Instant::now();
-    let mut last_pc: usize = 0x0000;
-
-    // Clock speed in Hz
-    let clock_speed = 500;
-    loop {
-        if !args.debug_file.is_empty() {
-            let pc: usize = p.get_program_counter();
-            let inst = ((p.get_mem_at(pc) as u16) << 8) + p.get_mem_at(pc + 1) as u16;
-            // Don't write the exact same instruction multiple times in a row
-            if pc != last_pc {
-                let to_write = format!("{:#6X}", inst);
-                writeln!(
-                    file.as_ref().unwrap(),
-                    "{:#5X} {}",
-                    pc,
-                    to_write[2..].to_string()
-                )
-                .unwrap();
-                last_pc = pc;
+    // Save state slot currently selected
+    let mut slot: u8 = 0;
+    let interval = args.rewind_interval.max(1);
+    let mut rewind = RewindBuffer::new(
+        interval,
+        (args.rewind_seconds.saturating_mul(60) / interval) as usize,
+        args.rewind_memory.saturating_mul(1024),
+    );
+    let mut rewinding = false;
+    let mut frames = 0;
+    let mut shown_speed = None;
+    while !emulator.is_finished() {
+        if let Some(g) = gdb.as_mut() {
+            let (p, debugger, interface) = emulator.split_mut();
+            if let Err(e) = g.poll(p, debugger) {
+                interface.show_message(&format!("GDB connection error: {}", e));
             }
         }
-        match p.step() {
-            Ok(()) => {}
-            Err(e) => panic!("{}", e),
+        if rewinding {
+            // Go back one snapshot each frame while rewinding
+            if let Err(e) = rewind.rewind(emulator.get_processor_mut()) {
+                emulator
+                    .get_interface_mut()
+                    .show_message(&format!("Could not rewind: {}", e));
+                rewinding = false;
+            }
+            emulator.idle_frame();
+        } else if let Some(reason) = emulator.run_frame() {
+            let gdb_connected = gdb.as_ref().is_some_and(|g| g.is_connected());
+            match reason {
+                // Let GDB inspect errors instead of exiting, if it is connected
+                StopReason::Error(e) if !gdb_connected => {
+                    emulator.exit();
+                    save_movie(&args.record, &movie);
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                _ => {
+                    if let Some(g) = gdb.as_mut() {
+                        g.on_stop(&reason).ok();
+                    }
+                    let (p, _, interface) = emulator.split_mut();
+                    interface.show_message(&reason.to_string());
+                    interface.show_state(&format_state(p));
+                }
+            }
+        }
+        // Snapshot each frame, and move the movie on, once all of its instructions have run
+        if emulator.get_frames() != frames {
+            frames = emulator.get_frames();
+            rewind.on_frame(emulator.get_processor());
+            if let Some(m) = movie.as_mut() {
+                if !next_movie_frame(m, emulator.get_processor_mut(), frames as usize, replaying) {
+                    movie = None;
+                    emulator.get_interface_mut().show_message("Replay finished");
+                }
+            }
+        }
+        if args.show_speed {
+            if let Some(speed) = emulator.get_scheduler().get_speed() {
+                if shown_speed != Some(speed) {
+                    shown_speed = Some(speed);
+                    emulator
+                        .get_interface_mut()
+                        .show_message(&speed.to_string());
+                }
+            }
+        }
+        if let Some(r) = recorder.as_mut() {
+            if let Err(e) = r.capture(emulator.get_processor()) {
+                emulator
+                    .get_interface_mut()
+                    .show_message(&format!("Stopped recording: {}", e));
+                recorder = None;
+            }
         }
 
-        if interface.update(&mut p) {
-            break;
+        let hotkeys = emulator.get_interface_mut().take_hotkeys();
+        let (p, debugger, interface) = emulator.split_mut();
+        for hotkey in hotkeys {
+            let message = match hotkey {
+                // Movies can only be replayed if the run is never interrupted
+                Hotkey::LoadState | Hotkey::StartRewind if movie.is_some() => {
+                    "Not available while recording or replaying a movie".to_string()
+                }
+                Hotkey::StartRewind => {
+                    rewinding = true;
+                    "Rewinding".to_string()
+                }
+                Hotkey::StopRewind => {
+                    rewinding = false;
+                    String::new()
+                }
+                Hotkey::TogglePause if debugger.is_paused() => {
+                    debugger.resume();
+                    interface.show_state("");
+                    "Resumed".to_string()
+                }
+                Hotkey::TogglePause => {
+                    debugger.pause();
+                    if let Some(g) = gdb.as_mut() {
+                        g.on_stop(&StopReason::Pause).ok();
+                    }
+                    interface.show_state(&format_state(p));
+                    StopReason::Pause.to_string()
+                }
+                // Stepping is only possible while paused
+                Hotkey::StepInto | Hotkey::StepOver | Hotkey::StepOut if !debugger.is_paused() => {
+                    continue;
+                }
+                Hotkey::StepInto => {
+                    debugger.step_into();
+                    continue;
+                }
+                Hotkey::StepOver => {
+                    debugger.step_over(p);
+                    continue;
+                }
+                Hotkey::StepOut => {
+                    debugger.step_out(p);
+                    continue;
+                }
+                Hotkey::Screenshot => {
+                    let format = args.screenshot_format.into();
+                    let path = next_screenshot_path(&rom, format);
+                    match fs::write(&path, screenshot.encode(p, format)) {
+                        Ok(()) => format!("Saved screenshot to {}", path),
+                        Err(e) => format!("Could not save screenshot to {}: {}", path, e),
+                    }
+                }
+                _ => handle_hotkey(hotkey, p, &rom, &mut slot),
+            };
+            interface.show_message(&message);
         }
+        emulator.wait_for_next_frame();
+    }
+    emulator.exit();
+    if let Some(Err(e)) = recorder.map(|r| r.finish()) {
+        eprintln!("Could not finish recording: {}", e);
+    }
+    save_movie(&args.record, &movie);
+    std::process::exit(emulator.get_interface_mut().get_exit_code());
+}
 
-        // Update clock
-        if dt.elapsed().as_millis() >= 1000 / 60 {
-            p.on_tick();
-            dt = Instant::now();
+// Build the quirks from the preset and any individual overrides given
+#[allow(clippy::needless_return)]
+fn build_quirks(args: &Args) -> Quirks {
+    let mut quirks = match args.quirks {
+        Some(preset) => preset.into(),
+        None => Quirks::for_variant(args.variant.into()),
+    };
+    let overrides = [
+        (args.shift_uses_vy, &mut quirks.shift_uses_vy),
+        (
+            args.load_store_increments_i,
+            &mut quirks.load_store_increments_i,
+        ),
+        (args.logic_resets_vf, &mut quirks.logic_resets_vf),
+        (args.clip_sprites, &mut quirks.clip_sprites),
+        (args.display_wait, &mut quirks.display_wait),
+        (args.jump_uses_vx, &mut quirks.jump_uses_vx),
+    ];
+    for (arg, quirk) in overrides {
+        if let Some(v) = arg {
+            *quirk = v;
+        }
+    }
+    return quirks;
+}
+
+// Set the inputs for the next frame of a movie, either recording the keys currently pressed or
+// reading them from the movie being replayed. Returns false once a replayed movie has ended
+#[allow(clippy::needless_return)]
+fn next_movie_frame(movie: &mut Movie, p: &mut Processor, frame: usize, replaying: bool) -> bool {
+    let inputs = if replaying {
+        match movie.get_frame(frame) {
+            Some(inputs) => inputs,
+            None => {
+                p.clear_input_override();
+                return false;
+            }
         }
+    } else {
+        let inputs = p.get_requested_inputs();
+        movie.add_frame(inputs);
+        inputs
+    };
+    // Keys pressed part way through a frame are only seen at the start of the next one
+    p.override_inputs(inputs);
+    return true;
+}
 
-        // FPS to render at
-        const FPS: u128 = 60;
-        if rt.elapsed().as_millis() >= 1000 / FPS {
-            interface.render(&p);
-            p.on_v_blank();
-            rt = Instant::now();
+// Write the movie being recorded, if there is one
+fn save_movie(path: &Option<String>, movie: &Option<Movie>) {
+    if let (Some(path), Some(movie)) = (path, movie) {
+        if let Err(e) = fs::write(path, movie.to_bytes()) {
+            eprintln!("Could not save movie to {}: {}", path, e);
         }
-        thread::sleep(Duration::from_millis(1000 / clock_speed).saturating_sub(ct.elapsed()));
-        ct = Instant::now();
     }
-    interface.exit();
+}
+
+// Read the key map from the config file, if there is one, then apply the bindings given on the command line
+#[cfg(any(feature = "terminal", feature = "open-gl"))]
+fn build_keymap(args: &Args) -> KeyMap {
+    let path = args
+        .config
+        .clone()
+        .or(config::default_path().filter(|p| p.exists()));
+    let mut keymap = match path {
+        Some(path) => match Config::load(&path, args.file.as_deref().map(Path::new)) {
+            Ok(c) => c.keymap,
+            Err(e) => {
+                eprintln!("Could not load config {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => KeyMap::new(),
+    };
+    for binding in &args.keys {
+        if let Err(e) = keymap.apply(binding) {
+            eprintln!("Invalid key binding: {}", e);
+            std::process::exit(1);
+        }
+    }
+    return keymap;
+}
+
+// Build the screenshot settings from the arguments
+#[allow(clippy::needless_return)]
+fn build_screenshot(args: &Args) -> Screenshot {
+    let mut screenshot = Screenshot::new();
+    if let Some(color) = args.screenshot_fg {
+        screenshot.set_foreground(color);
+    }
+    if let Some(color) = args.screenshot_bg {
+        screenshot.set_background(color);
+    }
+    screenshot.scale = args.screenshot_scale.max(1);
+    return screenshot;
+}
+
+// Get the first unused screenshot path next to the ROM, i.e. my_game.ch8.screenshot0.png
+#[allow(clippy::needless_return)]
+fn next_screenshot_path(rom: &str, format: ScreenshotFormat) -> String {
+    return (0..)
+        .map(|n| format!("{}.screenshot{}.{}", rom, n, format.get_extension()))
+        .find(|path| !Path::new(path).exists())
+        .unwrap();
+}
+
+// Perform the action for a hotkey, returning a message to show the user
+#[allow(clippy::needless_return)]
+fn handle_hotkey(hotkey: Hotkey, p: &mut Processor, rom: &str, slot: &mut u8) -> String {
+    // Save states are stored next to the ROM
+    let path = format!("{}.state{}", rom, slot);
+    return match hotkey {
+        Hotkey::SaveState => match fs::write(&path, p.save_state()) {
+            Ok(()) => format!("Saved state to slot {}", slot),
+            Err(e) => format!("Could not save state to {}: {}", path, e),
+        },
+        Hotkey::LoadState => match fs::read(&path) {
+            Ok(data) => match p.load_state(&data) {
+                Ok(()) => format!("Loaded state from slot {}", slot),
+                Err(e) => format!("Could not load state from {}: {}", path, e),
+            },
+            Err(e) => format!("Could not read {}: {}", path, e),
+        },
+        Hotkey::NextSlot => {
+            *slot = (*slot + 1) % 10;
+            format!("Selected slot {}", slot)
+        }
+        _ => String::new(),
+    };
+}
+
+#[allow(clippy::needless_return)]
+fn parse_address_arg(s: &str) -> Result<usize, String> {
+    return parse_address(s).ok_or(format!("Invalid address '{}'", s));
+}
+
+#[allow(clippy::needless_return)]
+fn parse_color_arg(s: &str) -> Result<[u8; 3], String> {
+    return parse_color(s).ok_or(format!("Invalid color '{}', expected #RRGGBB", s));
+}
+
+// Parse a range of addresses such as "0x200-0x2FF"
+#[allow(clippy::needless_return)]
+fn parse_range_arg(s: &str) -> Result<(usize, usize), String> {
+    let (start, end) = s
+        .split_once('-')
+        .ok_or(format!("Invalid range '{}', expected START-END", s))?;
+    return Ok((parse_address_arg(start)?, parse_address_arg(end)?));
 }
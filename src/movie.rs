@@ -0,0 +1,185 @@
+#![allow(clippy::needless_return)]
+use crate::errors::{EmulatorError, MovieError};
+use crate::hash::Fnv64;
+use crate::processor::{Processor, Variant};
+use crate::quirks::Quirks;
+
+// Every movie starts with this
+const MAGIC: &[u8; 4] = b"C8MV";
+// Bump whenever the layout below changes
+const VERSION: u8 = 1;
+// Size of everything before the frames
+const HEADER_SIZE: usize = 31;
+
+/*
+ * Movie layout, all multi byte values are little endian:
+ * magic (4), version (1), variant (1), quirks (1, one bit each), instructions per frame (4),
+ * seed (8), ROM hash (8), frame count (4), then the inputs of each frame (2, one bit per key)
+ */
+
+/**
+ * A recording of the keys pressed on every frame of a run, along with everything else needed
+ * to replay it exactly: the variant, quirks, random seed and a hash of the ROM.
+ *
+ * Replays are only exact if the processor runs the same number of instructions each frame,
+ * and the inputs only change between frames.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub variant: Variant,
+    pub quirks: Quirks,
+    /// Number of instructions run between each frame
+    pub instructions_per_frame: u32,
+    /// Seed of the random number generator used by CXNN
+    pub seed: u64,
+    /// Hash of the ROM the movie was recorded with, from `hash_rom`
+    pub rom_hash: u64,
+    // Inputs of each frame, one bit per key
+    frames: Vec<u16>,
+}
+
+impl Movie {
+    /// Start an empty movie for a run of the ROM given
+    pub fn new(
+        variant: Variant,
+        quirks: Quirks,
+        instructions_per_frame: u32,
+        seed: u64,
+        rom: &[u8],
+    ) -> Movie {
+        return Movie {
+            variant,
+            quirks,
+            instructions_per_frame: instructions_per_frame.max(1),
+            seed,
+            rom_hash: hash_rom(rom),
+            frames: Vec::new(),
+        };
+    }
+    /**
+     * Create a processor set up the same way as the one the movie was recorded with,
+     * with the ROM given loaded. Fails if the ROM is not the one the movie was recorded with.
+     */
+    pub fn create_processor(&self, rom: &[u8]) -> Result<Processor, MovieError> {
+        let hash = hash_rom(rom);
+        if hash != self.rom_hash {
+            return Err(MovieError::RomMismatch(self.rom_hash));
+        }
+        let mut p = Processor::new_with_seed(self.variant, self.quirks, self.seed);
+        p.load_program(rom);
+        return Ok(p);
+    }
+    /// Add a frame to the end of the movie, with the inputs given
+    pub fn add_frame(&mut self, inputs: [bool; 0x10]) {
+        let bits = inputs
+            .iter()
+            .enumerate()
+            .fold(0u16, |acc, (i, v)| acc | ((*v as u16) << i));
+        self.frames.push(bits);
+    }
+    /// Get the inputs of a frame, or `None` if the movie has ended
+    pub fn get_frame(&self, frame: usize) -> Option<[bool; 0x10]> {
+        let bits = *self.frames.get(frame)?;
+        return Some(std::array::from_fn(|i| (bits >> i) & 0x1 == 1));
+    }
+    /// Get the number of frames in the movie
+    pub fn get_frame_count(&self) -> usize {
+        return self.frames.len();
+    }
+    /**
+     * Replay the whole movie on the processor without an interface, stopping early if the program
+     * exits or hits an error. The processor should come from `create_processor`.
+     * Timers are updated and the vertical blank happens at the end of each frame.
+     */
+    pub fn replay(&self, p: &mut Processor) -> Result<(), EmulatorError> {
+        for frame in 0..self.frames.len() {
+            p.override_inputs(self.get_frame(frame).unwrap());
+            for _ in 0..self.instructions_per_frame {
+                p.step()?;
+                if p.has_exited() {
+                    return Ok(());
+                }
+            }
+            p.on_tick();
+            p.on_v_blank();
+        }
+        return Ok(());
+    }
+    /**
+     * Serialize the movie into a versioned binary blob.
+     * The movie can be loaded again using `from_bytes`.
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + self.frames.len() * 2);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(match self.variant {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip => 2,
+        });
+        out.push(self.quirks.to_bits());
+        out.extend_from_slice(&self.instructions_per_frame.to_le_bytes());
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        self.frames
+            .iter()
+            .for_each(|f| out.extend_from_slice(&f.to_le_bytes()));
+        return out;
+    }
+    /**
+     * Load a movie from a blob created by `to_bytes`.
+     */
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(MovieError::InvalidHeader);
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(MovieError::Truncated);
+        }
+        if data[4] != VERSION {
+            return Err(MovieError::UnsupportedVersion(data[4]));
+        }
+        let variant = match data[5] {
+            0 => Variant::Chip8,
+            1 => Variant::SuperChip,
+            2 => Variant::XoChip,
+            _ => return Err(MovieError::Corrupt("variant")),
+        };
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..(i + 4)].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(data[i..(i + 8)].try_into().unwrap());
+        let instructions_per_frame = u32_at(7);
+        if instructions_per_frame == 0 {
+            return Err(MovieError::Corrupt("instructions per frame"));
+        }
+        let frame_count = u32_at(27) as usize;
+        let frames = &data[HEADER_SIZE..];
+        if frames.len() < frame_count * 2 {
+            return Err(MovieError::Truncated);
+        }
+        if frames.len() > frame_count * 2 {
+            return Err(MovieError::Corrupt("length"));
+        }
+        return Ok(Movie {
+            variant,
+            quirks: Quirks::from_bits(data[6]),
+            instructions_per_frame,
+            seed: u64_at(11),
+            rom_hash: u64_at(19),
+            frames: frames
+                .chunks(2)
+                .map(|f| u16::from_le_bytes([f[0], f[1]]))
+                .collect(),
+        });
+    }
+}
+
+/**
+ * Hash a ROM using 64 bit FNV-1a, to check a movie is replayed with the ROM it was recorded with.
+ */
+pub fn hash_rom(rom: &[u8]) -> u64 {
+    let mut hash = Fnv64::new();
+    hash.write(rom);
+    return hash.finish();
+}
@@ -1,8 +1,32 @@
-use crate::errors::OpcodeError;
-use rand::Rng;
+#![allow(clippy::needless_return)]
+use crate::errors::EmulatorError;
+use crate::instruction::Instruction;
+use crate::quirks::Quirks;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+mod state;
 
 const SCREEN_WIDTH: usize = 64;
 const SCREEN_HEIGHT: usize = 32;
+// SUPER-CHIP high resolution mode
+const HIRES_SCREEN_WIDTH: usize = 128;
+const HIRES_SCREEN_HEIGHT: usize = 64;
+// Where the big (SUPER-CHIP) font is stored in memory, right after the small one
+const BIG_SPRITES_ADDR: usize = 0x60;
+// Memory sizes, XO-CHIP extends it to 64KiB
+const MEM_SIZE: usize = 0x1000;
+const XO_CHIP_MEM_SIZE: usize = 0x10000;
+
+/// The CHIP-8 variants the processor can emulate
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Variant {
+    /// The original CHIP-8 instruction set
+    Chip8,
+    /// SUPER-CHIP 1.1, adds a 128x64 high resolution mode, scrolling and 16x16 sprites
+    SuperChip,
+    /// XO-CHIP, adds 64KiB of memory, a second bitplane and audio patterns on top of SUPER-CHIP
+    XoChip,
+}
 
 /// The sprites for the digits 0-F, as bytes
 pub const SPRITES: [[u8; 5]; 16] = [
@@ -24,14 +48,52 @@ pub const SPRITES: [[u8; 5]; 16] = [
     [0xF0, 0x80, 0xF0, 0x80, 0x80],
 ];
 
+/// The SUPER-CHIP big sprites for the digits 0-F, as bytes
+pub const BIG_SPRITES: [[u8; 10]; 16] = [
+    [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C],
+    [0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C],
+    [0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF],
+    [0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C],
+    [0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06],
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C],
+    [0x3E, 0x7C, 0xC0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C],
+    [0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18],
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C],
+    [0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C],
+    [0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3],
+    [0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC],
+    [0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C],
+    [0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC],
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0],
+];
+
 /**
  * The actual CHIP-8 processor.
  * Decodes and runs any opcodes, stores memory, stores screen.
  * Needs to be paired with an interface to allow the user to actually interact with the program.
  */
 pub struct Processor {
-    // Buffer for the screen
-    screen_buffer: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // Which variant of CHIP-8 is being emulated
+    variant: Variant,
+    // Behaviours that differ between implementations
+    quirks: Quirks,
+    // Buffer for the screen, sized for the current resolution
+    // Each pixel stores which bitplanes are set, bit 0 is plane 1 and bit 1 is plane 2
+    screen_buffer: Vec<u8>,
+    // XO-CHIP bitplanes selected for drawing, scrolling and clearing
+    planes: u8,
+    // Whether the SUPER-CHIP high resolution mode is enabled
+    hires: bool,
+    // Set once the program has executed the SUPER-CHIP exit instruction
+    exited: bool,
+    // SUPER-CHIP RPL user flags, used by FX75 and FX85
+    // SUPER-CHIP only has 8, XO-CHIP extends it to 16
+    rpl_flags: [u8; 0x10],
+    // XO-CHIP audio pattern buffer, set by F002
+    audio_pattern: Option<[u8; 16]>,
+    // XO-CHIP audio pitch register, set by FX3A
+    pitch: u8,
     // Registers (1 through F)
     registers: [u8; 0x10],
     // Program Counter
@@ -40,15 +102,21 @@ pub struct Processor {
     stack: [u16; 0x10],
     sp: usize,
     // Memory
-    mem: [u8; 0x1000],
+    mem: Vec<u8>,
     // I (index register)
     i: u16,
     // Delay timer
     dt: u8,
     // Sound timer
     st: u8,
+    // Number of times the timers have been updated, which isn't part of the state
+    ticks: u64,
     // Keys that are currently pressed
     input_state: [bool; 0x10],
+    // Inputs last given to update_inputs, kept while they are overridden
+    requested_inputs: [bool; 0x10],
+    // Whether update_inputs is ignored because the inputs have been overridden
+    inputs_overridden: bool,
     // debug print mode used in tests
     debug_print: bool,
     // Key that was just released, should be set by the interface
@@ -57,26 +125,82 @@ pub struct Processor {
     last_key_released: Option<u8>,
 
     vblank: bool,
+    // Opcode currently being executed, used for errors
+    opcode: u16,
+    // Random number generator used by CXNN
+    rng: Box<dyn RngCore + Send>,
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Processor::new()
+    }
 }
 
 impl Processor {
     pub fn new() -> Processor {
+        Processor::new_with_variant(Variant::Chip8)
+    }
+    /**
+     * Create a processor emulating the given variant of CHIP-8.
+     * Uses the quirks usually expected by ROMs written for that variant.
+     */
+    pub fn new_with_variant(variant: Variant) -> Processor {
+        Processor::new_with_quirks(variant, Quirks::for_variant(variant))
+    }
+    /**
+     * Create a processor whose random number generator is seeded with `seed`,
+     * so that runs using CXNN can be reproduced.
+     */
+    pub fn new_with_seed(variant: Variant, quirks: Quirks, seed: u64) -> Processor {
+        let mut p = Processor::new_with_quirks(variant, quirks);
+        p.seed_rng(seed);
+        return p;
+    }
+    /**
+     * Create a processor emulating the given variant of CHIP-8 with the given quirks.
+     */
+    pub fn new_with_quirks(variant: Variant, quirks: Quirks) -> Processor {
         let mut c = Processor {
-            screen_buffer: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            variant,
+            quirks,
+            screen_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            planes: 0x1,
+            hires: false,
+            exited: false,
+            rpl_flags: [0x00; 0x10],
+            audio_pattern: None,
+            pitch: 64,
             registers: [0x00; 0x10],
             pc: 0x200,
             stack: [0x00; 0x10],
             sp: 0,
-            mem: [0x00; 0x1000],
+            mem: vec![
+                0x00;
+                if variant == Variant::XoChip {
+                    XO_CHIP_MEM_SIZE
+                } else {
+                    MEM_SIZE
+                }
+            ],
             i: 0,
             dt: 0,
             st: 0,
             input_state: [false; 0x10],
+            requested_inputs: [false; 0x10],
+            inputs_overridden: false,
             debug_print: false,
             last_key_released: None,
             vblank: false,
+            ticks: 0,
+            opcode: 0,
+            rng: Box::new(StdRng::from_entropy()),
         };
         (0..0x10).for_each(|i| c.mem[(6 * i)..(6 * i + 5)].copy_from_slice(&SPRITES[i]));
+        (0..0x10).for_each(|i| {
+            let addr = BIG_SPRITES_ADDR + 10 * i;
+            c.mem[addr..(addr + 10)].copy_from_slice(&BIG_SPRITES[i]);
+        });
 
         return c;
     }
@@ -104,9 +228,13 @@ impl Processor {
      * Perform the next step in whatever program has been loaded into memory.
      * Equivalent to just calling `execute` and incrementing `PC` by 2
      **/
-    pub fn step(&mut self) -> Result<(), OpcodeError> {
-        let r = self.execute(((self.mem[self.pc] as u16) << 8) | self.mem[self.pc + 1] as u16);
-        self.pc += 2;
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        // The PC can be set to anything, so don't add to it here in case it overflows
+        if self.pc >= self.mem.len() - 1 {
+            return Err(EmulatorError::PcOutOfBounds { pc: self.pc });
+        }
+        let r = self.execute(self.get_word_at(self.pc));
+        self.pc = self.pc.wrapping_add(2);
         r
     }
     /**
@@ -116,11 +244,38 @@ impl Processor {
     pub fn on_tick(&mut self) {
         self.dt = self.dt.saturating_sub(1);
         self.st = self.st.saturating_sub(1);
+        self.ticks += 1;
     }
     /**
      * Update the current input states to the inputs given.
      **/
     pub fn update_inputs(&mut self, inputs: [bool; 0x10]) {
+        self.requested_inputs = inputs;
+        if !self.inputs_overridden {
+            self.set_inputs(inputs);
+        }
+    }
+    /**
+     * Replace the inputs with the ones given, i.e. when replaying a movie.
+     * Calls to `update_inputs` have no effect until `clear_input_override` is called.
+     */
+    pub fn override_inputs(&mut self, inputs: [bool; 0x10]) {
+        self.inputs_overridden = true;
+        self.set_inputs(inputs);
+    }
+    /**
+     * Stop overriding the inputs, going back to the ones last given to `update_inputs`.
+     */
+    pub fn clear_input_override(&mut self) {
+        self.inputs_overridden = false;
+        self.set_inputs(self.requested_inputs);
+    }
+    /// Get the inputs last given to `update_inputs`, even if they are overridden
+    pub fn get_requested_inputs(&self) -> [bool; 0x10] {
+        return self.requested_inputs;
+    }
+    // Update the current input states, keeping track of which key was released
+    fn set_inputs(&mut self, inputs: [bool; 0x10]) {
         inputs.iter().enumerate().for_each(|(i, v)| {
             if !v && self.input_state[i] {
                 self.last_key_released = Some(i as u8);
@@ -132,12 +287,13 @@ impl Processor {
      * Executes a single given instruction.
      * Does not increment PC or affect DT or ST.
      **/
-    pub fn execute(&mut self, inst: u16) -> Result<(), OpcodeError> {
+    pub fn execute(&mut self, inst: u16) -> Result<(), EmulatorError> {
         if self.debug_print {
             println!("Inst is {:X}", inst);
             println!("Initial state:");
             self.dump_state();
         }
+        self.opcode = inst;
         /*
          * Match opcode
          * Naming convention for all these functions
@@ -150,68 +306,65 @@ impl Processor {
          *       addr: Address
          *       kp: Key press
          */
-        match inst & 0xF000 {
-            0x0000 => match inst & 0x0FFF {
-                0x0E0 => self.clr(),
-                0x0EE => self.ret(),
-                _ => {}
-            },
-            0x1000 => self.jmp(inst & 0x0FFF),
-            0x2000 => self.call(inst & 0xFFF),
-            0x3000 => self.se_r_kk(reg_at(inst, 1), (inst & 0xFF) as u8),
-            0x4000 => self.sne_r_kk(inst),
-            0x5000 => {
-                if inst & 0xF != 0 {
-                    return Err(OpcodeError::new(inst, self.pc as u8));
-                }
-                self.se_rx_ry(reg_at(inst, 1), reg_at(inst, 2));
-            }
-            0x6000 => self.ld_r_kk(reg_at(inst, 1), (inst & 0xFF) as u8),
-            0x7000 => self.add_r_kk(reg_at(inst, 1), (inst & 0xFF) as u8),
-            0x8000 => {
-                let rx = reg_at(inst, 1);
-                let ry = reg_at(inst, 2);
-                match inst & 0x000F {
-                    0 => self.ld_rx_ry(rx, ry),
-                    1 => self.or_rx_ry(rx, ry),
-                    2 => self.and_rx_ry(rx, ry),
-                    3 => self.xor_rx_ry(rx, ry),
-                    4 => self.add_rx_ry(rx, ry),
-                    5 => self.sub_rx_ry(rx, ry),
-                    6 => self.shr(rx, ry),
-                    7 => self.subn(rx, ry),
-                    0xE => self.shl(rx, ry),
-                    _ => return Err(OpcodeError::new(inst, self.pc as u8)),
-                }
-            }
-            0x9000 => {
-                if inst & 0xF != 0 {
-                    return Err(OpcodeError::new(inst, self.pc as u8));
-                }
-                self.sne_rx_ry(reg_at(inst, 1), reg_at(inst, 2));
-            }
-            0xA000 => self.ld_i(inst & 0x0FFF),
-            0xB000 => self.jmp_r0(inst & 0x0FFF),
-            0xC000 => self.rand(reg_at(inst, 1), inst & 0xFF),
-            0xD000 => self.draw(reg_at(inst, 1), reg_at(inst, 2), reg_at(inst, 3)),
-            0xE000 => match inst & 0x00FF {
-                0x9E => self.skp_r(reg_at(inst, 1)),
-                0xA1 => self.sknp_r(reg_at(inst, 1)),
-                _ => return Err(OpcodeError::new(inst, self.pc as u8)),
-            },
-            0xF000 => match inst & 0x00FF {
-                0x07 => self.ld_r_dt(reg_at(inst, 1)),
-                0x0A => self.ld_r_kp(reg_at(inst, 1)),
-                0x15 => self.ld_dt_r(reg_at(inst, 1)),
-                0x18 => self.ld_st_r(reg_at(inst, 1)),
-                0x1E => self.add_i_r(reg_at(inst, 1)),
-                0x29 => self.ld_i_spr_x(reg_at(inst, 1)),
-                0x33 => self.ld_bcd_r(reg_at(inst, 1)),
-                0x55 => self.store_at_i(reg_at(inst, 1)),
-                0x65 => self.load_from_i(reg_at(inst, 1)),
-                _ => return Err(OpcodeError::new(inst, self.pc as u8)),
-            },
-            _ => return Err(OpcodeError::new(inst, self.pc as u8)),
+        let instruction = match Instruction::decode(inst) {
+            Ok(i) if i.is_supported_by(self.variant) => i,
+            // Machine code routines, as well as 00NN instructions from other variants, are ignored
+            _ if inst & 0xF000 == 0 => Instruction::Sys(inst & 0xFFF),
+            _ => return Err(self.invalid_opcode()),
+        };
+        let r = |x: u8| x as usize;
+        match instruction {
+            Instruction::Sys(_) => {}
+            Instruction::Cls => self.clr(),
+            Instruction::Ret => self.ret()?,
+            Instruction::ScrollDown(n) => self.scroll(0, n as isize),
+            Instruction::ScrollUp(n) => self.scroll(0, -(n as isize)),
+            Instruction::ScrollRight => self.scroll(4, 0),
+            Instruction::ScrollLeft => self.scroll(-4, 0),
+            Instruction::Exit => self.exit(),
+            Instruction::Lores => self.set_hires(false),
+            Instruction::Hires => self.set_hires(true),
+            Instruction::Jp(addr) => self.jmp(addr),
+            Instruction::Call(addr) => self.call(addr)?,
+            Instruction::SeByte(x, kk) => self.se_r_kk(r(x), kk),
+            Instruction::SneByte(x, kk) => self.sne_r_kk(r(x), kk),
+            Instruction::SeReg(x, y) => self.se_rx_ry(r(x), r(y)),
+            Instruction::SaveRange(x, y) => self.store_rx_ry_at_i(r(x), r(y))?,
+            Instruction::LoadRange(x, y) => self.load_rx_ry_from_i(r(x), r(y))?,
+            Instruction::LdByte(x, kk) => self.ld_r_kk(r(x), kk),
+            Instruction::AddByte(x, kk) => self.add_r_kk(r(x), kk),
+            Instruction::LdReg(x, y) => self.ld_rx_ry(r(x), r(y)),
+            Instruction::Or(x, y) => self.or_rx_ry(r(x), r(y)),
+            Instruction::And(x, y) => self.and_rx_ry(r(x), r(y)),
+            Instruction::Xor(x, y) => self.xor_rx_ry(r(x), r(y)),
+            Instruction::AddReg(x, y) => self.add_rx_ry(r(x), r(y)),
+            Instruction::Sub(x, y) => self.sub_rx_ry(r(x), r(y)),
+            Instruction::Shr(x, y) => self.shr(r(x), r(y)),
+            Instruction::Subn(x, y) => self.subn(r(x), r(y)),
+            Instruction::Shl(x, y) => self.shl(r(x), r(y)),
+            Instruction::SneReg(x, y) => self.sne_rx_ry(r(x), r(y)),
+            Instruction::LdI(addr) => self.ld_i(addr),
+            Instruction::JpV0(addr) => self.jmp_r0((addr as usize >> 8) & 0xF, addr),
+            Instruction::Rnd(x, kk) => self.rand(r(x), kk as u16),
+            Instruction::Drw(x, y, n) => self.draw(r(x), r(y), n as usize)?,
+            Instruction::Skp(x) => self.skp_r(r(x)),
+            Instruction::Sknp(x) => self.sknp_r(r(x)),
+            Instruction::LdILong => self.ld_i_long(),
+            Instruction::Plane(n) => self.set_planes(n),
+            Instruction::Audio => self.ld_audio_pattern()?,
+            Instruction::LdRegDt(x) => self.ld_r_dt(r(x)),
+            Instruction::LdRegKey(x) => self.ld_r_kp(r(x)),
+            Instruction::LdDtReg(x) => self.ld_dt_r(r(x)),
+            Instruction::LdStReg(x) => self.ld_st_r(r(x)),
+            Instruction::AddIReg(x) => self.add_i_r(r(x)),
+            Instruction::LdFont(x) => self.ld_i_spr_x(r(x)),
+            Instruction::LdBigFont(x) => self.ld_i_big_spr_x(r(x)),
+            Instruction::Bcd(x) => self.ld_bcd_r(r(x))?,
+            Instruction::Pitch(x) => self.ld_pitch_r(r(x)),
+            Instruction::StoreRegs(x) => self.store_at_i(r(x))?,
+            Instruction::LoadRegs(x) => self.load_from_i(r(x))?,
+            Instruction::StoreRpl(x) => self.store_rpl(r(x)),
+            Instruction::LoadRpl(x) => self.load_rpl(r(x)),
         }
         if self.debug_print {
             println!("Post state:");
@@ -229,7 +382,7 @@ impl Processor {
         for i in 0..16 {
             print!("V{:X} = {:#2X}, ", i, self.registers[i])
         }
-        println!("");
+        println!();
         println!("Stack: {:X?}", self.stack);
         println!(
             "PC = {:X?}, SP = {:X?}, I = {:X?}, DT = {:X?}, ST = {:X?}",
@@ -242,15 +395,33 @@ impl Processor {
             }
             print!("{:02X?}", v);
         });
-        println!("");
+        println!();
         print!("Screen:");
         self.screen_buffer.iter().enumerate().for_each(|(i, s)| {
-            if i % 64 == 0 {
-                println!("");
+            if i % self.get_screen_width() == 0 {
+                println!();
             }
-            print!("{}", if *s { 1 } else { 0 });
+            print!("{}", s);
         });
-        println!("");
+        println!();
+    }
+
+    fn invalid_opcode(&self) -> EmulatorError {
+        return EmulatorError::InvalidOpcode {
+            opcode: self.opcode,
+            pc: self.pc,
+        };
+    }
+    // Check that the `len` bytes starting at I are all in memory
+    fn check_i_range(&self, len: usize) -> Result<(), EmulatorError> {
+        if self.i as usize + len > self.mem.len() {
+            return Err(EmulatorError::MemoryOutOfBounds {
+                opcode: self.opcode,
+                pc: self.pc,
+                address: (self.i as usize).max(self.mem.len()),
+            });
+        }
+        return Ok(());
     }
 
     fn ld_r_kk(&mut self, r: usize, kk: u8) {
@@ -262,18 +433,24 @@ impl Processor {
     }
 
     fn or_rx_ry(&mut self, rx: usize, ry: usize) {
-        self.registers[rx] = self.registers[rx] | self.registers[ry];
-        self.registers[0xF] = 0x0;
+        self.registers[rx] |= self.registers[ry];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0x0;
+        }
     }
 
     fn and_rx_ry(&mut self, rx: usize, ry: usize) {
-        self.registers[rx] = self.registers[rx] & self.registers[ry];
-        self.registers[0xF] = 0x0;
+        self.registers[rx] &= self.registers[ry];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0x0;
+        }
     }
 
     fn xor_rx_ry(&mut self, rx: usize, ry: usize) {
-        self.registers[rx] = self.registers[rx] ^ self.registers[ry];
-        self.registers[0xF] = 0x0;
+        self.registers[rx] ^= self.registers[ry];
+        if self.quirks.logic_resets_vf {
+            self.registers[0xF] = 0x0;
+        }
     }
 
     fn add_rx_ry(&mut self, rx: usize, ry: usize) {
@@ -291,8 +468,13 @@ impl Processor {
         self.registers[0xF] = vf;
     }
     fn shr(&mut self, rx: usize, ry: usize) {
-        let vf = if self.registers[ry] & 0x01 == 1 { 1 } else { 0 };
-        self.registers[rx] = self.registers[ry] >> 1;
+        let src = if self.quirks.shift_uses_vy { ry } else { rx };
+        let vf = if self.registers[src] & 0x01 == 1 {
+            1
+        } else {
+            0
+        };
+        self.registers[rx] = self.registers[src] >> 1;
         self.registers[0xF] = vf;
     }
     fn subn(&mut self, rx: usize, ry: usize) {
@@ -305,85 +487,186 @@ impl Processor {
         self.registers[0xF] = vf;
     }
     fn shl(&mut self, rx: usize, ry: usize) {
-        let vf = if self.registers[ry] & 0x80 == 0x80 {
+        let src = if self.quirks.shift_uses_vy { ry } else { rx };
+        let vf = if self.registers[src] & 0x80 == 0x80 {
             1
         } else {
             0
         };
-        self.registers[rx] = self.registers[ry] << 1;
+        self.registers[rx] = self.registers[src] << 1;
         self.registers[0xF] = vf;
     }
     fn clr(&mut self) {
-        self.screen_buffer = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let planes = self.planes;
+        self.screen_buffer.iter_mut().for_each(|p| *p &= !planes);
+    }
+    // Scroll the selected planes by (dx, dy) pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let w = self.get_screen_width() as isize;
+        let h = self.get_screen_height() as isize;
+        let old = self.screen_buffer.clone();
+        for y in 0..h {
+            for x in 0..w {
+                let (sx, sy) = (x - dx, y - dy);
+                let src = if sx >= 0 && sx < w && sy >= 0 && sy < h {
+                    old[(sy * w + sx) as usize]
+                } else {
+                    0
+                };
+                let coord = (y * w + x) as usize;
+                self.screen_buffer[coord] = (old[coord] & !self.planes) | (src & self.planes);
+            }
+        }
     }
-    fn ret(&mut self) {
+    fn exit(&mut self) {
+        self.exited = true;
+        // Same trick as LD X KP, just keep executing this instruction
+        self.repeat_instruction();
+    }
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen_buffer = vec![0; self.get_screen_width() * self.get_screen_height()];
+    }
+    fn ret(&mut self) -> Result<(), EmulatorError> {
+        if self.sp == 0 {
+            return Err(EmulatorError::StackUnderflow {
+                opcode: self.opcode,
+                pc: self.pc,
+            });
+        }
         self.sp -= 1;
         self.pc = self.stack[self.sp] as usize;
+        return Ok(());
     }
     fn jmp(&mut self, addr: u16) {
         // addr - 2 since we are about to add 2
-        self.pc = (addr - 2) as usize;
-    }
-    fn jmp_r0(&mut self, addr: u16) {
-        self.pc = (addr + self.registers[0] as u16) as usize - 2;
-    }
-    fn call(&mut self, addr: u16) {
+        self.pc = (addr as usize).wrapping_sub(2);
+    }
+    fn jmp_r0(&mut self, x: usize, addr: u16) {
+        // CHIP-48 and SUPER-CHIP treat BXNN as jumping to XNN + VX
+        let r = if self.quirks.jump_uses_vx { x } else { 0 };
+        self.pc = (addr as usize + self.registers[r] as usize).wrapping_sub(2);
+    }
+    fn call(&mut self, addr: u16) -> Result<(), EmulatorError> {
+        if self.sp == self.stack.len() {
+            return Err(EmulatorError::StackOverflow {
+                opcode: self.opcode,
+                pc: self.pc,
+            });
+        }
         self.stack[self.sp] = self.pc as u16;
         self.sp += 1;
-        self.pc = addr as usize - 2;
+        self.pc = (addr as usize).wrapping_sub(2);
+        return Ok(());
+    }
+    // Skip the next instruction
+    fn skip(&mut self) {
+        // On XO-CHIP the next instruction might be the 4 byte long F000 NNNN
+        if self.variant == Variant::XoChip && self.get_word_at(self.pc + 2) == 0xF000 {
+            self.pc += 4;
+        } else {
+            self.pc += 2;
+        }
     }
     fn se_r_kk(&mut self, r: usize, kk: u8) {
         if self.registers[r] == kk {
-            self.pc += 2;
+            self.skip();
         }
     }
     fn se_rx_ry(&mut self, rx: usize, ry: usize) {
         if self.registers[rx] == self.registers[ry] {
-            self.pc += 2;
+            self.skip();
         }
     }
-    fn sne_r_kk(&mut self, inst: u16) {
-        if self.registers[reg_at(inst, 1)] as u16 != inst & 0xFF {
-            self.pc += 2;
+    fn sne_r_kk(&mut self, r: usize, kk: u8) {
+        if self.registers[r] != kk {
+            self.skip();
         }
     }
     fn sne_rx_ry(&mut self, x: usize, y: usize) {
         if self.registers[x] != self.registers[y] {
-            self.pc += 2;
+            self.skip();
         }
     }
     fn skp_r(&mut self, x: usize) {
         if self.input_state[self.registers[x] as usize] {
-            self.pc += 2;
+            self.skip();
         }
     }
     fn sknp_r(&mut self, x: usize) {
         if !self.input_state[self.registers[x] as usize] {
-            self.pc += 2;
+            self.skip();
         }
     }
     fn ld_r_kp(&mut self, r: usize) {
         match self.last_key_released {
-            Some(i) => self.registers[r] = i as u8,
+            Some(i) => self.registers[r] = i,
             // Sneaky hack - in order to "wait" we just decrement PC so that we reach this addr again
             // In retrospect this probably isn't that sneaky
-            None => self.pc -= 2,
+            None => self.repeat_instruction(),
         }
     }
+    // Move PC back so that `step` runs this instruction again.
+    // Wraps like `step` does, so an instruction at address 0 can wait too.
+    fn repeat_instruction(&mut self) {
+        self.pc = self.pc.wrapping_sub(2);
+    }
     fn ld_i(&mut self, addr: u16) {
         self.i = addr;
     }
-    fn load_from_i(&mut self, n: usize) {
+    // Load the 16 bit address after this instruction into I
+    fn ld_i_long(&mut self) {
+        self.i = self.get_word_at(self.pc + 2);
+        self.pc += 2;
+    }
+    // Store Vx through Vy at I, in reverse order if x > y, without changing I
+    fn store_rx_ry_at_i(&mut self, x: usize, y: usize) -> Result<(), EmulatorError> {
+        self.check_i_range(x.abs_diff(y) + 1)?;
+        for (j, r) in register_range(x, y).enumerate() {
+            self.mem[self.i as usize + j] = self.registers[r];
+        }
+        return Ok(());
+    }
+    // Load Vx through Vy from I, in reverse order if x > y, without changing I
+    fn load_rx_ry_from_i(&mut self, x: usize, y: usize) -> Result<(), EmulatorError> {
+        self.check_i_range(x.abs_diff(y) + 1)?;
+        for (j, r) in register_range(x, y).enumerate() {
+            self.registers[r] = self.mem[self.i as usize + j];
+        }
+        return Ok(());
+    }
+    fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & 0x3;
+    }
+    fn ld_audio_pattern(&mut self) -> Result<(), EmulatorError> {
+        self.check_i_range(16)?;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(&self.mem[self.i as usize..self.i as usize + 16]);
+        self.audio_pattern = Some(pattern);
+        return Ok(());
+    }
+    fn ld_pitch_r(&mut self, r: usize) {
+        self.pitch = self.registers[r];
+    }
+    fn load_from_i(&mut self, n: usize) -> Result<(), EmulatorError> {
+        self.check_i_range(n + 1)?;
         for j in 0..(n + 1) {
-            self.registers[j as usize] = self.mem[(self.i + j as u16) as usize];
+            self.registers[j] = self.mem[self.i as usize + j];
+        }
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add((n + 1) as u16);
         }
-        self.i += (n + 1) as u16;
+        return Ok(());
     }
-    fn store_at_i(&mut self, n: usize) {
+    fn store_at_i(&mut self, n: usize) -> Result<(), EmulatorError> {
+        self.check_i_range(n + 1)?;
         for j in 0..(n + 1) {
-            self.mem[(self.i + j as u16) as usize] = self.registers[j as usize];
+            self.mem[self.i as usize + j] = self.registers[j];
+        }
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add((n + 1) as u16);
         }
-        self.i += (n + 1) as u16;
+        return Ok(());
     }
     fn add_i_r(&mut self, r: usize) {
         self.i = self.i.wrapping_add(self.registers[r] as u16);
@@ -392,7 +675,7 @@ impl Processor {
         self.registers[r] = self.registers[r].wrapping_add(kk);
     }
     fn rand(&mut self, r: usize, mask: u16) {
-        self.registers[r] = (rand::thread_rng().gen_range(0..0xFF) & mask) as u8;
+        self.registers[r] = (self.rng.next_u32() & mask as u32) as u8;
     }
     fn ld_st_r(&mut self, r: usize) {
         self.st = self.registers[r];
@@ -403,31 +686,57 @@ impl Processor {
     fn ld_r_dt(&mut self, x: usize) {
         self.registers[x] = self.dt;
     }
-    fn draw(&mut self, rx: usize, ry: usize, n: usize) {
-        if !self.vblank {
-            self.pc -= 2;
-            return;
+    fn draw(&mut self, rx: usize, ry: usize, n: usize) -> Result<(), EmulatorError> {
+        if self.quirks.display_wait {
+            if !self.vblank {
+                self.repeat_instruction();
+                return Ok(());
+            }
+            self.vblank = false;
         }
-        self.vblank = false;
+        let width = self.get_screen_width();
+        let height = self.get_screen_height();
+        let x = self.registers[rx] as usize % width;
+        let y = self.registers[ry] as usize % height;
+        // DXY0 draws a 16x16 sprite on SUPER-CHIP
+        let (rows, cols) = if n == 0 && self.variant != Variant::Chip8 {
+            (16, 16)
+        } else {
+            (n, 8)
+        };
+        let bytes_per_row = cols / 8;
+        let planes = (self.planes & 0x1) + (self.planes >> 1);
+        self.check_i_range(rows * bytes_per_row * planes as usize)?;
         self.registers[0xF] = 0;
-        let x = self.registers[rx] % SCREEN_WIDTH as u8;
-        let y = self.registers[ry] % SCREEN_HEIGHT as u8;
-        // XOR data onto screen
-        for j in 0..n {
-            let mut val = self.mem[self.i as usize + j];
-            for k in 0..8 {
-                if x as usize + k >= SCREEN_WIDTH || y as usize + j >= SCREEN_HEIGHT {
-                    continue;
+        // When drawing to both XO-CHIP planes, the sprite for plane 2 comes right after the one for plane 1
+        let mut addr = self.i as usize;
+        for plane in [0x1, 0x2] {
+            if self.planes & plane == 0 {
+                continue;
+            }
+            // XOR data onto screen
+            for j in 0..rows {
+                let mut val: u16 = 0;
+                for _ in 0..bytes_per_row {
+                    val = (val << 8) | self.mem[addr] as u16;
+                    addr += 1;
                 }
-                let coord: usize = (y as usize + j) * SCREEN_WIDTH + x as usize + k;
-                let p = (val & 0x80) != 0; // Get MSB
-                if p && self.screen_buffer[coord] {
-                    self.registers[0xF] = 1;
+                for k in 0..cols {
+                    if self.quirks.clip_sprites && (x + k >= width || y + j >= height) {
+                        continue;
+                    }
+                    let coord: usize = ((y + j) % height) * width + (x + k) % width;
+                    if (val >> (cols - 1 - k)) & 0x1 == 0 {
+                        continue;
+                    }
+                    if self.screen_buffer[coord] & plane != 0 {
+                        self.registers[0xF] = 1;
+                    }
+                    self.screen_buffer[coord] ^= plane;
                 }
-                self.screen_buffer[coord] = p ^ self.screen_buffer[coord];
-                val = val << 1;
             }
         }
+        return Ok(());
     }
     // Only loads the sprite for the LSByte of Vr
     fn ld_i_spr_x(&mut self, r: usize) {
@@ -435,10 +744,22 @@ impl Processor {
         // 5 + 1 buffer row
         self.i = (self.registers[r] as u16 & 0xF) * 0x6;
     }
-    fn ld_bcd_r(&mut self, r: usize) {
+    // Only loads the big sprite for the LSByte of Vr
+    fn ld_i_big_spr_x(&mut self, r: usize) {
+        self.i = (BIG_SPRITES_ADDR + 10 * (self.registers[r] as usize & 0xF)) as u16;
+    }
+    fn store_rpl(&mut self, n: usize) {
+        self.rpl_flags[0..=n].copy_from_slice(&self.registers[0..=n]);
+    }
+    fn load_rpl(&mut self, n: usize) {
+        self.registers[0..=n].copy_from_slice(&self.rpl_flags[0..=n]);
+    }
+    fn ld_bcd_r(&mut self, r: usize) -> Result<(), EmulatorError> {
+        self.check_i_range(3)?;
         self.mem[self.i as usize] = self.registers[r] / 100;
         self.mem[self.i as usize + 1] = (self.registers[r] / 10) % 10;
         self.mem[self.i as usize + 2] = self.registers[r] % 10;
+        return Ok(());
     }
 
     /// Get the value of an R register
@@ -453,17 +774,136 @@ impl Processor {
     pub fn get_i(&self) -> u16 {
         return self.i;
     }
+    /// Set the value of an R register, used by debuggers
+    pub fn set_register_value(&mut self, register: u8, value: u8) {
+        self.registers[register as usize] = value;
+    }
+    /// Set the value of the program counter, used by debuggers
+    pub fn set_program_counter(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+    /// Set the value of the I register, used by debuggers
+    pub fn set_i(&mut self, i: u16) {
+        self.i = i;
+    }
+    /// Set a single byte of memory at the address given, used by debuggers
+    pub fn set_mem_at(&mut self, addr: usize, value: u8) {
+        self.mem[addr] = value;
+    }
+    /// Set the D timer register, used by debuggers
+    pub fn set_dt(&mut self, dt: u8) {
+        self.dt = dt;
+    }
+    /// Set the S (sound) timer register, used by debuggers
+    pub fn set_st(&mut self, st: u8) {
+        self.st = st;
+    }
+    /// Get the value of the stack pointer, i.e. how many return addresses are on the stack
+    pub fn get_stack_pointer(&self) -> usize {
+        return self.sp;
+    }
+    /// Get the return addresses on the stack, the most recent one last
+    pub fn get_stack(&self) -> &[u16] {
+        return &self.stack[..self.sp];
+    }
+    /// Get the size of memory in bytes
+    pub fn get_mem_size(&self) -> usize {
+        return self.mem.len();
+    }
+    /// Get the whole of memory
+    pub fn get_memory(&self) -> &[u8] {
+        return &self.mem;
+    }
     /// Get a single byte of memory at the address given
     pub fn get_mem_at(&self, addr: usize) -> u8 {
         return self.mem[addr];
     }
+    /// Get the 2 bytes of memory at the address given as a single instruction
+    pub fn get_word_at(&self, addr: usize) -> u16 {
+        return ((self.mem[addr % self.mem.len()] as u16) << 8)
+            | self.mem[(addr + 1) % self.mem.len()] as u16;
+    }
     /**
      * Get a pixel at the given `x, y` position on the screen.
+     * Returns `true` if the pixel is set in any bitplane.
      * Accounts for screen wrapping.
      */
     pub fn get_pixel_at(&self, x: u8, y: u8) -> bool {
+        return self.get_pixel_color_at(x, y) != 0;
+    }
+    /**
+     * Get the color of the pixel at the given `x, y` position on the screen.
+     * Bit 0 is set if the pixel is set in the first bitplane, and bit 1 if it is set in the second (XO-CHIP only).
+     * Accounts for screen wrapping.
+     */
+    pub fn get_pixel_color_at(&self, x: u8, y: u8) -> u8 {
+        let width = self.get_screen_width();
         return self.screen_buffer
-            [((x as usize % 64) + y as usize * 64) % self.screen_buffer.len()];
+            [((x as usize % width) + y as usize * width) % self.screen_buffer.len()];
+    }
+    /// Get the XO-CHIP bitplanes currently selected
+    pub fn get_planes(&self) -> u8 {
+        return self.planes;
+    }
+    /// Get the XO-CHIP audio pattern, if the program has loaded one
+    pub fn get_audio_pattern(&self) -> Option<[u8; 16]> {
+        return self.audio_pattern;
+    }
+    /// Get the XO-CHIP audio pitch register
+    pub fn get_pitch(&self) -> u8 {
+        return self.pitch;
+    }
+    /// Get the width of the screen in the current resolution
+    pub fn get_screen_width(&self) -> usize {
+        return if self.hires {
+            HIRES_SCREEN_WIDTH
+        } else {
+            SCREEN_WIDTH
+        };
+    }
+    /// Get the height of the screen in the current resolution
+    pub fn get_screen_height(&self) -> usize {
+        return if self.hires {
+            HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        };
+    }
+    /// Get the width and height of the largest screen the variant can use
+    pub fn get_max_screen_size(&self) -> (usize, usize) {
+        return match self.variant {
+            Variant::Chip8 => (SCREEN_WIDTH, SCREEN_HEIGHT),
+            _ => (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT),
+        };
+    }
+    /// Return whether the SUPER-CHIP high resolution mode is enabled
+    pub fn is_hires(&self) -> bool {
+        return self.hires;
+    }
+    /// Return whether the program has exited using the SUPER-CHIP `00FD` instruction
+    pub fn has_exited(&self) -> bool {
+        return self.exited;
+    }
+    /**
+     * Replace the random number generator used by CXNN.
+     */
+    pub fn set_rng(&mut self, rng: Box<dyn RngCore + Send>) {
+        self.rng = rng;
+    }
+    /**
+     * Reseed the random number generator used by CXNN.
+     * Replaces any generator given to `set_rng`.
+     */
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Box::new(StdRng::seed_from_u64(seed));
+    }
+    /// Get the variant of CHIP-8 being emulated
+    pub fn get_variant(&self) -> Variant {
+        return self.variant;
+    }
+    /// Get the quirks the processor is using
+    pub fn get_quirks(&self) -> Quirks {
+        return self.quirks;
     }
     /**
      * Return whether the processor has the `i` key currently being pressed.
@@ -480,6 +920,13 @@ impl Processor {
     pub fn get_st(&self) -> u8 {
         return self.st;
     }
+    /**
+     * Get the number of times `on_tick` has been called, i.e. to keep audio in time with the timers.
+     * Loading a state leaves it unchanged.
+     */
+    pub fn get_ticks(&self) -> u64 {
+        return self.ticks;
+    }
     /// Callback on VBlank (vertical interrupt)
     /// Used to ensure display waiting
     /// To ignore, just call every tick
@@ -488,9 +935,11 @@ impl Processor {
     }
 }
 
-// Get index of the register given the instruction
-// and the position of the byte from the left in the instruction
-// i.e. inst = 0xABCD, pos = 3, res = 0x000C
-fn reg_at(inst: u16, pos: u8) -> usize {
-    return ((inst >> (12 - pos * 4)) & 0x000F) as usize;
+// Get the registers from x to y inclusive, going backwards if x > y
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
 }
@@ -0,0 +1,228 @@
+#![allow(clippy::needless_return)]
+use super::{Processor, Variant};
+use crate::errors::StateError;
+use crate::hash::Fnv64;
+use crate::quirks::Quirks;
+
+// Every save state starts with this
+const MAGIC: &[u8; 4] = b"C8ST";
+// Bump whenever the layout below changes
+const VERSION: u8 = 1;
+
+/*
+ * Save state layout, all multi byte values are little endian:
+ * magic (4), version (1), variant (1), quirks (1, one bit each),
+ * registers (16), pc (4), stack (16 * 2), sp (1), i (2), dt (1), st (1),
+ * hires (1), exited (1), planes (1), rpl flags (16), has audio pattern (1), audio pattern (16), pitch (1),
+ * input state (2, one bit per key), last key released (1, 0xFF for none), vblank (1),
+ * memory length (4), memory, screen length (4), screen
+ */
+impl Processor {
+    /**
+     * Serialize the full state of the processor into a versioned binary blob.
+     * The state can be restored using `load_state`.
+     */
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.mem.len() + self.screen_buffer.len() + 0x80);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        out.push(match self.variant {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip => 2,
+        });
+        out.push(self.quirks.to_bits());
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&(self.pc as u32).to_le_bytes());
+        self.stack
+            .iter()
+            .for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+        out.push(self.sp as u8);
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.push(self.dt);
+        out.push(self.st);
+        out.push(self.hires as u8);
+        out.push(self.exited as u8);
+        out.push(self.planes);
+        out.extend_from_slice(&self.rpl_flags);
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or([0; 16]));
+        out.push(self.pitch);
+        let inputs = self
+            .input_state
+            .iter()
+            .enumerate()
+            .fold(0u16, |acc, (i, v)| acc | ((*v as u16) << i));
+        out.extend_from_slice(&inputs.to_le_bytes());
+        out.push(self.last_key_released.unwrap_or(0xFF));
+        out.push(self.vblank as u8);
+        out.extend_from_slice(&(self.mem.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.mem);
+        out.extend_from_slice(&(self.screen_buffer.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.screen_buffer);
+        return out;
+    }
+    /**
+     * Restore the state of the processor from a blob created by `save_state`.
+     * The processor is left unchanged if the blob cannot be loaded.
+     */
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(4).map_err(|_| StateError::InvalidHeader)? != MAGIC {
+            return Err(StateError::InvalidHeader);
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let variant = match r.u8()? {
+            0 => Variant::Chip8,
+            1 => Variant::SuperChip,
+            2 => Variant::XoChip,
+            _ => return Err(StateError::Corrupt("variant")),
+        };
+        // Build the new state separately so that self is untouched on error
+        let mut p = Processor::new_with_quirks(variant, Quirks::from_bits(r.u8()?));
+        p.registers.copy_from_slice(r.bytes(0x10)?);
+        p.pc = r.u32()? as usize;
+        for v in p.stack.iter_mut() {
+            *v = r.u16()?;
+        }
+        p.sp = r.u8()? as usize;
+        if p.sp > p.stack.len() {
+            return Err(StateError::Corrupt("stack pointer"));
+        }
+        p.i = r.u16()?;
+        p.dt = r.u8()?;
+        p.st = r.u8()?;
+        p.hires = r.bool()?;
+        p.exited = r.bool()?;
+        p.planes = r.u8()?;
+        if p.planes > 0x3 {
+            return Err(StateError::Corrupt("planes"));
+        }
+        p.rpl_flags.copy_from_slice(r.bytes(0x10)?);
+        let has_pattern = r.bool()?;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(r.bytes(16)?);
+        p.audio_pattern = if has_pattern { Some(pattern) } else { None };
+        p.pitch = r.u8()?;
+        let inputs = r.u16()?;
+        (0..0x10).for_each(|i| p.input_state[i] = (inputs >> i) & 0x1 == 1);
+        p.last_key_released = match r.u8()? {
+            0xFF => None,
+            k if k < 0x10 => Some(k),
+            _ => return Err(StateError::Corrupt("last key released")),
+        };
+        p.vblank = r.bool()?;
+        let mem_len = r.u32()? as usize;
+        if mem_len != p.mem.len() {
+            return Err(StateError::Corrupt("memory size"));
+        }
+        p.mem.copy_from_slice(r.bytes(mem_len)?);
+        let screen_len = r.u32()? as usize;
+        if screen_len != p.get_screen_width() * p.get_screen_height() {
+            return Err(StateError::Corrupt("screen size"));
+        }
+        p.screen_buffer = r.bytes(screen_len)?.to_vec();
+        if p.screen_buffer.iter().any(|px| *px > 0x3) {
+            return Err(StateError::Corrupt("screen"));
+        }
+        if r.pos != data.len() {
+            return Err(StateError::Corrupt("length"));
+        }
+        // The random number generator is not part of the state, so keep the current one
+        p.debug_print = self.debug_print;
+        std::mem::swap(&mut p.rng, &mut self.rng);
+        // Neither is whether the inputs are overridden, i.e. by a movie
+        p.requested_inputs = self.requested_inputs;
+        p.inputs_overridden = self.inputs_overridden;
+        p.ticks = self.ticks;
+        *self = p;
+        return Ok(());
+    }
+    /**
+     * Get a 64 bit hash of the screen, for cheaply checking what is drawn.
+     * Covers the size of the screen and the bitplanes set for every pixel, and is the same
+     * across versions and platforms.
+     */
+    pub fn screen_hash(&self) -> u64 {
+        let mut hash = Fnv64::new();
+        hash.write(&(self.get_screen_width() as u16).to_le_bytes());
+        hash.write(&(self.get_screen_height() as u16).to_le_bytes());
+        hash.write(&self.screen_buffer);
+        return hash.finish();
+    }
+    /**
+     * Get a 64 bit hash of the full state of the processor, for cheaply checking that two runs match.
+     * Covers everything in a save state, but unlike a save state's layout it is the same
+     * across versions and platforms. The random number generator is not included.
+     */
+    pub fn state_hash(&self) -> u64 {
+        // Never change the order here, so that old hashes stay valid
+        let mut hash = Fnv64::new();
+        hash.write(&[
+            match self.variant {
+                Variant::Chip8 => 0,
+                Variant::SuperChip => 1,
+                Variant::XoChip => 2,
+            },
+            self.quirks.to_bits(),
+        ]);
+        hash.write(&self.registers);
+        hash.write(&(self.pc as u32).to_le_bytes());
+        self.stack.iter().for_each(|v| hash.write(&v.to_le_bytes()));
+        hash.write(&[self.sp as u8]);
+        hash.write(&self.i.to_le_bytes());
+        hash.write(&[
+            self.dt,
+            self.st,
+            self.hires as u8,
+            self.exited as u8,
+            self.planes,
+        ]);
+        hash.write(&self.rpl_flags);
+        hash.write(&[self.audio_pattern.is_some() as u8]);
+        hash.write(&self.audio_pattern.unwrap_or([0; 16]));
+        hash.write(&[self.pitch]);
+        hash.write(&self.input_state.map(|v| v as u8));
+        hash.write(&[self.last_key_released.unwrap_or(0xFF), self.vblank as u8]);
+        hash.write(&self.mem);
+        hash.write(&self.screen_buffer);
+        return hash.finish();
+    }
+}
+
+// Reads values out of a save state, failing if it runs out of data
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() - self.pos < n {
+            return Err(StateError::Truncated);
+        }
+        self.pos += n;
+        return Ok(&self.data[(self.pos - n)..self.pos]);
+    }
+    fn u8(&mut self) -> Result<u8, StateError> {
+        return Ok(self.bytes(1)?[0]);
+    }
+    fn bool(&mut self) -> Result<bool, StateError> {
+        return match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt("flag")),
+        };
+    }
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let b = self.bytes(2)?;
+        return Ok(u16::from_le_bytes([b[0], b[1]]));
+    }
+    fn u32(&mut self) -> Result<u32, StateError> {
+        let b = self.bytes(4)?;
+        return Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    }
+}
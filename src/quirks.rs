@@ -0,0 +1,109 @@
+#![allow(clippy::needless_return)]
+use crate::processor::Variant;
+
+/**
+ * Behaviours that differ between CHIP-8 implementations.
+ * Different ROMs expect different choices, so each one can be toggled separately.
+ * Presets for the most common implementations are provided.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6` and `8XYE` shift VY into VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// `FX55` and `FX65` increment I by X + 1
+    pub load_store_increments_i: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset VF to 0
+    pub logic_resets_vf: bool,
+    /// `DXYN` clips sprites at the edges of the screen, instead of wrapping them around
+    pub clip_sprites: bool,
+    /// `DXYN` waits for the vertical blank interrupt before drawing
+    pub display_wait: bool,
+    /// `BXNN` jumps to XNN + VX, instead of XNN + V0
+    pub jump_uses_vx: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::cosmac_vip()
+    }
+}
+
+impl Quirks {
+    /// Quirks of the original COSMAC VIP interpreter
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            logic_resets_vf: true,
+            clip_sprites: true,
+            display_wait: true,
+            jump_uses_vx: false,
+        }
+    }
+    /// Quirks of the CHIP-48 interpreter for the HP-48 calculators
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: true,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+            jump_uses_vx: true,
+        }
+    }
+    /// Quirks of the SUPER-CHIP 1.1 interpreter
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            logic_resets_vf: false,
+            clip_sprites: true,
+            display_wait: false,
+            jump_uses_vx: true,
+        }
+    }
+    /// Quirks of XO-CHIP, as implemented by Octo
+    pub fn xo_chip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            logic_resets_vf: false,
+            clip_sprites: false,
+            display_wait: false,
+            jump_uses_vx: false,
+        }
+    }
+    /// The quirks ROMs written for the variant given usually expect
+    pub fn for_variant(variant: Variant) -> Quirks {
+        return match variant {
+            Variant::Chip8 => Quirks::cosmac_vip(),
+            Variant::SuperChip => Quirks::schip(),
+            Variant::XoChip => Quirks::xo_chip(),
+        };
+    }
+    // Pack the quirks into a byte, one bit each, for save states and movies
+    pub(crate) fn to_bits(self) -> u8 {
+        return [
+            self.shift_uses_vy,
+            self.load_store_increments_i,
+            self.logic_resets_vf,
+            self.clip_sprites,
+            self.display_wait,
+            self.jump_uses_vx,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (i, v)| acc | ((*v as u8) << i));
+    }
+    pub(crate) fn from_bits(bits: u8) -> Quirks {
+        let bit = |i: u8| (bits >> i) & 0x1 == 1;
+        return Quirks {
+            shift_uses_vy: bit(0),
+            load_store_increments_i: bit(1),
+            logic_resets_vf: bit(2),
+            clip_sprites: bit(3),
+            display_wait: bit(4),
+            jump_uses_vx: bit(5),
+        };
+    }
+}
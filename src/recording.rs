@@ -0,0 +1,195 @@
+#![allow(clippy::needless_return)]
+use crate::processor::Processor;
+use crate::screenshot::Screenshot;
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Frames shown per second, as rendered by the emulator
+const FPS: u64 = 60;
+// Shortest delay between GIF frames in hundredths of a second, since most viewers slow down anything shorter
+const MIN_GIF_DELAY: u64 = 2;
+
+/**
+ * Format of a recording.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecordingFormat {
+    /// Looping animated GIF, with identical frames merged into one
+    Gif,
+    /// Every frame as RGB bytes one after another, i.e. for piping to
+    /// `ffmpeg -f rawvideo -pix_fmt rgb24 -video_size 64x32 -framerate 60 -i - out.mp4`.
+    /// Identical frames are still written, since the frame rate is what keeps the timing.
+    Raw,
+}
+
+impl RecordingFormat {
+    /// Get the format for the path given, GIF if it ends in `.gif` and raw otherwise
+    pub fn from_path(path: &Path) -> RecordingFormat {
+        return match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("gif") => RecordingFormat::Gif,
+            _ => RecordingFormat::Raw,
+        };
+    }
+}
+
+enum Encoder<W: Write> {
+    Gif(gif::Encoder<W>),
+    Raw(W),
+}
+
+/**
+ * Records the processor's screen each frame, as an animated GIF or raw RGB frames.
+ *
+ * Frames are read from the processor rather than the window, so recording works in every interface,
+ * including headless. The recording keeps the size it was created with, and the screen is stretched
+ * to fit it when the resolution changes.
+ */
+pub struct Recorder<W: Write> {
+    encoder: Encoder<W>,
+    screenshot: Screenshot,
+    width: usize,
+    height: usize,
+    // Frames captured so far
+    frames: u64,
+    // Frames written to the output so far
+    frames_written: u64,
+    // The GIF frame waiting to be written until it changes, and the frame it was first shown on
+    pending: Option<(Vec<u8>, u64)>,
+}
+
+impl<W: Write> Recorder<W> {
+    /**
+     * Start a recording of the size given, using the screenshot's colors.
+     * The size should already be scaled by the screenshot's scale.
+     */
+    pub fn new(
+        out: W,
+        format: RecordingFormat,
+        screenshot: Screenshot,
+        width: usize,
+        height: usize,
+    ) -> io::Result<Recorder<W>> {
+        let encoder = match format {
+            RecordingFormat::Gif => {
+                let palette: Vec<u8> = screenshot.palette.concat();
+                let mut encoder =
+                    gif::Encoder::new(out, width as u16, height as u16, &palette).map_err(to_io)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(to_io)?;
+                Encoder::Gif(encoder)
+            }
+            RecordingFormat::Raw => Encoder::Raw(out),
+        };
+        return Ok(Recorder {
+            encoder,
+            screenshot,
+            width,
+            height,
+            frames: 0,
+            frames_written: 0,
+            pending: None,
+        });
+    }
+    /**
+     * Start a recording big enough for the largest screen the processor's variant can use,
+     * scaled by the screenshot's scale.
+     */
+    pub fn for_processor(
+        out: W,
+        format: RecordingFormat,
+        screenshot: Screenshot,
+        p: &Processor,
+    ) -> io::Result<Recorder<W>> {
+        let (width, height) = p.get_max_screen_size();
+        let scale = screenshot.scale.max(1);
+        return Recorder::new(out, format, screenshot, width * scale, height * scale);
+    }
+    /// Get the width and height of the recording
+    pub fn get_size(&self) -> (usize, usize) {
+        return (self.width, self.height);
+    }
+    /// Get the number of frames captured so far
+    pub fn get_frames(&self) -> u64 {
+        return self.frames;
+    }
+    /// Get the number of frames written so far, after identical ones have been merged
+    pub fn get_frames_written(&self) -> u64 {
+        return self.frames_written;
+    }
+    /**
+     * Capture the processor's screen as the next frame. Should be called once every 60th of a second,
+     * whenever the screen is rendered.
+     */
+    pub fn capture(&mut self, p: &Processor) -> io::Result<()> {
+        let frame = self.screenshot.get_frame(p, self.width, self.height);
+        let index = self.frames;
+        self.frames += 1;
+        if let Encoder::Raw(out) = &mut self.encoder {
+            let rgb: Vec<u8> = frame
+                .iter()
+                .flat_map(|c| self.screenshot.palette[*c as usize])
+                .collect();
+            out.write_all(&rgb)?;
+            self.frames_written += 1;
+            return Ok(());
+        }
+        match self.pending.take() {
+            Some((pending, start)) if pending == frame => self.pending = Some((pending, start)),
+            // Frames shown too briefly for a GIF are replaced by the next one, keeping the overall timing
+            Some((_, start)) if get_delay(start, index) < MIN_GIF_DELAY => {
+                self.pending = Some((frame, start))
+            }
+            Some((pending, start)) => {
+                self.write_gif_frame(pending, start, index)?;
+                self.pending = Some((frame, index));
+            }
+            None => self.pending = Some((frame, index)),
+        }
+        return Ok(());
+    }
+    /**
+     * Write any frame still waiting and finish the recording, returning the output.
+     */
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some((pending, start)) = self.pending.take() {
+            let end = self.frames.max(start + 1);
+            self.write_gif_frame(pending, start, end)?;
+        }
+        return match self.encoder {
+            Encoder::Gif(encoder) => encoder.into_inner(),
+            Encoder::Raw(mut out) => {
+                out.flush()?;
+                Ok(out)
+            }
+        };
+    }
+    // Write a GIF frame shown from frame `start` until just before frame `end`
+    fn write_gif_frame(&mut self, frame: Vec<u8>, start: u64, end: u64) -> io::Result<()> {
+        if let Encoder::Gif(encoder) = &mut self.encoder {
+            let frame = gif::Frame {
+                width: self.width as u16,
+                height: self.height as u16,
+                delay: get_delay(start, end).min(u16::MAX as u64) as u16,
+                buffer: Cow::Owned(frame),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).map_err(to_io)?;
+            self.frames_written += 1;
+        }
+        return Ok(());
+    }
+}
+
+// Get how long to show a frame from frame `start` until frame `end`, in hundredths of a second.
+// Rounding the start and end separately keeps the total length right.
+fn get_delay(start: u64, end: u64) -> u64 {
+    let to_centis = |frame: u64| (frame * 100 + FPS / 2) / FPS;
+    return to_centis(end) - to_centis(start);
+}
+
+fn to_io(e: gif::EncodingError) -> io::Error {
+    return match e {
+        gif::EncodingError::Io(e) => e,
+        e => io::Error::other(e),
+    };
+}
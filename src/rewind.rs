@@ -0,0 +1,182 @@
+#![allow(clippy::needless_return)]
+use crate::errors::StateError;
+use crate::processor::Processor;
+use std::collections::VecDeque;
+
+/**
+ * Ring buffer of processor snapshots used to rewind gameplay.
+ *
+ * Only the newest snapshot is kept in full. Every older one is stored as a compressed delta from
+ * the snapshot after it, since most of memory doesn't change between snapshots.
+ * The oldest snapshots are dropped once either the snapshot or memory limit is reached.
+ */
+pub struct RewindBuffer {
+    // Frames between snapshots
+    interval: u32,
+    // Maximum number of snapshots kept
+    max_snapshots: usize,
+    // Maximum number of bytes used by the snapshots
+    max_bytes: usize,
+    // Frames since the last snapshot
+    frames: u32,
+    // Newest snapshot, in full
+    latest: Option<Vec<u8>>,
+    // Deltas that turn each snapshot into the one before it, oldest first
+    deltas: VecDeque<Vec<u8>>,
+    // Total size of all the deltas
+    delta_bytes: usize,
+}
+
+impl RewindBuffer {
+    /**
+     * Create a buffer that takes a snapshot every `interval` frames, and keeps at most
+     * `max_snapshots` snapshots using at most around `max_bytes` bytes.
+     */
+    pub fn new(interval: u32, max_snapshots: usize, max_bytes: usize) -> RewindBuffer {
+        RewindBuffer {
+            interval: interval.max(1),
+            max_snapshots,
+            max_bytes,
+            frames: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+    /**
+     * Should be called once per frame while the game is running.
+     * Takes a snapshot of the processor every `interval` frames.
+     */
+    pub fn on_frame(&mut self, p: &Processor) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.push(p.save_state());
+        }
+    }
+    /**
+     * Add a snapshot, as created by `Processor::save_state`.
+     */
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.max_snapshots == 0 {
+            return;
+        }
+        if let Some(latest) = self.latest.take() {
+            let delta = encode_delta(&state, &latest);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.latest = Some(state);
+        // Drop the oldest snapshots until we are within the limits again
+        while !self.deltas.is_empty()
+            && (self.len() > self.max_snapshots || self.memory_used() > self.max_bytes)
+        {
+            let delta = self.deltas.pop_front().unwrap();
+            self.delta_bytes -= delta.len();
+        }
+    }
+    /**
+     * Restore the processor to the newest snapshot, and drop it so the next call goes further back.
+     * The oldest snapshot is never dropped, so rewinding stops there.
+     * Returns `false` if there are no snapshots, or the error if the snapshot can't be loaded, in which
+     * case the processor and snapshots are left as they were.
+     */
+    pub fn rewind(&mut self, p: &mut Processor) -> Result<bool, StateError> {
+        let latest = match &self.latest {
+            Some(l) => l,
+            None => return Ok(false),
+        };
+        p.load_state(latest)?;
+        if let Some(delta) = self.deltas.pop_back() {
+            self.delta_bytes -= delta.len();
+            self.latest = Some(apply_delta(latest, &delta));
+        }
+        self.frames = 0;
+        return Ok(true);
+    }
+    /// Get the number of snapshots stored
+    pub fn len(&self) -> usize {
+        return self.deltas.len() + self.latest.iter().len();
+    }
+    /// Return whether there are no snapshots stored
+    pub fn is_empty(&self) -> bool {
+        return self.latest.is_none();
+    }
+    /// Get the number of bytes used by the snapshots
+    pub fn memory_used(&self) -> usize {
+        return self.delta_bytes + self.latest.as_ref().map_or(0, |l| l.len());
+    }
+    /// Remove all snapshots
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.frames = 0;
+    }
+}
+
+/*
+ * Deltas are the length of the target state followed by the XOR of both states, run length encoded
+ * as pairs of (number of zero bytes, number of literal bytes, literal bytes).
+ * Counts are stored as LEB128 varints.
+ */
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let byte_at = |s: &[u8], i: usize| s.get(i).copied().unwrap_or(0);
+    let xor: Vec<u8> = (0..to.len())
+        .map(|i| byte_at(from, i) ^ byte_at(to, i))
+        .collect();
+    let mut out = Vec::new();
+    write_varint(&mut out, to.len());
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take_while(|b| **b == 0).count();
+        i += zeros;
+        let literals = xor[i..].iter().take_while(|b| **b != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend_from_slice(&xor[i..(i + literals)]);
+        i += literals;
+    }
+    return out;
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut out: Vec<u8> = (0..len)
+        .map(|i| from.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for b in &delta[pos..(pos + literals)] {
+            out[i] ^= b;
+            i += 1;
+        }
+        pos += literals;
+    }
+    return out;
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8 & 0x7F) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = data[*pos];
+        *pos += 1;
+        v |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return v;
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,188 @@
+#![allow(clippy::needless_return)]
+use crate::processor::Processor;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Characters to draw each pixel color with in text screenshots, one for each combination of XO-CHIP bitplanes
+const GLYPHS: [char; 4] = ['.', '#', '+', '@'];
+
+/**
+ * File format of a screenshot.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    /// Color image in the PNG format
+    Png,
+    /// Color image in the binary PPM format
+    Ppm,
+    /// Black and white image in the plain PBM format, with set pixels in black
+    Pbm,
+    /// ASCII art, one character per pixel and one line per row
+    Text,
+}
+
+impl ScreenshotFormat {
+    /// Get the format usually used for files with the extension of the path given, i.e. `.png`
+    pub fn from_path(path: &Path) -> Option<ScreenshotFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        return match extension.as_str() {
+            "png" => Some(ScreenshotFormat::Png),
+            "ppm" => Some(ScreenshotFormat::Ppm),
+            "pbm" => Some(ScreenshotFormat::Pbm),
+            "txt" => Some(ScreenshotFormat::Text),
+            _ => None,
+        };
+    }
+    /// Get the extension for files in the format
+    pub fn get_extension(&self) -> &'static str {
+        return match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Ppm => "ppm",
+            ScreenshotFormat::Pbm => "pbm",
+            ScreenshotFormat::Text => "txt",
+        };
+    }
+}
+
+/**
+ * Settings for taking screenshots of the processor's screen.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+    /// RGB color of each combination of XO-CHIP bitplanes.
+    /// The first is the background, and the second the foreground of programs that only use one plane.
+    pub palette: [[u8; 3]; 4],
+    /// Width and height of each CHIP-8 pixel in the screenshot
+    pub scale: usize,
+}
+
+impl Default for Screenshot {
+    fn default() -> Self {
+        Screenshot {
+            palette: [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]],
+            scale: 1,
+        }
+    }
+}
+
+impl Screenshot {
+    pub fn new() -> Screenshot {
+        return Screenshot::default();
+    }
+    pub fn set_foreground(&mut self, color: [u8; 3]) {
+        self.palette[1] = color;
+    }
+    pub fn set_background(&mut self, color: [u8; 3]) {
+        self.palette[0] = color;
+    }
+    /// Get the width and height of a screenshot of the processor's screen
+    pub fn get_size(&self, p: &Processor) -> (usize, usize) {
+        let scale = self.scale.max(1);
+        return (p.get_screen_width() * scale, p.get_screen_height() * scale);
+    }
+    /**
+     * Take a screenshot of the processor's screen in the format given.
+     */
+    pub fn encode(&self, p: &Processor, format: ScreenshotFormat) -> Vec<u8> {
+        return match format {
+            ScreenshotFormat::Png => self.to_png(p),
+            ScreenshotFormat::Ppm => self.to_ppm(p),
+            ScreenshotFormat::Pbm => self.to_pbm(p).into_bytes(),
+            ScreenshotFormat::Text => self.to_text(p).into_bytes(),
+        };
+    }
+    /**
+     * Save a screenshot of the processor's screen to the path given,
+     * in the format given by the path's extension or as a PNG if it is unknown.
+     */
+    pub fn save(&self, p: &Processor, path: &Path) -> io::Result<()> {
+        let format = ScreenshotFormat::from_path(path).unwrap_or(ScreenshotFormat::Png);
+        return fs::write(path, self.encode(p, format));
+    }
+    pub fn to_png(&self, p: &Processor) -> Vec<u8> {
+        let (width, height) = self.get_size(p);
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        // Writing to memory can't fail
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.get_rgb(p)).unwrap();
+        writer.finish().unwrap();
+        return data;
+    }
+    pub fn to_ppm(&self, p: &Processor) -> Vec<u8> {
+        let (width, height) = self.get_size(p);
+        let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        data.extend(self.get_rgb(p));
+        return data;
+    }
+    pub fn to_pbm(&self, p: &Processor) -> String {
+        let (width, height) = self.get_size(p);
+        let mut image = format!("P1\n{} {}\n", width, height);
+        for row in self.get_pixels(p) {
+            let row: Vec<&str> = row
+                .iter()
+                .map(|c| if *c == 0 { "0" } else { "1" })
+                .collect();
+            image.push_str(&row.join(" "));
+            image.push('\n');
+        }
+        return image;
+    }
+    pub fn to_text(&self, p: &Processor) -> String {
+        let mut text = String::new();
+        for row in self.get_pixels(p) {
+            text.extend(row.iter().map(|c| GLYPHS[*c as usize]));
+            text.push('\n');
+        }
+        return text;
+    }
+    /**
+     * Get the screen stretched to the size given, as the index in the palette of each pixel, row by row.
+     * Used to keep the same size when the resolution changes, i.e. when recording.
+     */
+    pub fn get_frame(&self, p: &Processor, width: usize, height: usize) -> Vec<u8> {
+        let (screen_width, screen_height) = (p.get_screen_width(), p.get_screen_height());
+        let mut frame = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let y = y * screen_height / height;
+            frame
+                .extend((0..width).map(|x| {
+                    p.get_pixel_color_at((x * screen_width / width) as u8, y as u8) & 0x3
+                }));
+        }
+        return frame;
+    }
+    // Get the scaled rows of the screen, as the index in the palette of each pixel
+    fn get_pixels(&self, p: &Processor) -> Vec<Vec<u8>> {
+        let (width, height) = self.get_size(p);
+        return self
+            .get_frame(p, width, height)
+            .chunks(width)
+            .map(|row| row.to_vec())
+            .collect();
+    }
+    // Get the scaled screen as RGB bytes
+    fn get_rgb(&self, p: &Processor) -> Vec<u8> {
+        return self
+            .get_pixels(p)
+            .iter()
+            .flatten()
+            .flat_map(|c| self.palette[*c as usize])
+            .collect();
+    }
+}
+
+/**
+ * Parse a color written as `#RRGGBB` or `RRGGBB`.
+ */
+pub fn parse_color(s: &str) -> Option<[u8; 3]> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[(2 * i)..(2 * i + 2)], 16).ok();
+    return Some([channel(0)?, channel(1)?, channel(2)?]);
+}
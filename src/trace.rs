@@ -0,0 +1,343 @@
+#![allow(clippy::needless_return)]
+use crate::instruction::Instruction;
+use crate::processor::Processor;
+use serde_json::{json, Value};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+mod diff;
+pub use diff::{diff_processors, diff_traces, Divergence};
+
+/**
+ * Format a trace is written in.
+ */
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line of text per instruction
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/**
+ * A single instruction in a trace, along with the state of the processor after running it.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Number of instructions run before this one
+    pub step: u64,
+    /// Address of the instruction
+    pub pc: usize,
+    pub opcode: u16,
+    /// Disassembly of the instruction
+    pub instruction: String,
+    /// V0 through VF
+    pub registers: [u8; 0x10],
+    pub i: u16,
+    pub sp: usize,
+    pub dt: u8,
+    pub st: u8,
+    /// Address and new value of every byte of memory the instruction changed
+    pub memory: Vec<(usize, u8)>,
+}
+
+impl TraceEntry {
+    /**
+     * Record the state of the processor after it ran the instruction given,
+     * finding the memory it changed by comparing against the memory it could write to before it ran.
+     */
+    fn capture(
+        step: u64,
+        pc: usize,
+        opcode: u16,
+        p: &Processor,
+        memory_before: &[(usize, u8)],
+    ) -> TraceEntry {
+        let instruction = match Instruction::decode(opcode) {
+            Ok(i) => i.to_string(),
+            Err(_) => format!("DW 0x{:04X}", opcode),
+        };
+        let mut registers = [0; 0x10];
+        for (r, v) in registers.iter_mut().enumerate() {
+            *v = p.get_register_value(r as u8);
+        }
+        let mut memory: Vec<(usize, u8)> = memory_before
+            .iter()
+            .filter(|(addr, old)| p.get_mem_at(*addr) != *old)
+            .map(|(addr, _)| (*addr, p.get_mem_at(*addr)))
+            .collect();
+        memory.sort();
+        return TraceEntry {
+            step,
+            pc,
+            opcode,
+            instruction,
+            registers,
+            i: p.get_i(),
+            sp: p.get_stack_pointer(),
+            dt: p.get_dt(),
+            st: p.get_st(),
+            memory,
+        };
+    }
+    /// Get the entry as a JSON object
+    pub fn to_json(&self) -> Value {
+        let memory: Vec<Value> = self
+            .memory
+            .iter()
+            .map(|(addr, value)| json!({ "addr": addr, "value": value }))
+            .collect();
+        return json!({
+            "step": self.step,
+            "pc": self.pc,
+            "opcode": self.opcode,
+            "instruction": self.instruction,
+            "v": self.registers,
+            "i": self.i,
+            "sp": self.sp,
+            "dt": self.dt,
+            "st": self.st,
+            "memory": memory,
+        });
+    }
+    /// Read an entry from a JSON object written by `to_json`
+    pub fn from_json(value: &Value) -> Option<TraceEntry> {
+        let number = |name: &str| value[name].as_u64();
+        let mut registers = [0; 0x10];
+        let values = value["v"].as_array()?;
+        if values.len() != registers.len() {
+            return None;
+        }
+        for (r, v) in registers.iter_mut().zip(values.iter()) {
+            *r = u8::try_from(v.as_u64()?).ok()?;
+        }
+        let memory = value["memory"]
+            .as_array()?
+            .iter()
+            .map(|m| Some((m["addr"].as_u64()? as usize, m["value"].as_u64()? as u8)))
+            .collect::<Option<Vec<(usize, u8)>>>()?;
+        return Some(TraceEntry {
+            step: number("step")?,
+            pc: number("pc")? as usize,
+            opcode: number("opcode")? as u16,
+            instruction: value["instruction"].as_str()?.to_string(),
+            registers,
+            i: number("i")? as u16,
+            sp: number("sp")? as usize,
+            dt: number("dt")? as u8,
+            st: number("st")? as u8,
+            memory,
+        });
+    }
+}
+
+/**
+ * Text format of an entry, i.e.
+ * `00000003 204 7101 ADD V1, 0x01        V=12010000000000000000000000000000 I=345 SP=0 DT=00 ST=00 [347]=02`
+ */
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers: String = self
+            .registers
+            .iter()
+            .map(|r| format!("{:02X}", r))
+            .collect();
+        write!(
+            f,
+            "{:08} {:03X} {:04X} {:<20} V={} I={:03X} SP={:X} DT={:02X} ST={:02X}",
+            self.step,
+            self.pc,
+            self.opcode,
+            self.instruction,
+            registers,
+            self.i,
+            self.sp,
+            self.dt,
+            self.st
+        )?;
+        for (addr, value) in self.memory.iter() {
+            write!(f, " [{:03X}]={:02X}", addr, value)?;
+        }
+        return Ok(());
+    }
+}
+
+/**
+ * Parse an entry in the text format written by `Display`.
+ */
+impl FromStr for TraceEntry {
+    type Err = String;
+    fn from_str(s: &str) -> Result<TraceEntry, String> {
+        let invalid = || format!("Invalid trace entry '{}'", s);
+        let hex = |v: &str| usize::from_str_radix(v, 16).map_err(|_| invalid());
+        let (instruction, state) = s.split_once(" V=").ok_or_else(invalid)?;
+        let mut parts = instruction.splitn(4, ' ');
+        let mut next = || parts.next().ok_or_else(invalid);
+        let step = next()?.parse().map_err(|_| invalid())?;
+        let pc = hex(next()?)?;
+        let opcode = hex(next()?)? as u16;
+        let instruction = next()?.trim().to_string();
+        let mut fields = state.split(' ');
+        let values = fields.next().ok_or_else(invalid)?;
+        if values.len() != 32 {
+            return Err(invalid());
+        }
+        let mut registers = [0; 0x10];
+        for (r, v) in registers.iter_mut().enumerate() {
+            *v = hex(&values[(2 * r)..(2 * r + 2)])? as u8;
+        }
+        let mut field = |name: &str| match fields.next().and_then(|f| f.strip_prefix(name)) {
+            Some(v) => hex(v),
+            None => Err(invalid()),
+        };
+        let i = field("I=")? as u16;
+        let sp = field("SP=")?;
+        let dt = field("DT=")? as u8;
+        let st = field("ST=")? as u8;
+        let memory = fields
+            .map(|f| {
+                let (addr, value) = f
+                    .strip_prefix('[')
+                    .and_then(|f| f.split_once("]="))
+                    .ok_or_else(invalid)?;
+                return Ok((hex(addr)?, hex(value)? as u8));
+            })
+            .collect::<Result<Vec<(usize, u8)>, String>>()?;
+        return Ok(TraceEntry {
+            step,
+            pc,
+            opcode,
+            instruction,
+            registers,
+            i,
+            sp,
+            dt,
+            st,
+            memory,
+        });
+    }
+}
+
+/**
+ * Read a trace written by a `Tracer`, in either format.
+ */
+pub fn parse_trace(text: &str) -> Result<Vec<TraceEntry>, String> {
+    return text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(n, line)| {
+            let entry = if line.trim_start().starts_with('{') {
+                serde_json::from_str(line)
+                    .ok()
+                    .and_then(|v| TraceEntry::from_json(&v))
+                    .ok_or(format!("Invalid trace entry '{}'", line))
+            } else {
+                line.parse()
+            };
+            return entry.map_err(|e| format!("Line {}: {}", n + 1, e));
+        })
+        .collect();
+}
+
+/**
+ * Records every instruction the processor runs, along with the registers, timers and any memory it
+ * changed, in either a text or JSON lines format.
+ *
+ * `before_step` and `after_step` should be called around every call to `Processor::step`.
+ * Tracing can be limited to ranges of addresses, and to a maximum number of entries.
+ */
+pub struct Tracer<W: Write> {
+    out: W,
+    format: TraceFormat,
+    // Address ranges to trace, inclusive, or every address if empty
+    ranges: Vec<(usize, usize)>,
+    limit: Option<u64>,
+    written: u64,
+    step: u64,
+    // Address and opcode of the instruction about to run, if it is being traced
+    pending: Option<(usize, u16)>,
+    // Addresses the instruction can write to, and their values before it ran
+    memory: Vec<(usize, u8)>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W, format: TraceFormat) -> Tracer<W> {
+        return Tracer {
+            out,
+            format,
+            ranges: Vec::new(),
+            limit: None,
+            written: 0,
+            step: 0,
+            pending: None,
+            memory: Vec::new(),
+        };
+    }
+    /**
+     * Only trace instructions at addresses from `start` to `end` inclusive.
+     * Can be called more than once to trace several ranges.
+     */
+    pub fn add_range(&mut self, start: usize, end: usize) {
+        self.ranges.push((start, end));
+    }
+    /// Stop tracing after the number of entries given
+    pub fn set_limit(&mut self, limit: u64) {
+        self.limit = Some(limit);
+    }
+    /// Return whether the limit has been reached
+    pub fn is_full(&self) -> bool {
+        return self.limit.is_some_and(|l| self.written >= l);
+    }
+    /// Get the number of entries written so far
+    pub fn get_entries_written(&self) -> u64 {
+        return self.written;
+    }
+    /// Remember the state of the processor before it runs an instruction
+    pub fn before_step(&mut self, p: &Processor) {
+        let pc = p.get_program_counter();
+        let in_range =
+            self.ranges.is_empty() || self.ranges.iter().any(|(s, e)| (*s..=*e).contains(&pc));
+        if !in_range || self.is_full() || pc + 1 >= p.get_mem_size() {
+            self.pending = None;
+            return;
+        }
+        let opcode = p.get_word_at(pc);
+        self.pending = Some((pc, opcode));
+        self.memory = get_memory_written(p, opcode);
+    }
+    /// Write the instruction the processor ran since `before_step`, if it is being traced
+    pub fn after_step(&mut self, p: &Processor) -> io::Result<()> {
+        let step = self.step;
+        self.step += 1;
+        let (pc, opcode) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+        let entry = TraceEntry::capture(step, pc, opcode, p, &self.memory);
+        self.written += 1;
+        return match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", entry),
+            TraceFormat::Json => writeln!(self.out, "{}", entry.to_json()),
+        };
+    }
+    pub fn flush(&mut self) -> io::Result<()> {
+        return self.out.flush();
+    }
+}
+
+// Get every address the instruction can write to, along with its value before the instruction runs.
+// Only FX33, FX55 and XO-CHIP's 5XY2 write to memory, all starting at I.
+fn get_memory_written(p: &Processor, opcode: u16) -> Vec<(usize, u8)> {
+    let len = match Instruction::decode(opcode) {
+        Ok(Instruction::Bcd(_)) => 3,
+        Ok(Instruction::StoreRegs(x)) => x as usize + 1,
+        Ok(Instruction::SaveRange(x, y)) => x.abs_diff(y) as usize + 1,
+        _ => 0,
+    };
+    return (0..len)
+        .map(|j| (p.get_i() as usize + j) % p.get_mem_size())
+        .map(|addr| (addr, p.get_mem_at(addr)))
+        .collect();
+}
@@ -0,0 +1,186 @@
+#![allow(clippy::needless_return)]
+use super::{get_memory_written, TraceEntry};
+use crate::processor::Processor;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Most memory differences to show
+const MAX_MEMORY_SHOWN: usize = 32;
+
+/**
+ * The first point where two runs of a program differ.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of instructions both runs agreed on
+    pub index: usize,
+    /// The last instruction both runs agreed on
+    pub previous: Option<TraceEntry>,
+    /// The first instruction that differs on each side, or `None` if that run had already ended
+    pub left: Option<TraceEntry>,
+    pub right: Option<TraceEntry>,
+    /// Every byte of memory that differs, as the address and the left and right values.
+    /// A value is `None` if it isn't known, i.e. when it was never written in a trace.
+    pub memory: Vec<(usize, Option<u8>, Option<u8>)>,
+    /// Whether what is on the screen differs. Traces don't record the screen, so this is only set when
+    /// running processors side by side.
+    pub screen: bool,
+    /// Why either run stopped, if it was because of an error
+    pub error: Option<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |e: &Option<TraceEntry>| match e {
+            Some(e) => e.to_string(),
+            None => "<end of run>".to_string(),
+        };
+        writeln!(f, "Runs differ after {} matching instructions", self.index)?;
+        if let Some(previous) = &self.previous {
+            writeln!(f, "Last match: {}", previous)?;
+        }
+        writeln!(f, "Left:       {}", describe(&self.left))?;
+        writeln!(f, "Right:      {}", describe(&self.right))?;
+        if let Some(error) = &self.error {
+            writeln!(f, "{}", error)?;
+        }
+        if self.screen {
+            writeln!(f, "Screens differ")?;
+        }
+        if !self.memory.is_empty() {
+            writeln!(f, "Memory differences (address: left right):")?;
+            let byte = |b: &Option<u8>| match b {
+                Some(b) => format!("{:02X}", b),
+                None => "--".to_string(),
+            };
+            for (addr, left, right) in self.memory.iter().take(MAX_MEMORY_SHOWN) {
+                writeln!(f, "  {:03X}: {} {}", addr, byte(left), byte(right))?;
+            }
+            if self.memory.len() > MAX_MEMORY_SHOWN {
+                writeln!(f, "  and {} more", self.memory.len() - MAX_MEMORY_SHOWN)?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+/**
+ * Find the first entry where two traces differ, or `None` if they are the same.
+ * The memory of each side is rebuilt from the changes in the trace up to that entry.
+ */
+pub fn diff_traces(left: &[TraceEntry], right: &[TraceEntry]) -> Option<Divergence> {
+    let mut left_memory = BTreeMap::new();
+    let mut right_memory = BTreeMap::new();
+    for index in 0..left.len().max(right.len()) {
+        let (l, r) = (left.get(index), right.get(index));
+        if let Some(l) = l {
+            left_memory.extend(l.memory.iter().copied());
+        }
+        if let Some(r) = r {
+            right_memory.extend(r.memory.iter().copied());
+        }
+        if l == r {
+            continue;
+        }
+        let addresses: Vec<usize> = left_memory
+            .keys()
+            .chain(right_memory.keys())
+            .copied()
+            .collect();
+        let mut memory: Vec<(usize, Option<u8>, Option<u8>)> = addresses
+            .into_iter()
+            .map(|a| {
+                (
+                    a,
+                    left_memory.get(&a).copied(),
+                    right_memory.get(&a).copied(),
+                )
+            })
+            .filter(|(_, l, r)| l != r)
+            .collect();
+        memory.sort();
+        memory.dedup();
+        return Some(Divergence {
+            index,
+            previous: index.checked_sub(1).map(|i| left[i].clone()),
+            left: l.cloned(),
+            right: r.cloned(),
+            memory,
+            screen: false,
+            error: None,
+        });
+    }
+    return None;
+}
+
+/**
+ * Run two processors side by side, i.e. with different quirks, until their states or screens differ.
+ * Returns `None` if they never differ in `max_steps` instructions, or if both stop the same way.
+ * Timers are updated every `instructions_per_frame` instructions, and no keys are pressed.
+ */
+pub fn diff_processors(
+    left: &mut Processor,
+    right: &mut Processor,
+    max_steps: u64,
+    instructions_per_frame: u64,
+) -> Option<Divergence> {
+    let mut previous = None;
+    for step in 0..max_steps {
+        if step > 0 && step.is_multiple_of(instructions_per_frame.max(1)) {
+            for p in [&mut *left, &mut *right] {
+                p.on_tick();
+                p.on_v_blank();
+            }
+        }
+        let (l, left_written, left_error) = run_step(left, step);
+        let (r, right_written, right_error) = run_step(right, step);
+        // Memory only changes where an instruction writes, so there is no need to compare the rest
+        let written = left_written
+            .iter()
+            .chain(right_written.iter())
+            .any(|(addr, _)| left.get_memory().get(*addr) != right.get_memory().get(*addr));
+        let screen = left.screen_hash() != right.screen_hash();
+        if l != r || written || screen || left_error != right_error {
+            let memory: Vec<(usize, Option<u8>, Option<u8>)> = left
+                .get_memory()
+                .iter()
+                .zip(right.get_memory().iter())
+                .enumerate()
+                .filter(|(_, (l, r))| l != r)
+                .map(|(addr, (l, r))| (addr, Some(*l), Some(*r)))
+                .collect();
+            let errors: Vec<String> = [("Left", left_error), ("Right", right_error)]
+                .into_iter()
+                .filter_map(|(side, e)| Some(format!("{} stopped: {}", side, e?)))
+                .collect();
+            let error = Some(errors.join("\n")).filter(|e| !e.is_empty());
+            return Some(Divergence {
+                index: step as usize,
+                previous,
+                left: Some(l),
+                right: Some(r),
+                memory,
+                screen,
+                error,
+            });
+        }
+        if left_error.is_some() || left.has_exited() {
+            return None;
+        }
+        previous = Some(l);
+    }
+    return None;
+}
+
+// Run a single instruction, recording it along with the memory it could write to
+fn run_step(p: &mut Processor, step: u64) -> (TraceEntry, Vec<(usize, u8)>, Option<String>) {
+    let pc = p.get_program_counter();
+    let opcode = p.get_word_at(pc);
+    let memory = get_memory_written(p, opcode);
+    let error = p.step().err().map(|e| e.to_string());
+    return (
+        TraceEntry::capture(step, pc, opcode, p, &memory),
+        memory,
+        error,
+    );
+}
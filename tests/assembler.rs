@@ -0,0 +1,130 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::assembler::assemble_with_source_map;
+    use rust_chip8_opengl::{assemble, Instruction, Processor};
+
+    #[test]
+    fn test_assemble_program() {
+        let source = "
+            ; Compute the fibonacci sequence
+            count = 101
+            result = 0x400
+
+            start:  LD V0, 0x01
+                    LD V1, 1
+                    LD V2, 0b1
+                    LD V3, 0x01
+                    LD I, result
+            loop:
+                    LD [I], V0      ; Save V0 in memory
+                    LD V4, V0
+                    ADD V0, V1
+                    LD V1, V2
+                    ADD V3, 0x01
+                    SE V3, count
+                    JP loop
+            end:    JP end
+            data:   db 0x12, 0x34
+                    dw end, data + 1
+        ";
+        let rom = assemble(source).unwrap();
+        let expected: [u16; 16] = [
+            0x6001, 0x6101, 0x6201, 0x6301, 0xA400, 0xF055, 0x8400, 0x8014, 0x8120, 0x7301, 0x3365,
+            0x120A, 0x1218, 0x1234, 0x0218, 0x021B,
+        ];
+        let expected: Vec<u8> = expected.iter().flat_map(|w| w.to_be_bytes()).collect();
+        assert_eq!(rom, expected);
+        // And it can be run
+        let mut p = Processor::new();
+        p.load_program(&rom);
+        for _ in 0..20 {
+            p.step().unwrap();
+        }
+        assert_eq!(p.get_mem_at(0x400), 1);
+    }
+
+    #[test]
+    fn test_assemble_disassembly() {
+        // Every instruction should assemble back from its disassembly
+        for opcode in 0..=0xFFFF {
+            if let Ok(inst) = Instruction::decode(opcode) {
+                if inst == Instruction::LdILong {
+                    continue;
+                }
+                let rom = assemble(&inst.to_string()).unwrap();
+                assert_eq!(rom, opcode.to_be_bytes(), "{}", inst);
+            }
+        }
+        assert_eq!(
+            assemble("ld i, long target\ntarget: cls").unwrap(),
+            [0xF0, 0x00, 0x02, 0x04, 0x00, 0xE0]
+        );
+        assert_eq!(assemble("SHR V3").unwrap(), [0x83, 0x36]);
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let cases = [
+            ("CLS\nFOO V0", 2),
+            ("\n\nJP missing", 3),
+            ("LD V0, 0x100", 1),
+            ("a: CLS\na: CLS", 2),
+            ("DRW V0, V1", 1),
+            ("v1 = 2", 1),
+            ("LD V0, 1 +", 1),
+            ("LD I, é", 1),
+            ("LD I, é…", 1),
+            ("LD V0, 0x7FFFFFFFFFFFFFFF + 1", 1),
+            ("big = 0x7FFFFFFFFFFFFFFF\nLD V0, big + big", 2),
+            ("a = b\nb = 0x7FFFFFFFFFFFFFFF + 1", 2),
+            ("LD I, LONGé", 1),
+            ("a = b\nb = a", 1),
+            ("a = 1\nb = a + missing", 2),
+            ("a = b\nb = missing\nc = a", 2),
+        ];
+        for (source, line) in cases {
+            let e = assemble(source).unwrap_err();
+            assert_eq!(e.get_line(), line, "{}", e);
+            assert!(e.to_string().starts_with(&format!("Line {}: ", line)));
+        }
+    }
+
+    #[test]
+    fn test_assemble_constants() {
+        let source = "
+            ; Constants can use labels and constants defined after them
+            end_offset = end - start
+            size = end_offset + 2
+            back = -2
+                    LD V0, size
+                    LD V1, -back
+                    LD I, end + back
+            start:  CLS
+            end:    JP start - -2 - 2
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x60, 0x04, 0x61, 0x02, 0xA2, 0x06, 0x00, 0xE0, 0x12, 0x06]
+        );
+        // Negative values are still out of range
+        assert_eq!(
+            assemble("LD V0, -1").unwrap_err().to_string(),
+            "Line 1: Value -1 is out of range, minimum is 0"
+        );
+    }
+    #[test]
+    fn test_source_map() {
+        let source = "CLS\n\nloop:\n    LD I, LONG 0x1234\n    db 1, 2\n    JP loop ; comment\n";
+        let (rom, map) = assemble_with_source_map(source).unwrap();
+        assert_eq!(rom.len(), 10);
+        assert_eq!(map.get_address(1), Some(0x200));
+        // Lines without any code map to the next one with some
+        assert_eq!(map.get_address(2), Some(0x202));
+        assert_eq!(map.get_address(4), Some(0x202));
+        assert_eq!(map.get_address(5), Some(0x206));
+        assert_eq!(map.get_address(6), Some(0x208));
+        assert_eq!(map.get_address(7), None);
+        assert_eq!(map.get_line(0x208), Some(6));
+        assert_eq!(map.get_line(0x204), None);
+    }
+}
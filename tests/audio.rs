@@ -0,0 +1,203 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::audio::{
+        AudioBackend, FileBackend, NullBackend, Synth, Tone, WavBackend, Waveform, SAMPLE_RATE,
+    };
+    use rust_chip8_opengl::interfaces::HeadlessInterface;
+    use rust_chip8_opengl::{assemble, Emulator, Processor, Variant};
+    use std::fs::{self, File};
+    use std::io::BufWriter;
+
+    // Samples in a frame
+    const FRAME: usize = (SAMPLE_RATE / 60) as usize;
+
+    fn get_samples(bytes: &[u8]) -> Vec<i16> {
+        return bytes
+            .chunks(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect();
+    }
+
+    fn square(frequency: f32) -> Tone {
+        return Tone {
+            frequency,
+            volume: 1.0,
+            waveform: Waveform::Square,
+        };
+    }
+
+    // Update the backend and the timers once a frame, like the emulator
+    fn run_frame(audio: &mut dyn AudioBackend, p: &mut Processor) {
+        audio.update(p).unwrap();
+        p.on_tick();
+    }
+
+    #[test]
+    fn test_file_backend() {
+        let mut p = Processor::new();
+        let mut audio = FileBackend::new(Vec::new(), square(1000.0));
+        // Silent until the sound timer is set
+        run_frame(&mut audio, &mut p);
+        p.set_st(2);
+        // Updating more than once a frame doesn't change anything
+        audio.update(&p).unwrap();
+        run_frame(&mut audio, &mut p);
+        run_frame(&mut audio, &mut p);
+        run_frame(&mut audio, &mut p);
+        // Nothing is written until the frame's tick
+        audio.update(&p).unwrap();
+        assert_eq!(audio.get_samples(), FRAME as u64 * 4);
+        let samples = get_samples(&audio.into_inner().unwrap());
+        assert_eq!(samples.len(), FRAME * 4);
+        assert!(samples[..FRAME].iter().all(|s| *s == 0));
+        // Plays for exactly as many frames as the sound timer was set to, then fades out
+        assert_ne!(samples[FRAME], 0);
+        assert!(samples[(FRAME + 100)..(FRAME * 3)]
+            .iter()
+            .all(|s| s.abs() == i16::MAX));
+        assert!(samples[FRAME * 3].abs() < i16::MAX);
+        assert!(samples[(FRAME * 3 + 300)..].iter().all(|s| *s == 0));
+    }
+
+    #[test]
+    fn test_file_backend_pattern() {
+        let mut p = Processor::new_with_variant(Variant::XoChip);
+        p.load_program_u16(&[0xA300, 0xF002]);
+        p.step().unwrap();
+        p.step().unwrap();
+        p.set_st(1);
+        let mut audio = FileBackend::new(Vec::new(), square(1000.0));
+        run_frame(&mut audio, &mut p);
+        audio.update(&p).unwrap();
+        // An empty pattern plays a constant low level rather than the tone, once faded in
+        let samples = get_samples(&audio.into_inner().unwrap());
+        assert_eq!(samples.len(), FRAME);
+        assert!(samples[100..].iter().all(|s| *s == -i16::MAX));
+    }
+
+    // Beeps for 5 frames, then for a frame using an XO-CHIP pattern, then halts
+    const PROGRAM: &str = "
+            LD V0, 5
+            LD ST, V0
+            LD DT, V0
+    wait:   LD V1, DT
+            SE V1, 0
+            JP wait
+            LD I, pattern
+            AUDIO
+            LD V0, 1
+            LD ST, V0
+    halt:   JP halt
+    pattern:
+            DB 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00
+            DB 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00
+    ";
+
+    // Run the program headless, writing a WAV file, and return the file
+    fn record_wav(name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("rust-chip8-audio-{}.wav", name));
+        let out = BufWriter::new(File::create(&path).unwrap());
+        let mut p = Processor::new_with_variant(Variant::XoChip);
+        p.load_program(&assemble(PROGRAM).unwrap());
+        let mut headless = HeadlessInterface::new();
+        headless.set_audio(Box::new(WavBackend::new(out, square(1000.0)).unwrap()));
+        headless.set_max_frames(20);
+        let mut emulator = Emulator::new(p, Box::new(headless));
+        emulator.set_paced(false);
+        emulator.run().unwrap();
+        emulator.exit();
+        return fs::read(path).unwrap();
+    }
+
+    #[test]
+    fn test_wav() {
+        let wav = record_wav("a");
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[36..40], b"data");
+        let data_size = u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize;
+        assert_eq!(wav.len(), 44 + data_size);
+        let riff_size = u32::from_le_bytes(wav[4..8].try_into().unwrap()) as usize;
+        assert_eq!(riff_size, wav.len() - 8);
+        // A frame of samples for each of the 20 frames
+        let samples = get_samples(&wav[44..]);
+        assert_eq!(samples.len(), FRAME * 20);
+        // The tone starts at the start of a frame
+        let start = samples.iter().position(|s| *s != 0).unwrap();
+        assert_eq!(start % FRAME, 0);
+        let tone_end = start + FRAME * 5;
+        assert!(samples[(start + 100)..tone_end]
+            .iter()
+            .all(|s| s.abs() == i16::MAX));
+        // The pattern is set in the frame the tone ends, so follows straight on from it
+        let pattern_start = tone_end;
+        // The pattern plays at its own pitch, so it doesn't look like the tone
+        let pattern = &samples[pattern_start..(pattern_start + FRAME)];
+        assert!(pattern.iter().all(|s| s.abs() == i16::MAX));
+        assert_ne!(&pattern[..200], &samples[(start + 100)..(start + 300)]);
+        assert!(samples[(pattern_start + FRAME + 300)..]
+            .iter()
+            .all(|s| *s == 0));
+        // The same run always writes the same file
+        assert_eq!(record_wav("b"), wav);
+    }
+
+    #[test]
+    fn test_envelope() {
+        // Low enough that the wave stays high throughout
+        let mut synth = Synth::new(square(10.0));
+        synth.set_playing(true);
+        let attack: Vec<f32> = (0..200).map(|_| synth.next_sample()).collect();
+        // Ramps up instead of jumping straight to full volume
+        assert!(attack[0] > 0.0 && attack[0] < 0.1);
+        assert!(attack.windows(2).all(|w| w[1] >= w[0]));
+        assert_eq!(attack[199], 1.0);
+        synth.set_playing(false);
+        let release: Vec<f32> = (0..400).map(|_| synth.next_sample()).collect();
+        assert!(release.windows(2).all(|w| w[1] <= w[0]));
+        assert!(release[0] > 0.9);
+        assert_eq!(release[399], 0.0);
+    }
+
+    #[test]
+    fn test_waveforms() {
+        let get_wave = |waveform: Waveform| {
+            let mut synth = Synth::new(Tone {
+                frequency: SAMPLE_RATE as f32 / 400.0,
+                volume: 0.5,
+                waveform,
+            });
+            synth.set_playing(true);
+            // Skip the attack
+            (0..400).for_each(|_| {
+                synth.next_sample();
+            });
+            return (0..400).map(|_| synth.next_sample()).collect::<Vec<f32>>();
+        };
+        let square = get_wave(Waveform::Square);
+        // Rounding can move the edges by a sample
+        assert!(square[1..199].iter().all(|s| *s == 0.5));
+        assert!(square[201..399].iter().all(|s| *s == -0.5));
+        let sine = get_wave(Waveform::Sine);
+        assert!((sine[100] - 0.5).abs() < 1e-3);
+        assert!((sine[300] + 0.5).abs() < 1e-3);
+        let triangle = get_wave(Waveform::Triangle);
+        assert!((triangle[0] + 0.5).abs() < 1e-3);
+        assert!((triangle[200] - 0.5).abs() < 1e-3);
+        // Noise holds each level for half a period
+        let noise = get_wave(Waveform::Noise);
+        assert!(noise.iter().all(|s| s.abs() <= 0.5));
+        assert!(noise[2..198].iter().all(|s| *s == noise[2]));
+        assert_ne!(noise[2], noise[202]);
+        assert_eq!(get_wave(Waveform::Noise), noise);
+    }
+
+    #[test]
+    fn test_null_backend() {
+        let mut p = Processor::new();
+        p.set_st(10);
+        let mut audio = NullBackend;
+        assert!(audio.update(&p).is_ok());
+        assert!(audio.finish().is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::dap::{read_message, DapSession};
+    use rust_chip8_opengl::{Quirks, Variant};
+    use serde_json::{json, Value};
+    use std::cell::RefCell;
+    use std::fs;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    // Output shared between the session and the test
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            return Ok(buf.len());
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    struct Client {
+        session: DapSession<Output>,
+        output: Output,
+        seq: i64,
+    }
+
+    impl Client {
+        fn new() -> Client {
+            let output = Output::default();
+            return Client {
+                session: DapSession::new(output.clone()),
+                output,
+                seq: 0,
+            };
+        }
+        // Take every message sent since the last call
+        fn take_messages(&mut self) -> Vec<Value> {
+            let data = self.output.0.replace(Vec::new());
+            let mut reader = data.as_slice();
+            let mut messages = Vec::new();
+            while let Some(m) = read_message(&mut reader).unwrap() {
+                messages.push(m);
+            }
+            return messages;
+        }
+        // Send a request and return its response
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            self.session.handle(&request).unwrap();
+            let messages = self.take_messages();
+            let response = messages.iter().find(|m| m["type"] == "response").unwrap();
+            assert_eq!(response["request_seq"], self.seq);
+            return response.clone();
+        }
+        // Run until the program stops, returning the stopped event
+        fn wait_for_stop(&mut self) -> Value {
+            for _ in 0..1000 {
+                self.session.run_frame().unwrap();
+                let messages = self.take_messages();
+                if let Some(event) = messages.iter().find(|m| m["event"] == "stopped") {
+                    return event.clone();
+                }
+            }
+            panic!("Program never stopped");
+        }
+        fn get_pc(&self) -> usize {
+            return self.session.get_processor().unwrap().get_program_counter();
+        }
+    }
+
+    fn write_temp_file(name: &str, contents: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("rust-chip8-dap-{}", name));
+        fs::write(&path, contents).unwrap();
+        return path.to_str().unwrap().to_string();
+    }
+
+    const SOURCE: &str = "LD V0, 0x12
+LD I, 0x345
+loop:
+    ADD V1, 1
+    LD B, V1
+    JP loop
+";
+
+    #[test]
+    fn test_source_breakpoints_and_stepping() {
+        let path = write_temp_file("source.asm", SOURCE.as_bytes());
+        let mut c = Client::new();
+        let response = c.request("initialize", json!({ "adapterID": "chip8" }));
+        assert_eq!(response["body"]["supportsInstructionBreakpoints"], true);
+        assert_eq!(
+            c.request("launch", json!({ "program": path }))["success"],
+            true
+        );
+        // The label's line moves to the next instruction
+        let response = c.request(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [{ "line": 3 }] }),
+        );
+        assert_eq!(response["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(response["body"]["breakpoints"][0]["line"], 4);
+        c.request("configurationDone", json!({}));
+
+        let stopped = c.wait_for_stop();
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+        assert_eq!(c.get_pc(), 0x204);
+        let trace = c.request("stackTrace", json!({ "threadId": 1 }));
+        let frame = &trace["body"]["stackFrames"][0];
+        assert_eq!(frame["line"], 4);
+        assert_eq!(frame["source"]["path"], path.as_str());
+        assert_eq!(frame["instructionPointerReference"], "0x204");
+
+        assert_eq!(c.request("next", json!({ "threadId": 1 }))["success"], true);
+        assert_eq!(c.wait_for_stop()["body"]["reason"], "step");
+        assert_eq!(c.get_pc(), 0x206);
+
+        // Swap the source breakpoint for one on an address
+        c.request(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [] }),
+        );
+        let response = c.request(
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x206", "offset": 2 }] }),
+        );
+        assert_eq!(response["body"]["breakpoints"][0]["verified"], true);
+        c.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(c.wait_for_stop()["body"]["reason"], "breakpoint");
+        assert_eq!(c.get_pc(), 0x208);
+
+        c.request("disconnect", json!({}));
+        assert!(c.session.is_terminated());
+    }
+
+    #[test]
+    fn test_variables() {
+        let path = write_temp_file("variables.asm", SOURCE.as_bytes());
+        let mut c = Client::new();
+        c.request("initialize", json!({}));
+        c.request("launch", json!({ "program": path, "stopOnEntry": true }));
+        c.request("configurationDone", json!({}));
+        assert!(!c.session.is_running());
+        c.request("stepIn", json!({ "threadId": 1 }));
+        assert_eq!(c.wait_for_stop()["body"]["reason"], "step");
+
+        let scopes = c.request("scopes", json!({ "frameId": 0 }));
+        let scopes = scopes["body"]["scopes"].as_array().unwrap().clone();
+        let names: Vec<&str> = scopes.iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["Registers", "Stack", "Memory"]);
+
+        let reference = &scopes[0]["variablesReference"];
+        let registers = c.request("variables", json!({ "variablesReference": reference }));
+        let registers = &registers["body"]["variables"];
+        assert_eq!(registers[0]["name"], "V0");
+        assert_eq!(registers[0]["value"], "0x12");
+        assert_eq!(registers[17]["name"], "PC");
+        assert_eq!(registers[17]["value"], "0x202");
+
+        let reference = &scopes[2]["variablesReference"];
+        let memory = c.request(
+            "variables",
+            json!({ "variablesReference": reference, "start": 0x20, "count": 1 }),
+        );
+        let memory = &memory["body"]["variables"];
+        assert_eq!(memory.as_array().unwrap().len(), 1);
+        assert_eq!(memory[0]["name"], "0x200");
+        assert!(memory[0]["value"]
+            .as_str()
+            .unwrap()
+            .starts_with("60 12 A3 45"));
+    }
+
+    #[test]
+    fn test_invalid_opcode_is_exception() {
+        // 5XY1 is only valid on XO-CHIP
+        let path = write_temp_file("invalid.ch8", &[0x60, 0x01, 0x50, 0x01]);
+        let mut c = Client::new();
+        c.request("initialize", json!({}));
+        c.request("launch", json!({ "program": path }));
+        c.request("configurationDone", json!({}));
+        let stopped = c.wait_for_stop();
+        assert_eq!(stopped["body"]["reason"], "exception");
+        assert!(stopped["body"]["text"].as_str().unwrap().contains("5001"));
+        let response = c.request("launch", json!({ "program": "missing.ch8" }));
+        assert_eq!(response["success"], false);
+    }
+
+    #[test]
+    fn test_launch_settings() {
+        // ADD V1, 1 then JP 0x200
+        let path = write_temp_file("settings.ch8", &[0x71, 0x01, 0x12, 0x00]);
+        let mut c = Client::new();
+        c.request("initialize", json!({}));
+        let response = c.request(
+            "launch",
+            json!({
+                "program": path,
+                "variant": "schip",
+                "quirks": { "display_wait": true },
+                "ipf": 4,
+            }),
+        );
+        assert_eq!(response["success"], true);
+        c.request("configurationDone", json!({}));
+        let p = c.session.get_processor().unwrap();
+        assert_eq!(p.get_variant(), Variant::SuperChip);
+        assert_eq!(
+            p.get_quirks(),
+            Quirks {
+                display_wait: true,
+                ..Quirks::schip()
+            }
+        );
+        c.session.run_frame().unwrap();
+        let p = c.session.get_processor().unwrap();
+        assert_eq!(p.get_register_value(1), 2);
+
+        // Instructions per second are rounded to whole instructions per frame
+        c.request("launch", json!({ "program": path, "ips": 230 }));
+        c.request("configurationDone", json!({}));
+        c.session.run_frame().unwrap();
+        let p = c.session.get_processor().unwrap();
+        assert_eq!(p.get_register_value(1), 2);
+
+        for args in [
+            json!({ "program": path, "variant": "chip9" }),
+            json!({ "program": path, "quirks": "octo" }),
+            json!({ "program": path, "quirks": { "display_wait": 1 } }),
+        ] {
+            assert_eq!(c.request("launch", args)["success"], false);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::debugger::{Condition, Register, StopReason};
+    use rust_chip8_opengl::{assemble, Debugger, Processor};
+
+    const PROGRAM: &str = "
+        start:  LD V0, 0
+        loop:   ADD V0, 1
+                CALL store
+                JP loop
+        store:  LD I, 0x400
+                CALL inner
+                LD [I], V0
+                RET
+        inner:  ADD V1, 2
+                RET
+    ";
+
+    fn setup() -> (Processor, Debugger) {
+        let mut p = Processor::new();
+        p.load_program(&assemble(PROGRAM).unwrap());
+        return (p, Debugger::new());
+    }
+
+    // Run until the debugger pauses
+    fn run(p: &mut Processor, d: &mut Debugger) -> StopReason {
+        for _ in 0..1000 {
+            if let Some(reason) = d.step(p) {
+                assert!(d.is_paused());
+                return reason;
+            }
+        }
+        panic!("Debugger never paused");
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let (mut p, mut d) = setup();
+        d.add_breakpoint(0x208);
+        assert_eq!(run(&mut p, &mut d), StopReason::Breakpoint(0x208));
+        assert_eq!(p.get_program_counter(), 0x208);
+        // Doesn't run while paused
+        assert_eq!(d.step(&mut p), None);
+        assert_eq!(p.get_program_counter(), 0x208);
+        d.resume();
+        assert_eq!(run(&mut p, &mut d), StopReason::Breakpoint(0x208));
+        assert_eq!(p.get_register_value(0), 2);
+        d.remove_breakpoint(0x208);
+        d.resume();
+        for _ in 0..100 {
+            assert_eq!(d.step(&mut p), None);
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_at_start() {
+        let (mut p, mut d) = setup();
+        d.add_breakpoint(0x200);
+        // Stops before anything runs
+        assert_eq!(d.step(&mut p), Some(StopReason::Breakpoint(0x200)));
+        assert_eq!(p.get_program_counter(), 0x200);
+        // Resuming runs the instruction instead of stopping again
+        d.resume();
+        assert_eq!(d.step(&mut p), None);
+        assert_eq!(p.get_program_counter(), 0x202);
+        // Stepping onto a breakpoint stops for the step, then continuing runs from it
+        d.add_breakpoint(0x204);
+        d.step_into();
+        assert_eq!(d.step(&mut p), Some(StopReason::Step));
+        d.resume();
+        assert_eq!(d.step(&mut p), None);
+        assert_eq!(p.get_program_counter(), 0x208);
+    }
+
+    #[test]
+    fn test_watchpoint() {
+        let (mut p, mut d) = setup();
+        d.add_watchpoint(0x400);
+        assert_eq!(
+            run(&mut p, &mut d),
+            StopReason::Watchpoint {
+                address: 0x400,
+                old: 0,
+                new: 1
+            }
+        );
+        // Just after LD [I], V0
+        assert_eq!(p.get_program_counter(), 0x20E);
+        d.resume();
+        // Writing the same value doesn't count
+        d.add_watchpoint(0x401);
+        assert_eq!(
+            run(&mut p, &mut d),
+            StopReason::Watchpoint {
+                address: 0x400,
+                old: 1,
+                new: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_condition() {
+        let (mut p, mut d) = setup();
+        let condition: Condition = "V1 >= 0x06".parse().unwrap();
+        assert_eq!(condition.register, Register::V(1));
+        d.add_condition(condition);
+        assert_eq!(run(&mut p, &mut d), StopReason::Condition(condition));
+        assert_eq!(p.get_register_value(1), 6);
+        // Only breaks again once the condition stops being true and becomes true again
+        d.resume();
+        for _ in 0..100 {
+            assert_eq!(d.step(&mut p), None);
+        }
+        assert!("V1 ~ 2".parse::<Condition>().is_err());
+        assert!("VG == 2".parse::<Condition>().is_err());
+        assert!("I == x".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_stepping() {
+        let (mut p, mut d) = setup();
+        d.pause();
+        d.step_into();
+        assert_eq!(d.step(&mut p), Some(StopReason::Step));
+        assert_eq!(p.get_program_counter(), 0x202);
+        d.step_into();
+        assert_eq!(d.step(&mut p), Some(StopReason::Step));
+        assert_eq!(p.get_program_counter(), 0x204);
+        // Step over the call to store, which calls inner
+        d.step_over(&p);
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        assert_eq!(p.get_program_counter(), 0x206);
+        assert_eq!(p.get_stack_pointer(), 0);
+        assert_eq!(p.get_register_value(1), 2);
+        // Step over instructions that aren't calls back to the call to store
+        d.step_over(&p);
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        d.step_over(&p);
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        assert_eq!(p.get_program_counter(), 0x204);
+        // Step into store and inner, then step out of both
+        d.step_into();
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        d.step_into();
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        d.step_into();
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        assert_eq!(p.get_program_counter(), 0x210);
+        assert_eq!(p.get_stack(), &[0x204, 0x20A]);
+        d.step_out(&p);
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        assert_eq!(p.get_program_counter(), 0x20C);
+        d.step_out(&p);
+        assert_eq!(run(&mut p, &mut d), StopReason::Step);
+        assert_eq!(p.get_program_counter(), 0x206);
+        assert_eq!(p.get_stack_pointer(), 0);
+    }
+
+    #[test]
+    fn test_pause_on_error() {
+        let mut p = Processor::new();
+        p.load_program_u16(&[0x00EE]);
+        let mut d = Debugger::new();
+        assert!(matches!(run(&mut p, &mut d), StopReason::Error(_)));
+    }
+}
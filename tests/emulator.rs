@@ -0,0 +1,111 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::debugger::StopReason;
+    use rust_chip8_opengl::interfaces::HeadlessInterface;
+    use rust_chip8_opengl::{assemble, Emulator, EmulatorError, Processor, Variant};
+    use std::time::{Duration, Instant};
+
+    // Counts instructions in I, and frames using the delay timer
+    const PROGRAM: &str = "
+            LD V0, 0xFF
+            LD DT, V0
+            LD V1, 1
+    loop:   ADD I, V1
+            JP loop
+    ";
+
+    fn create(program: &str) -> Emulator {
+        let mut p = Processor::new();
+        p.load_program(&assemble(program).unwrap());
+        let mut emulator = Emulator::new(p, Box::new(HeadlessInterface::new()));
+        emulator.set_paced(false);
+        return emulator;
+    }
+
+    #[test]
+    fn test_run_for() {
+        let mut emulator = create(PROGRAM);
+        emulator.set_instructions_per_frame(1000 / 60);
+        emulator.run_for(Duration::from_secs(2)).unwrap();
+        assert_eq!(emulator.get_frames(), 120);
+        let p = emulator.get_processor();
+        assert_eq!(p.get_dt(), 0xFF - 120);
+        // Half of the 1917 instructions after the first three are ADDs, starting with one
+        assert_eq!(p.get_i(), 959);
+    }
+
+    #[test]
+    fn test_instructions_per_frame() {
+        let mut emulator = create("loop: ADD V0, 1\nJP loop");
+        emulator.set_instructions_per_frame(10);
+        for _ in 0..6 {
+            emulator.run_frame();
+        }
+        // Half of the 60 instructions are ADDs
+        assert_eq!(emulator.get_processor().get_register_value(0), 30);
+        // 500 instructions a second round to 8 a frame
+        emulator.set_instructions_per_second(500);
+        assert_eq!(emulator.get_instructions_per_frame(), 8);
+        emulator.set_instructions_per_second(1);
+        assert_eq!(emulator.get_instructions_per_frame(), 1);
+    }
+
+    #[test]
+    fn test_speed() {
+        let mut emulator = create(PROGRAM);
+        emulator.set_instructions_per_frame(5);
+        assert_eq!(
+            emulator.get_scheduler().get_frame_time(),
+            Duration::from_secs(1) / 60
+        );
+        assert_eq!(emulator.get_scheduler().get_speed(), None);
+        // Unpaced frames run as fast as possible, so the speed is measured however long it takes
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(1100) {
+            emulator.run_frame();
+        }
+        let speed = emulator.get_scheduler().get_speed().unwrap();
+        assert!(speed.frames_per_second > 60.0);
+        let ratio = speed.instructions_per_second / speed.frames_per_second;
+        assert!((ratio - 5.0).abs() < 1e-9);
+        assert_eq!(emulator.get_scheduler().get_dropped_frames(), 0);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut emulator = create(PROGRAM);
+        emulator.get_debugger_mut().add_breakpoint(0x206);
+        assert_eq!(emulator.run_frame(), Some(StopReason::Breakpoint(0x206)));
+        // The frame isn't finished until the debugger resumes
+        assert_eq!(emulator.get_frames(), 0);
+        emulator.run_frame();
+        assert_eq!(emulator.get_frames(), 0);
+        emulator.get_debugger_mut().resume();
+        emulator.get_debugger_mut().clear();
+        emulator.run_frame();
+        assert_eq!(emulator.get_frames(), 1);
+    }
+
+    #[test]
+    fn test_run() {
+        let mut p = Processor::new_with_variant(Variant::SuperChip);
+        p.load_program(&assemble("LD V0, 1\nEXIT").unwrap());
+        let mut emulator = Emulator::new(p, Box::new(HeadlessInterface::new()));
+        emulator.set_paced(false);
+        emulator.run().unwrap();
+        assert!(emulator.is_finished());
+        assert_eq!(emulator.get_processor().get_register_value(0), 1);
+
+        let mut p = Processor::new();
+        p.load_program_u16(&[0x6001, 0x5121]);
+        let mut emulator = Emulator::new(p, Box::new(HeadlessInterface::new()));
+        emulator.set_paced(false);
+        assert_eq!(
+            emulator.run(),
+            Err(EmulatorError::InvalidOpcode {
+                opcode: 0x5121,
+                pc: 0x202
+            })
+        );
+    }
+}
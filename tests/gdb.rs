@@ -0,0 +1,137 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::gdb::GdbServer;
+    use rust_chip8_opengl::{assemble, Debugger, Processor};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    struct Session {
+        server: GdbServer,
+        client: TcpStream,
+        p: Processor,
+        d: Debugger,
+    }
+
+    impl Session {
+        fn new(program: &str) -> Session {
+            let mut p = Processor::new();
+            p.load_program(&assemble(program).unwrap());
+            let mut server = GdbServer::bind(0).unwrap();
+            let mut d = Debugger::new();
+            let client = TcpStream::connect(("127.0.0.1", server.get_port())).unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_millis(10)))
+                .unwrap();
+            while !server.is_connected() {
+                server.poll(&mut p, &mut d).unwrap();
+            }
+            assert!(d.is_paused());
+            return Session {
+                server,
+                client,
+                p,
+                d,
+            };
+        }
+        // Run the emulator until the next reply packet arrives
+        fn receive(&mut self) -> String {
+            let mut data = Vec::new();
+            for _ in 0..1000 {
+                self.server.poll(&mut self.p, &mut self.d).unwrap();
+                if let Some(reason) = self.d.step(&mut self.p) {
+                    self.server.on_stop(&reason).unwrap();
+                }
+                let mut buf = [0; 1024];
+                if let Ok(n) = self.client.read(&mut buf) {
+                    data.extend_from_slice(&buf[..n]);
+                }
+                let text = String::from_utf8_lossy(&data).to_string();
+                let text = text.trim_start_matches('+');
+                if let (Some(start), Some(end)) = (text.find('$'), text.find('#')) {
+                    if text.len() >= end + 3 {
+                        return text[(start + 1)..end].to_string();
+                    }
+                }
+            }
+            panic!("No reply");
+        }
+        fn request(&mut self, packet: &str) -> String {
+            let checksum = packet.bytes().fold(0u8, |a, b| a.wrapping_add(b));
+            write!(self.client, "${}#{:02x}", packet, checksum).unwrap();
+            return self.receive();
+        }
+    }
+
+    const PROGRAM: &str = "
+            LD V0, 0x12
+            LD I, 0x345
+    loop:   ADD V1, 1
+            LD B, V1
+            JP loop
+    ";
+
+    #[test]
+    fn test_registers_and_memory() {
+        let mut s = Session::new(PROGRAM);
+        assert!(s
+            .request("qSupported:swbreak+")
+            .contains("qXfer:features:read+"));
+        assert!(s
+            .request("qXfer:features:read:target.xml:0,fff")
+            .starts_with("l<?xml"));
+        assert_eq!(s.request("?"), "S05");
+        // V0-VF, I, PC, SP, DT, ST
+        let regs = s.request("g");
+        assert_eq!(regs.len(), 2 * (16 + 2 + 2 + 3));
+        assert_eq!(&regs[32..40], "00000002");
+        assert_eq!(s.request("p11"), "0002");
+        assert_eq!(s.request("m200,4"), "6012a345");
+        assert_eq!(s.request("M400,2:abcd"), "OK");
+        assert_eq!(s.p.get_mem_at(0x401), 0xCD);
+        assert_eq!(s.request("P3=7f"), "OK");
+        assert_eq!(s.p.get_register_value(3), 0x7F);
+        // Values have to be exactly the size of the register
+        assert_eq!(s.request("P11=ffffffffffffffff"), "E01");
+        assert_eq!(s.request("P3=7f00"), "E01");
+        assert_eq!(s.request("P15=00"), "E01");
+        assert_eq!(s.request(&format!("G{}00", regs)), "E01");
+        assert_eq!(s.request(&format!("G{}", &regs[2..])), "E01");
+        assert_eq!(s.p.get_register_value(3), 0x7F);
+        assert_eq!(s.request(&format!("G{}", regs)), "OK");
+        assert_eq!(s.p.get_register_value(3), 0);
+        assert_eq!(s.request("m1000,1"), "E01");
+        assert_eq!(s.request("mffffffffffffffff,10"), "E01");
+        assert_eq!(s.request("Mffffffffffffffff,1:00"), "E01");
+        assert_eq!(s.request("vMustReplyEmpty"), "");
+    }
+
+    #[test]
+    fn test_breakpoints_and_stepping() {
+        let mut s = Session::new(PROGRAM);
+        assert_eq!(s.request("s"), "S05");
+        assert_eq!(s.p.get_program_counter(), 0x202);
+        assert_eq!(s.p.get_register_value(0), 0x12);
+        assert_eq!(s.request("Z0,206,2"), "OK");
+        assert_eq!(s.request("c"), "S05");
+        assert_eq!(s.p.get_program_counter(), 0x206);
+        assert_eq!(s.request("c"), "S05");
+        assert_eq!(s.p.get_register_value(1), 2);
+        assert_eq!(s.request("z0,206,2"), "OK");
+        // Watch the BCD ones digit
+        assert_eq!(s.request("Z2,347,1"), "OK");
+        assert_eq!(s.request("c"), "T05watch:347;");
+        assert_eq!(s.p.get_mem_at(0x347), 2);
+        // Interrupt
+        assert_eq!(s.request("z2,347,1"), "OK");
+        assert_eq!(s.request("Z2,ffffffffffffffff,10"), "E01");
+        assert_eq!(s.request("z2,ffffffffffffffff,10"), "E01");
+        write!(s.client, "$c#63").unwrap();
+        s.client.write_all(&[0x03]).unwrap();
+        assert_eq!(s.receive(), "S02");
+        assert!(s.d.is_paused());
+        // Detaching resumes
+        s.request("D");
+        assert!(!s.d.is_paused());
+    }
+}
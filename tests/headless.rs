@@ -0,0 +1,159 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::interfaces::{Finish, HeadlessInterface, Interface};
+    use rust_chip8_opengl::{assemble, Emulator, Processor};
+    use std::fs;
+
+    // Run like the emulator does, a frame every 8 instructions, until the interface finishes
+    fn run(interface: &mut HeadlessInterface, program: &str) -> Processor {
+        let mut p = Processor::new();
+        p.load_program(&assemble(program).unwrap());
+        for i in 1..100_000 {
+            p.step().unwrap();
+            if interface.update(&mut p) {
+                return p;
+            }
+            if i % 8 == 0 {
+                p.on_tick();
+                interface.render(&p);
+                p.on_v_blank();
+            }
+        }
+        panic!("Never finished");
+    }
+
+    const PROGRAM: &str = "
+            LD V0, 5
+            LD F, V0
+            LD V1, 1
+            DRW V1, V1, 5
+    loop:   ADD V2, 1
+            SE V2, 0xFF
+            JP loop
+    halt:   JP halt
+    ";
+
+    #[test]
+    fn test_frames() {
+        let mut h = HeadlessInterface::new();
+        h.set_max_frames(3);
+        run(&mut h, PROGRAM);
+        assert_eq!(h.get_finish(), Some(Finish::Frames));
+        assert_eq!(h.get_frames(), 3);
+        assert_eq!(h.get_exit_code(), 0);
+    }
+
+    #[test]
+    fn test_conditions_and_halt() {
+        let mut h = HeadlessInterface::new();
+        h.add_condition("V2 == 0x10".parse().unwrap());
+        let p = run(&mut h, PROGRAM);
+        assert!(matches!(h.get_finish(), Some(Finish::Condition(_))));
+        assert_eq!(p.get_register_value(2), 0x10);
+
+        let mut h = HeadlessInterface::new();
+        h.set_stop_on_halt(true);
+        let p = run(&mut h, PROGRAM);
+        assert_eq!(h.get_finish(), Some(Finish::Halted));
+        assert_eq!(p.get_program_counter(), 0x20E);
+        assert_eq!(h.get_exit_code(), 0);
+
+        // Running out of frames first is a failure
+        let mut h = HeadlessInterface::new();
+        h.set_stop_on_halt(true);
+        h.set_max_frames(2);
+        run(&mut h, PROGRAM);
+        assert_eq!(h.get_finish(), Some(Finish::Frames));
+        assert_eq!(h.get_exit_code(), 2);
+    }
+
+    #[test]
+    fn test_hash_frames() {
+        let mut h = HeadlessInterface::new();
+        h.add_hash_frame(1);
+        h.add_hash_frame(40);
+        h.set_max_frames(40);
+        let p = run(&mut h, PROGRAM);
+        let hashes = h.get_hashes();
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(hashes[0].frame, 1);
+        assert_eq!(hashes[0].screen, Processor::new().screen_hash());
+        assert_eq!(hashes[1].frame, 40);
+        assert_eq!(hashes[1].screen, p.screen_hash());
+        assert_ne!(hashes[1].state, hashes[0].state);
+    }
+
+    #[test]
+    fn test_hash_frames_with_partial_frames() {
+        // Rendering between frames, as the emulator does while paused, doesn't change the hashes
+        let mut expected = HeadlessInterface::new();
+        expected.add_hash_frame(5);
+        expected.set_max_frames(5);
+        run(&mut expected, PROGRAM);
+        let mut h = HeadlessInterface::new();
+        h.add_hash_frame(5);
+        h.set_max_frames(5);
+        let mut p = Processor::new();
+        p.load_program(&assemble(PROGRAM).unwrap());
+        for i in 1..100_000 {
+            p.step().unwrap();
+            if h.update(&mut p) {
+                break;
+            }
+            if i % 8 == 0 {
+                p.on_tick();
+                h.render(&p);
+                p.on_v_blank();
+            } else if i % 3 == 0 {
+                h.render(&p);
+            }
+        }
+        assert_eq!(h.get_hashes(), expected.get_hashes());
+    }
+
+    #[test]
+    fn test_frames_with_breakpoint() {
+        // Frames cut short by a breakpoint, and frames rendered while paused, aren't counted
+        let mut h = HeadlessInterface::new();
+        h.set_max_frames(3);
+        let mut p = Processor::new();
+        p.load_program(&assemble(PROGRAM).unwrap());
+        let mut emulator = Emulator::new(p, Box::new(h));
+        emulator.set_paced(false);
+        emulator.set_instructions_per_frame(8);
+        emulator.get_debugger_mut().add_breakpoint(0x20A);
+        while emulator.run_frame().is_none() {}
+        assert_eq!(emulator.get_frames(), 1);
+        for _ in 0..5 {
+            emulator.run_frame();
+        }
+        emulator.get_debugger_mut().resume();
+        emulator.get_debugger_mut().clear();
+        while !emulator.is_finished() {
+            emulator.run_frame();
+        }
+        assert_eq!(emulator.get_frames(), 3);
+    }
+
+    #[test]
+    fn test_screen_output() {
+        let dir = std::env::temp_dir();
+        let text_path = dir.join("rust-chip8-headless.txt");
+        let pbm_path = dir.join("rust-chip8-headless.pbm");
+        for path in [&text_path, &pbm_path] {
+            let mut h = HeadlessInterface::new();
+            h.set_stop_on_halt(true);
+            h.set_screen_output(path.to_str().unwrap());
+            run(&mut h, PROGRAM);
+        }
+        let text = fs::read_to_string(text_path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(&lines[1][..6], ".####.");
+        assert_eq!(&lines[2][..6], ".#....");
+        let pbm = fs::read_to_string(pbm_path).unwrap();
+        let lines: Vec<&str> = pbm.lines().collect();
+        assert_eq!(lines[..2], ["P1", "64 32"]);
+        assert!(lines[3].starts_with("0 1 1 1 1 0"));
+    }
+}
@@ -0,0 +1,77 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::instruction::disassemble;
+    use rust_chip8_opengl::{Instruction, Variant};
+
+    #[test]
+    fn test_decode_encode_round_trip() {
+        for opcode in 0..=0xFFFF {
+            if let Ok(inst) = Instruction::decode(opcode) {
+                assert_eq!(
+                    inst.encode(),
+                    opcode,
+                    "{:04X} decoded to {:?}",
+                    opcode,
+                    inst
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Instruction::decode(0x00E0), Ok(Instruction::Cls));
+        assert_eq!(Instruction::decode(0x0123), Ok(Instruction::Sys(0x123)));
+        assert_eq!(Instruction::decode(0x6105), Ok(Instruction::LdByte(1, 5)));
+        assert_eq!(Instruction::decode(0xD124), Ok(Instruction::Drw(1, 2, 4)));
+        assert_eq!(Instruction::decode(0xF000), Ok(Instruction::LdILong));
+        assert_eq!(Instruction::decode(0xF301), Ok(Instruction::Plane(3)));
+        for invalid in [0x5001, 0x800F, 0x9001, 0xE000, 0xF0FF, 0xF100, 0xF102] {
+            let e = Instruction::decode(invalid).unwrap_err();
+            assert_eq!(e.get_opcode(), invalid);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            (0x6005, "LD V0, 0x05"),
+            (0xD124, "DRW V1, V2, 4"),
+            (0x8AB4, "ADD VA, VB"),
+            (0x2345, "CALL 0x345"),
+            (0xF329, "LD F, V3"),
+            (0xF055, "LD [I], V0"),
+            (0xFE65, "LD VE, [I]"),
+            (0x00C4, "SCD 4"),
+        ];
+        for (opcode, text) in cases {
+            assert_eq!(Instruction::decode(opcode).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_supported_by() {
+        let hires = Instruction::Hires;
+        assert!(!hires.is_supported_by(Variant::Chip8));
+        assert!(hires.is_supported_by(Variant::SuperChip));
+        assert!(hires.is_supported_by(Variant::XoChip));
+        let plane = Instruction::Plane(1);
+        assert!(!plane.is_supported_by(Variant::SuperChip));
+        assert!(plane.is_supported_by(Variant::XoChip));
+        assert!(!Instruction::StoreRpl(8).is_supported_by(Variant::SuperChip));
+        assert!(Instruction::StoreRpl(8).is_supported_by(Variant::XoChip));
+        assert!(Instruction::Cls.is_supported_by(Variant::Chip8));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = [0x60, 0x05, 0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0xAB];
+        assert_eq!(
+            disassemble(&program, 0x200),
+            "0x200  6005       LD V0, 0x05\n\
+             0x202  F000 1234  LD I, LONG 0x1234\n\
+             0x206  FFFF       DW 0xFFFF\n\
+             0x208  AB         DB 0xAB\n"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::config::Config;
+    use rust_chip8_opengl::keymap::KeyMap;
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = KeyMap::new();
+        assert_eq!(keymap.get_keys(0x0), ["x"]);
+        assert_eq!(keymap.get_keys(0xF), ["v"]);
+        assert_eq!(keymap.get_chip8_keys("W"), [0x5]);
+        assert!(keymap.get_chip8_keys("up").is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut keymap = KeyMap::new();
+        keymap.apply("5=z, Up").unwrap();
+        keymap.apply("a=z").unwrap();
+        assert_eq!(keymap.get_keys(0x5), ["z", "up"]);
+        // A key can press more than one CHIP-8 key
+        assert_eq!(keymap.get_chip8_keys("z"), [0x5, 0xA]);
+        assert_eq!(keymap.get_chip8_keys("up"), [0x5]);
+        assert!(keymap.get_chip8_keys("w").is_empty());
+        assert!(keymap.apply("5").is_err());
+        assert!(keymap.apply("G=w").is_err());
+        assert!(keymap.apply("10=w").is_err());
+    }
+
+    const CONFIG: &str = r#"
+        [keymap]
+        0 = "a"
+        5 = ["z", "up"]
+
+        [roms."PONG.ch8".keymap]
+        1 = ["w", "up"]
+        4 = "s"
+
+        [roms."other.ch8".keymap]
+        0 = "q"
+    "#;
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse(CONFIG, None).unwrap();
+        assert_eq!(config.keymap.get_keys(0x0), ["a"]);
+        assert_eq!(config.keymap.get_keys(0x5), ["z", "up"]);
+        // Keys that aren't listed keep their default
+        assert_eq!(config.keymap.get_keys(0x1), ["1"]);
+        // ROM overrides are applied on top, matching the file name ignoring case
+        let config = Config::parse(CONFIG, Some("pong.ch8")).unwrap();
+        assert_eq!(config.keymap.get_keys(0x0), ["a"]);
+        assert_eq!(config.keymap.get_keys(0x1), ["w", "up"]);
+        assert_eq!(config.keymap.get_keys(0x4), ["s"]);
+        assert_eq!(config.keymap.get_chip8_keys("up"), [0x1, 0x5]);
+        let config = Config::parse(CONFIG, Some("other.ch8")).unwrap();
+        assert_eq!(config.keymap.get_keys(0x0), ["q"]);
+        assert_eq!(config.keymap.get_keys(0x1), ["1"]);
+        assert_eq!(Config::parse("", None).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_config_errors() {
+        assert!(Config::parse("[keymap", None).is_err());
+        assert!(Config::parse("[keys]\n0 = \"a\"", None).is_err());
+        assert!(Config::parse("[keymap]\nG = \"a\"", None).is_err());
+        assert!(Config::parse("[keymap]\n0 = 1", None).is_err());
+        assert!(Config::parse("[keymap]\n0 = [\"a\", 1]", None).is_err());
+        // Mistakes in other ROMs' key maps are still found
+        assert!(Config::parse("[roms.\"a.ch8\".keymap]\nG = \"a\"", Some("b.ch8")).is_err());
+    }
+}
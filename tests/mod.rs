@@ -1,2 +1,17 @@
+mod assembler;
+mod audio;
+mod dap;
+mod debugger;
+mod emulator;
+mod gdb;
+mod headless;
+mod instructions;
+mod keymap;
+mod movie;
 mod programs;
+mod recording;
+mod rewind;
+mod save_states;
+mod screenshot;
+mod trace;
 mod unit_tests;
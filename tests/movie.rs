@@ -0,0 +1,117 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::movie::hash_rom;
+    use rust_chip8_opengl::{assemble, Movie, MovieError, Processor, Quirks, Variant};
+
+    // Adds a random number to V1 every time 5 is pressed and released, and copies VA from the delay timer
+    const PROGRAM: &str = "
+            LD V0, 5
+    loop:   LD V2, K
+            SE V2, V0
+            JP loop
+            RND V3, 0xFF
+            ADD V1, V3
+            LD VA, DT
+            LD DT, V3
+            JP loop
+    ";
+
+    fn record(rom: &[u8], seed: u64) -> Movie {
+        let mut movie = Movie::new(Variant::Chip8, Quirks::chip48(), 8, seed, rom);
+        for frame in 0..120 {
+            let mut inputs = [false; 0x10];
+            inputs[5] = frame % 10 < 5;
+            inputs[3] = frame % 7 == 0;
+            movie.add_frame(inputs);
+        }
+        return movie;
+    }
+
+    // Replay a movie, returning the registers once it has finished
+    fn replay(movie: &Movie, rom: &[u8]) -> Vec<u8> {
+        let mut p = movie.create_processor(rom).unwrap();
+        movie.replay(&mut p).unwrap();
+        return (0..0x10).map(|i| p.get_register_value(i)).collect();
+    }
+
+    #[test]
+    fn test_replay_is_exact() {
+        let rom = assemble(PROGRAM).unwrap();
+        let movie = record(&rom, 1234);
+        let first = replay(&movie, &rom);
+        assert_ne!(first[1], 0);
+        assert_eq!(replay(&movie, &rom), first);
+
+        // Loading the movie back gives the same run
+        let loaded = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(loaded, movie);
+        assert_eq!(replay(&loaded, &rom), first);
+
+        // But a different seed doesn't
+        assert_ne!(replay(&record(&rom, 4321), &rom), first);
+    }
+
+    #[test]
+    fn test_frames() {
+        let rom = assemble(PROGRAM).unwrap();
+        let movie = record(&rom, 0);
+        assert_eq!(movie.get_frame_count(), 120);
+        assert!(movie.get_frame(3).unwrap()[5]);
+        assert!(!movie.get_frame(5).unwrap()[5]);
+        assert!(movie.get_frame(7).unwrap()[3]);
+        assert_eq!(movie.get_frame(120), None);
+        assert_eq!(movie.rom_hash, hash_rom(&rom));
+    }
+
+    #[test]
+    fn test_rom_mismatch() {
+        let rom = assemble(PROGRAM).unwrap();
+        let movie = record(&rom, 0);
+        assert_eq!(
+            movie.create_processor(&[0x00, 0xE0]).err(),
+            Some(MovieError::RomMismatch(hash_rom(&rom)))
+        );
+    }
+
+    #[test]
+    fn test_invalid_movies() {
+        let rom = assemble(PROGRAM).unwrap();
+        let data = record(&rom, 0).to_bytes();
+        assert_eq!(
+            Movie::from_bytes(b"C8ST").err(),
+            Some(MovieError::InvalidHeader)
+        );
+        assert_eq!(
+            Movie::from_bytes(&data[..(data.len() - 1)]).err(),
+            Some(MovieError::Truncated)
+        );
+        let mut newer = data.clone();
+        newer[4] = 99;
+        assert_eq!(
+            Movie::from_bytes(&newer).err(),
+            Some(MovieError::UnsupportedVersion(99))
+        );
+        let mut longer = data.clone();
+        longer.push(0);
+        assert_eq!(
+            Movie::from_bytes(&longer).err(),
+            Some(MovieError::Corrupt("length"))
+        );
+    }
+
+    #[test]
+    fn test_override_inputs() {
+        let mut p = Processor::new();
+        let mut pressed = [false; 0x10];
+        pressed[2] = true;
+        p.override_inputs(pressed);
+        // The interface's inputs are kept, but ignored
+        p.update_inputs([false; 0x10]);
+        assert!(p.get_input_state(2));
+        assert_eq!(p.get_requested_inputs(), [false; 0x10]);
+        p.clear_input_override();
+        assert!(!p.get_input_state(2));
+        p.update_inputs(pressed);
+        assert!(p.get_input_state(2));
+    }
+}
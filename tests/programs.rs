@@ -1,3 +1,4 @@
+#![allow(clippy::needless_return)]
 #[cfg(test)]
 extern crate assert_hex;
 
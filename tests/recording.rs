@@ -0,0 +1,94 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::recording::RecordingFormat;
+    use rust_chip8_opengl::{assemble, Processor, Recorder, Screenshot, Variant};
+    use std::path::Path;
+
+    // Draw the font sprite for 0 in the top left corner, or erase it if it is already there
+    fn toggle_zero(p: &mut Processor) {
+        p.load_program(&assemble("LD F, V0\nDRW V0, V0, 5").unwrap());
+        p.step().unwrap();
+        p.on_v_blank();
+        p.step().unwrap();
+    }
+
+    // Decode a GIF, returning the delay of each frame
+    fn read_delays(data: &[u8]) -> Vec<u16> {
+        let mut decoder = gif::DecodeOptions::new().read_info(data).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        return delays;
+    }
+
+    #[test]
+    fn test_raw() {
+        let mut p = Processor::new();
+        let mut r =
+            Recorder::for_processor(Vec::new(), RecordingFormat::Raw, Screenshot::new(), &p)
+                .unwrap();
+        r.capture(&p).unwrap();
+        toggle_zero(&mut p);
+        r.capture(&p).unwrap();
+        r.capture(&p).unwrap();
+        assert_eq!(r.get_frames_written(), 3);
+        let data = r.finish().unwrap();
+        let frame_size = 64 * 32 * 3;
+        assert_eq!(data.len(), 3 * frame_size);
+        assert_eq!(data[0..3], [0, 0, 0]);
+        assert_eq!(data[frame_size..(frame_size + 3)], [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_gif_merges_identical_frames() {
+        let mut p = Processor::new();
+        let mut r =
+            Recorder::for_processor(Vec::new(), RecordingFormat::Gif, Screenshot::new(), &p)
+                .unwrap();
+        for _ in 0..60 {
+            r.capture(&p).unwrap();
+        }
+        toggle_zero(&mut p);
+        for _ in 0..30 {
+            r.capture(&p).unwrap();
+        }
+        assert_eq!(r.get_frames(), 90);
+        let data = r.finish().unwrap();
+        assert!(data.starts_with(b"GIF89a"));
+        assert_eq!(read_delays(&data), [100, 50]);
+    }
+
+    #[test]
+    fn test_gif_keeps_timing() {
+        // A frame that changes every 60th of a second is too fast for a GIF
+        let mut p = Processor::new();
+        let mut r =
+            Recorder::for_processor(Vec::new(), RecordingFormat::Gif, Screenshot::new(), &p)
+                .unwrap();
+        for _ in 0..30 {
+            toggle_zero(&mut p);
+            r.capture(&p).unwrap();
+        }
+        let delays = read_delays(&r.finish().unwrap());
+        assert!(delays.iter().all(|d| *d >= 2));
+        assert_eq!(delays.iter().sum::<u16>(), 50);
+    }
+
+    #[test]
+    fn test_size() {
+        let mut screenshot = Screenshot::new();
+        screenshot.scale = 2;
+        let p = Processor::new_with_variant(Variant::SuperChip);
+        let r = Recorder::for_processor(Vec::new(), RecordingFormat::Raw, screenshot, &p).unwrap();
+        assert_eq!(r.get_size(), (256, 128));
+        assert_eq!(
+            RecordingFormat::from_path(Path::new("run.GIF")),
+            RecordingFormat::Gif
+        );
+        assert_eq!(
+            RecordingFormat::from_path(Path::new("run.rgb")),
+            RecordingFormat::Raw
+        );
+    }
+}
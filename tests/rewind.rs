@@ -0,0 +1,117 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::{Processor, RewindBuffer};
+
+    // Clears the screen and redraws V0 each time round the loop, 7 instructions, so every frame makes a
+    // small change to memory and the screen and the deltas between snapshots stay small
+    const PROGRAM: [u16; 8] = [
+        0x6000, // LD V0, 0x00
+        0xA400, // LD I, 0x400
+        0xF033, // LD B, V0
+        0xF029, // LD F, V0
+        0x00E0, // CLS
+        0xD115, // DRW V1, V1, 5
+        0x7001, // ADD V0, 0x01
+        0x1202, // JP 0x202
+    ];
+
+    fn new_processor() -> Processor {
+        let mut p = Processor::new();
+        p.load_program_u16(&PROGRAM);
+        return p;
+    }
+
+    // Run once round the loop, and update the timers
+    fn run_frame(p: &mut Processor) {
+        for _ in 0..7 {
+            p.step().unwrap();
+        }
+        p.on_v_blank();
+        p.on_tick();
+    }
+
+    #[test]
+    fn test_rewind_restores_snapshots_in_reverse() {
+        let mut p = new_processor();
+        let mut rewind = RewindBuffer::new(3, 100, usize::MAX);
+        let mut states = Vec::new();
+        for frame in 1..=30 {
+            run_frame(&mut p);
+            rewind.on_frame(&p);
+            if frame % 3 == 0 {
+                states.push(p.save_state());
+            }
+        }
+        assert_eq!(rewind.len(), 10);
+        for state in states.iter().rev() {
+            assert!(rewind.rewind(&mut p).unwrap());
+            assert_eq!(&p.save_state(), state);
+        }
+        // Stays at the oldest snapshot once everything has been rewound
+        assert!(rewind.rewind(&mut p).unwrap());
+        assert_eq!(p.save_state(), states[0]);
+        assert_eq!(rewind.len(), 1);
+    }
+
+    #[test]
+    fn test_rewind_limits() {
+        let mut p = new_processor();
+        // Limited by number of snapshots
+        let mut rewind = RewindBuffer::new(1, 5, usize::MAX);
+        for _ in 0..20 {
+            run_frame(&mut p);
+            rewind.on_frame(&p);
+        }
+        assert_eq!(rewind.len(), 5);
+        // Limited by memory, deltas should be much smaller than full snapshots
+        let size = p.save_state().len();
+        let mut rewind = RewindBuffer::new(1, 1000, size * 2);
+        for _ in 0..100 {
+            run_frame(&mut p);
+            rewind.on_frame(&p);
+        }
+        assert!(rewind.memory_used() <= size * 2);
+        assert!(rewind.len() > 10);
+        // Disabled
+        let mut rewind = RewindBuffer::new(1, 0, usize::MAX);
+        rewind.on_frame(&p);
+        assert!(rewind.is_empty());
+        assert!(!rewind.rewind(&mut p).unwrap());
+    }
+
+    #[test]
+    fn test_rewind_across_resolution_change() {
+        let mut p = Processor::new_with_variant(rust_chip8_opengl::Variant::SuperChip);
+        p.load_program_u16(&[0x00FF, 0x00FE, 0x1200]);
+        let mut rewind = RewindBuffer::new(1, 10, usize::MAX);
+        p.step().unwrap();
+        rewind.on_frame(&p);
+        p.step().unwrap();
+        rewind.on_frame(&p);
+        assert!(!p.is_hires());
+        rewind.rewind(&mut p).unwrap();
+        assert!(!p.is_hires());
+        rewind.rewind(&mut p).unwrap();
+        assert!(p.is_hires());
+        assert_eq!(p.get_program_counter(), 0x202);
+    }
+
+    #[test]
+    fn test_rewind_pc_past_end() {
+        // The PC runs off the end of memory after an instruction in the last word
+        let mut p = new_processor();
+        let end = p.get_mem_size();
+        p.set_mem_at(end - 2, 0x60);
+        p.set_mem_at(end - 1, 0x01);
+        p.set_program_counter(end - 2);
+        let mut rewind = RewindBuffer::new(1, 10, usize::MAX);
+        rewind.on_frame(&p);
+        p.step().unwrap();
+        rewind.on_frame(&p);
+        p.step().unwrap_err();
+        assert!(rewind.rewind(&mut p).unwrap());
+        assert_eq!(p.get_program_counter(), end);
+        assert!(rewind.rewind(&mut p).unwrap());
+        assert_eq!(p.get_program_counter(), end - 2);
+    }
+}
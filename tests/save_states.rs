@@ -0,0 +1,155 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::{EmulatorError, Processor, Quirks, StateError, Variant};
+
+    // Counts up in V0, writing it as BCD and drawing it before calling an empty subroutine,
+    // so memory, the screen and the stack all have to be saved
+    const PROGRAM: [u16; 9] = [
+        0x6000, // LD V0, 0x00
+        0xA400, // LD I, 0x400
+        0xF033, // LD B, V0
+        0xF029, // LD F, V0
+        0xD115, // DRW V1, V1, 5
+        0x7001, // ADD V0, 0x01
+        0x2210, // CALL 0x210
+        0x1202, // JP 0x202
+        0x00EE, // RET
+    ];
+
+    fn new_processor(variant: Variant, quirks: Quirks) -> Processor {
+        let mut p = Processor::new_with_quirks(variant, quirks);
+        p.load_program_u16(&PROGRAM);
+        return p;
+    }
+
+    fn run(p: &mut Processor, steps: usize) {
+        for _ in 0..steps {
+            p.step().unwrap();
+            p.on_v_blank();
+            p.on_tick();
+        }
+    }
+
+    fn assert_same(a: &Processor, b: &Processor) {
+        assert_eq!(a.save_state(), b.save_state());
+        assert_eq!(a.get_program_counter(), b.get_program_counter());
+        assert_eq!(a.get_i(), b.get_i());
+        (0..0x10).for_each(|r| assert_eq!(a.get_register_value(r), b.get_register_value(r)));
+    }
+
+    #[test]
+    fn test_save_load_round_trip() {
+        for variant in [Variant::Chip8, Variant::SuperChip, Variant::XoChip] {
+            let mut p = new_processor(variant, Quirks::for_variant(variant));
+            run(&mut p, 37);
+            let state = p.save_state();
+            let mut q = Processor::new();
+            q.load_state(&state).unwrap();
+            assert_same(&p, &q);
+            assert_eq!(q.get_variant(), variant);
+            // Both should keep running identically
+            run(&mut p, 50);
+            run(&mut q, 50);
+            assert_same(&p, &q);
+        }
+    }
+
+    #[test]
+    fn test_load_restores_earlier_state() {
+        let mut p = new_processor(Variant::Chip8, Quirks::chip48());
+        run(&mut p, 20);
+        let state = p.save_state();
+        let pc = p.get_program_counter();
+        let v0 = p.get_register_value(0);
+        run(&mut p, 20);
+        p.update_inputs([true; 0x10]);
+        p.load_state(&state).unwrap();
+        assert_eq!(p.get_program_counter(), pc);
+        assert_eq!(p.get_register_value(0), v0);
+        assert_eq!(p.get_quirks(), Quirks::chip48());
+        assert!(!p.get_input_state(0));
+    }
+
+    #[test]
+    fn test_save_load_pc_past_end() {
+        // Running an instruction in the last word of memory leaves the PC just past the end
+        let mut p = new_processor(Variant::Chip8, Quirks::cosmac_vip());
+        let end = p.get_mem_size();
+        p.set_mem_at(end - 2, 0x60);
+        p.set_mem_at(end - 1, 0x01);
+        p.set_program_counter(end - 2);
+        p.step().unwrap();
+        assert_eq!(p.get_program_counter(), end);
+        let mut q = Processor::new();
+        q.load_state(&p.save_state()).unwrap();
+        assert_same(&p, &q);
+        assert_eq!(
+            q.step().unwrap_err(),
+            EmulatorError::PcOutOfBounds { pc: end }
+        );
+    }
+
+    #[test]
+    fn test_load_invalid_states() {
+        let mut p = new_processor(Variant::Chip8, Quirks::cosmac_vip());
+        run(&mut p, 10);
+        let state = p.save_state();
+        let before = p.save_state();
+
+        assert_eq!(p.load_state(&[]), Err(StateError::InvalidHeader));
+        assert_eq!(p.load_state(b"not a state"), Err(StateError::InvalidHeader));
+
+        let mut bad_version = state.clone();
+        bad_version[4] = 0xFF;
+        assert_eq!(
+            p.load_state(&bad_version),
+            Err(StateError::UnsupportedVersion(0xFF))
+        );
+
+        assert_eq!(
+            p.load_state(&state[0..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        assert_eq!(p.load_state(&state[0..30]), Err(StateError::Truncated));
+
+        let mut bad_variant = state.clone();
+        bad_variant[5] = 7;
+        assert!(matches!(
+            p.load_state(&bad_variant),
+            Err(StateError::Corrupt(_))
+        ));
+
+        let mut extra = state.clone();
+        extra.push(0);
+        assert!(matches!(p.load_state(&extra), Err(StateError::Corrupt(_))));
+
+        // Nothing should have changed
+        assert_eq!(p.save_state(), before);
+    }
+
+    #[test]
+    fn test_hashes() {
+        let mut a = new_processor(Variant::Chip8, Quirks::cosmac_vip());
+        let mut b = new_processor(Variant::Chip8, Quirks::cosmac_vip());
+        // Hashes must never change between versions, or old ones would stop matching
+        assert_eq!(a.screen_hash(), 0xDEC2E009E36B7315);
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        run(&mut a, 6);
+        assert_ne!(a.screen_hash(), b.screen_hash());
+        assert_ne!(a.state_hash(), b.state_hash());
+        run(&mut b, 6);
+        assert_eq!(a.screen_hash(), b.screen_hash());
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        // Changes that don't touch the screen only change the state hash
+        let screen = a.screen_hash();
+        a.execute(0x6A01).unwrap();
+        assert_eq!(a.screen_hash(), screen);
+        assert_ne!(a.state_hash(), b.state_hash());
+
+        // Restoring a state restores its hash
+        b.load_state(&a.save_state()).unwrap();
+        assert_eq!(a.state_hash(), b.state_hash());
+    }
+}
@@ -0,0 +1,93 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::screenshot::{parse_color, ScreenshotFormat};
+    use rust_chip8_opengl::{assemble, Processor, Screenshot};
+    use std::path::Path;
+
+    // A processor with the font sprite for 0 drawn in the top left corner
+    fn draw_zero() -> Processor {
+        let mut p = Processor::new();
+        p.load_program(&assemble("LD F, V0\nDRW V0, V0, 5").unwrap());
+        p.step().unwrap();
+        // Drawing waits for the vertical blank
+        p.on_v_blank();
+        p.step().unwrap();
+        return p;
+    }
+
+    #[test]
+    fn test_text() {
+        let text = Screenshot::new().to_text(&draw_zero());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(lines[0].len(), 64);
+        assert!(lines[0].starts_with("####...."));
+        assert!(lines[1].starts_with("#..#...."));
+        assert!(lines[5].chars().all(|c| c == '.'));
+    }
+
+    #[test]
+    fn test_scale() {
+        let mut screenshot = Screenshot::new();
+        screenshot.scale = 3;
+        let p = draw_zero();
+        assert_eq!(screenshot.get_size(&p), (192, 96));
+        let text = screenshot.to_text(&p);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 96);
+        assert_eq!(lines[2], lines[0]);
+        assert!(lines[3].starts_with("###......###"));
+    }
+
+    #[test]
+    fn test_pbm() {
+        let pbm = Screenshot::new().to_pbm(&draw_zero());
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("64 32"));
+        assert!(lines.next().unwrap().starts_with("1 1 1 1 0 "));
+    }
+
+    #[test]
+    fn test_ppm_colors() {
+        let mut screenshot = Screenshot::new();
+        screenshot.set_foreground([0x33, 0xFF, 0x66]);
+        screenshot.set_background([1, 2, 3]);
+        let ppm = screenshot.to_ppm(&draw_zero());
+        let header = b"P6\n64 32\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm.len(), header.len() + 64 * 32 * 3);
+        let pixels = &ppm[header.len()..];
+        assert_eq!(pixels[0..3], [0x33, 0xFF, 0x66]);
+        assert_eq!(pixels[12..15], [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_png() {
+        let png = Screenshot::new().encode(&draw_zero(), ScreenshotFormat::Png);
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(png[16..24], [0, 0, 0, 64, 0, 0, 0, 32]);
+    }
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(
+            ScreenshotFormat::from_path(Path::new("a.ch8.screenshot0.PNG")),
+            Some(ScreenshotFormat::Png)
+        );
+        assert_eq!(
+            ScreenshotFormat::from_path(Path::new("screen.txt")),
+            Some(ScreenshotFormat::Text)
+        );
+        assert_eq!(ScreenshotFormat::from_path(Path::new("screen")), None);
+        assert_eq!(ScreenshotFormat::Ppm.get_extension(), "ppm");
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#33FF66"), Some([0x33, 0xFF, 0x66]));
+        assert_eq!(parse_color("0a0B0c"), Some([0x0A, 0x0B, 0x0C]));
+        assert_eq!(parse_color("#FFF"), None);
+        assert_eq!(parse_color("#GG0000"), None);
+    }
+}
@@ -0,0 +1,222 @@
+#![allow(clippy::needless_return)]
+mod tests {
+    use rust_chip8_opengl::trace::{
+        diff_processors, diff_traces, parse_trace, TraceEntry, TraceFormat,
+    };
+    use rust_chip8_opengl::{assemble, Processor, Quirks, Tracer, Variant};
+
+    const PROGRAM: &str = "
+            LD V0, 0x12
+            LD I, 0x345
+    loop:   ADD V1, 1
+            LD B, V1
+            JP loop
+    ";
+
+    // Run the program, tracing every instruction
+    fn run(tracer: &mut Tracer<&mut Vec<u8>>, steps: usize) {
+        run_program(tracer, PROGRAM, steps);
+    }
+
+    fn run_program(tracer: &mut Tracer<&mut Vec<u8>>, program: &str, steps: usize) {
+        let mut p = Processor::new();
+        p.load_program(&assemble(program).unwrap());
+        for _ in 0..steps {
+            tracer.before_step(&p);
+            p.step().unwrap();
+            tracer.after_step(&p).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_text_trace() {
+        let mut out = Vec::new();
+        let mut tracer = Tracer::new(&mut out, TraceFormat::Text);
+        run(&mut tracer, 4);
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("00000000 200 6012 LD V0, 0x12"));
+        assert!(lines[0].contains(" V=12000000000000000000000000000000 I=000 SP=0 DT=00 ST=00"));
+        // BCD of 1 only changes the last digit, since memory starts cleared
+        assert!(lines[3].starts_with("00000003 206 F133 LD B, V1"));
+        assert!(lines[3].ends_with("I=345 SP=0 DT=00 ST=00 [347]=01"));
+    }
+
+    #[test]
+    fn test_trace_store_registers() {
+        let mut out = Vec::new();
+        let mut tracer = Tracer::new(&mut out, TraceFormat::Text);
+        run_program(
+            &mut tracer,
+            "
+            LD V0, 1
+            LD V1, 2
+            LD I, 0x300
+            LD [I], V1
+            LD I, 0x300
+            LD [I], V1
+        ",
+            6,
+        );
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        // Memory is found at I before it is incremented, and unchanged bytes aren't shown
+        assert!(lines[3].ends_with("I=302 SP=0 DT=00 ST=00 [300]=01 [301]=02"));
+        assert!(lines[5].ends_with("I=302 SP=0 DT=00 ST=00"));
+    }
+
+    #[test]
+    fn test_json_trace() {
+        let mut out = Vec::new();
+        let mut tracer = Tracer::new(&mut out, TraceFormat::Json);
+        run(&mut tracer, 4);
+        let text = String::from_utf8(out).unwrap();
+        let entries: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[1]["pc"], 0x202);
+        assert_eq!(entries[1]["opcode"], 0xA345);
+        assert_eq!(entries[1]["instruction"], "LD I, 0x345");
+        assert_eq!(entries[1]["i"], 0x345);
+        assert_eq!(entries[2]["v"][1], 1);
+        assert_eq!(entries[3]["memory"][0]["addr"], 0x347);
+        assert_eq!(entries[3]["memory"][0]["value"], 1);
+    }
+
+    #[test]
+    fn test_trace_range_and_limit() {
+        let mut out = Vec::new();
+        let mut tracer = Tracer::new(&mut out, TraceFormat::Text);
+        tracer.add_range(0x204, 0x204);
+        tracer.set_limit(3);
+        run(&mut tracer, 20);
+        assert!(tracer.is_full());
+        assert_eq!(tracer.get_entries_written(), 3);
+        drop(tracer);
+        let text = String::from_utf8(out).unwrap();
+        let steps: Vec<&str> = text.lines().map(|l| &l[..12]).collect();
+        // Steps are still counted while not tracing
+        assert_eq!(steps, ["00000002 204", "00000005 204", "00000008 204"]);
+    }
+
+    #[test]
+    fn test_parse_trace() {
+        for format in [TraceFormat::Text, TraceFormat::Json] {
+            let mut out = Vec::new();
+            run(&mut Tracer::new(&mut out, format), 6);
+            let entries = parse_trace(&String::from_utf8(out).unwrap()).unwrap();
+            assert_eq!(entries.len(), 6);
+            assert_eq!(entries[3].pc, 0x206);
+            assert_eq!(entries[3].instruction, "LD B, V1");
+            assert_eq!(entries[3].registers[1], 1);
+            assert_eq!(entries[3].memory, [(0x347, 1)]);
+            assert_eq!(entries[5].step, 5);
+            assert_eq!(
+                entries[5].to_string().parse::<TraceEntry>(),
+                Ok(entries[5].clone())
+            );
+        }
+        assert!(parse_trace("00000000 200 6012 LD V0, 0x12 V=12")
+            .unwrap_err()
+            .starts_with("Line 1: "));
+    }
+
+    #[test]
+    fn test_diff_traces() {
+        let mut left = Vec::new();
+        run(&mut Tracer::new(&mut left, TraceFormat::Text), 20);
+        let mut right = Vec::new();
+        let changed = PROGRAM.replace("ADD V1, 1", "ADD V1, 2");
+        run_program(
+            &mut Tracer::new(&mut right, TraceFormat::Json),
+            &changed,
+            20,
+        );
+        let left = parse_trace(&String::from_utf8(left).unwrap()).unwrap();
+        let right = parse_trace(&String::from_utf8(right).unwrap()).unwrap();
+        assert_eq!(diff_traces(&left, &left), None);
+
+        let d = diff_traces(&left, &right).unwrap();
+        assert_eq!(d.index, 2);
+        assert_eq!(d.previous.unwrap().pc, 0x202);
+        assert_eq!(d.left.unwrap().opcode, 0x7101);
+        assert_eq!(d.right.unwrap().opcode, 0x7102);
+
+        // A shorter trace ends before the other
+        let d = diff_traces(&left, &left[..10]).unwrap();
+        assert_eq!(d.index, 10);
+        assert_eq!(d.right, None);
+    }
+
+    #[test]
+    fn test_diff_processors() {
+        let program = assemble(
+            "
+            LD V0, 1
+            LD V1, 4
+            LD I, 0x300
+    loop:   SHR V0, V1
+            LD [I], V0
+            JP loop
+        ",
+        )
+        .unwrap();
+        let mut sides = [Quirks::cosmac_vip(), Quirks::chip48()].map(|quirks| {
+            let mut p = Processor::new_with_seed(Variant::Chip8, quirks, 0);
+            p.load_program(&program);
+            return p;
+        });
+        let [left, right] = &mut sides;
+        let d = diff_processors(left, right, 100, 8).unwrap();
+        assert_eq!(d.index, 3);
+        assert_eq!(d.left.as_ref().unwrap().registers[0], 2);
+        assert_eq!(d.right.as_ref().unwrap().registers[0], 0);
+        assert_eq!(d.memory, []);
+        assert!(!d.screen);
+        assert!(d
+            .to_string()
+            .starts_with("Runs differ after 3 matching instructions"));
+
+        // The same settings never differ
+        let mut left = Processor::new_with_seed(Variant::Chip8, Quirks::chip48(), 0);
+        let mut right = Processor::new_with_seed(Variant::Chip8, Quirks::chip48(), 0);
+        left.load_program(&program);
+        right.load_program(&program);
+        assert_eq!(diff_processors(&mut left, &mut right, 100, 8), None);
+    }
+
+    #[test]
+    fn test_diff_processors_screen() {
+        // Only the screen differs when a sprite is clipped instead of wrapping
+        let program = assemble(
+            "
+            LD V0, 60
+            LD V1, 0
+            LD I, sprite
+            DRW V0, V1, 1
+    loop:   JP loop
+    sprite: db 0xFF
+        ",
+        )
+        .unwrap();
+        let mut sides = [true, false].map(|clip_sprites| {
+            let quirks = Quirks {
+                clip_sprites,
+                display_wait: false,
+                ..Quirks::chip48()
+            };
+            let mut p = Processor::new_with_seed(Variant::Chip8, quirks, 0);
+            p.load_program(&program);
+            return p;
+        });
+        let [left, right] = &mut sides;
+        let d = diff_processors(left, right, 100, 8).unwrap();
+        assert_eq!(d.index, 3);
+        assert_eq!(d.left, d.right);
+        assert!(d.screen);
+        assert!(d.to_string().contains("Screens differ"));
+    }
+}
@@ -3,22 +3,26 @@ extern crate assert_hex;
 
 mod tests {
     use assert_hex::assert_eq_hex;
-    use rand::Rng;
-    use rust_chip8_opengl::processor::{Processor, SPRITES};
+    use rand::{rngs::mock::StepRng, Rng};
+    use rust_chip8_opengl::processor::{Processor, Variant, BIG_SPRITES, SPRITES};
+    use rust_chip8_opengl::{EmulatorError, Quirks};
 
     // Build an instruction from 4 4bit values
     // Returns 0x[a][b][c][d]
     // All of a, b, c, d should be at most 4 bits long
+    #[allow(clippy::needless_return, clippy::unnecessary_cast)]
     fn build_inst(a: u8, b: u8, c: u8, d: u8) -> u16 {
         return ((a as u16 & 0x0F) << 12)
             | ((b as u16 & 0x0F) << 8)
             | ((c as u16 & 0x0F) << 4)
             | (d as u16 & 0x0F) as u16;
     }
+    #[allow(clippy::needless_return)]
     fn rand_byte(max: u16) -> u16 {
         return rand::thread_rng().gen_range(0..=max) as u16;
     }
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn test_ld_vx_kk() {
         let mut emu = Processor::new();
         let mut set_vals: [u8; 16] = [0; 16];
@@ -184,6 +188,7 @@ mod tests {
         assert_eq_hex!(emu.get_program_counter(), (v - 2) as usize);
     }
     #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn test_call_ret() {
         let mut emu = Processor::new();
         let addr = rand_byte(0x0FFD) + 2;
@@ -193,6 +198,7 @@ mod tests {
         assert_eq_hex!(emu.get_program_counter(), 0x200 as usize);
     }
     #[test]
+    #[allow(clippy::unnecessary_cast, clippy::needless_range_loop)]
     fn stress_test_call_ret() {
         let mut emu = Processor::new();
         let mut addrs = [0 as usize; 16];
@@ -265,6 +271,7 @@ mod tests {
         })
     }
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn test_ld_i_vx() {
         stress_test(|emu, x, _y, _val_x, _val_y| {
             // We are loading up to 16 values into memory so max is 16 bits less
@@ -293,6 +300,7 @@ mod tests {
         })
     }
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn test_ld_vx_i() {
         stress_test(|emu, x, _y, _val_x, _val_y| {
             let addr = rand_byte(0xFF0);
@@ -385,6 +393,37 @@ mod tests {
         })
     }
     #[test]
+    #[allow(clippy::needless_return)]
+    fn test_rand_seeded() {
+        let run = |seed: u64| -> Vec<u8> {
+            let mut emu = Processor::new_with_seed(Variant::Chip8, Quirks::default(), seed);
+            return (0..64)
+                .map(|_| {
+                    emu.execute(0xC0FF).unwrap();
+                    emu.get_register_value(0)
+                })
+                .collect();
+        };
+        assert_eq!(run(1234), run(1234));
+        assert_ne!(run(1234), run(4321));
+        // Reseeding restarts the sequence
+        let mut emu = Processor::new();
+        emu.seed_rng(1234);
+        emu.execute(0xC0FF).unwrap();
+        assert_eq_hex!(emu.get_register_value(0), run(1234)[0]);
+    }
+    #[test]
+    fn test_rand_full_range() {
+        let mut emu = Processor::new();
+        emu.set_rng(Box::new(StepRng::new(0xFF, 0)));
+        emu.execute(0xC0FF).unwrap();
+        assert_eq_hex!(emu.get_register_value(0), 0xFF);
+        emu.set_rng(Box::new(StepRng::new(0x00, 0)));
+        emu.execute(0xC0FF).unwrap();
+        assert_eq_hex!(emu.get_register_value(0), 0x00);
+    }
+    #[test]
+    #[allow(clippy::unnecessary_cast)]
     fn test_drw() {
         stress_test(|emu, x, y, _val_x, _val_y| {
             emu.execute(0xA200).unwrap();
@@ -509,6 +548,7 @@ mod tests {
         })
     }
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn test_ld_vx_spr() {
         stress_test(|emu, x, _y, val_x, _val_y| {
             emu.execute(build_inst(0xf, x, 0x2, 0x9)).unwrap();
@@ -545,13 +585,479 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_error_details() {
+        let mut p = Processor::new();
+        p.load_program_u16(&[0x1300]);
+        p.step().unwrap();
+        // The full PC is kept, not just the lowest byte
+        let e = p.execute(0x5AB1).unwrap_err();
+        assert_eq!(
+            e,
+            EmulatorError::InvalidOpcode {
+                opcode: 0x5AB1,
+                pc: 0x300
+            }
+        );
+        assert_eq!(e.get_opcode(), Some(0x5AB1));
+        assert_eq!(e.get_pc(), 0x300);
+        assert_eq!(e.get_address(), None);
+    }
+    #[test]
+    fn test_error_on_stack_overflow_and_underflow() {
+        let mut p = Processor::new();
+        // Recurse forever
+        p.load_program_u16(&[0x2200]);
+        for _ in 0..16 {
+            p.step().unwrap();
+        }
+        let e = p.step().unwrap_err();
+        assert_eq!(
+            e,
+            EmulatorError::StackOverflow {
+                opcode: 0x2200,
+                pc: 0x200
+            }
+        );
+        let mut p = Processor::new();
+        let e = p.execute(0x00EE).unwrap_err();
+        assert_eq!(
+            e,
+            EmulatorError::StackUnderflow {
+                opcode: 0x00EE,
+                pc: 0x200
+            }
+        );
+    }
+    #[test]
+    fn test_error_on_memory_out_of_bounds() {
+        for inst in [0xD015, 0xFF55, 0xFF65, 0xF033] {
+            let mut p = Processor::new();
+            p.execute(0xAFFE).unwrap();
+            p.on_v_blank();
+            let e = p.execute(inst).unwrap_err();
+            assert_eq!(e.get_address(), Some(0x1000));
+            assert_eq!(e.get_opcode(), Some(inst));
+        }
+        // Fits exactly
+        let mut p = Processor::new();
+        p.execute(0xAFFD).unwrap();
+        p.execute(0xF033).unwrap();
+        p.execute(0xF265).unwrap();
+    }
+    #[test]
+    fn test_error_on_pc_out_of_bounds() {
+        let mut p = Processor::new();
+        p.load_program_u16(&[0x1FFE]);
+        p.step().unwrap();
+        // 0x0000 at 0xFFE is ignored, then the PC runs off the end
+        p.step().unwrap();
+        assert_eq!(
+            p.step().unwrap_err(),
+            EmulatorError::PcOutOfBounds { pc: 0x1000 }
+        );
+        // Even a PC that would overflow
+        p.set_program_counter(usize::MAX);
+        assert_eq!(
+            p.step().unwrap_err(),
+            EmulatorError::PcOutOfBounds { pc: usize::MAX }
+        );
+    }
+    #[test]
+    fn test_wait_at_address_0() {
+        // Instructions that wait by running again should hold the PC at 0 instead of underflowing
+        let mut p = Processor::new_with_seed(Variant::SuperChip, Quirks::cosmac_vip(), 0);
+        let load_at_0 = |p: &mut Processor, inst: u16| {
+            p.set_mem_at(0, (inst >> 8) as u8);
+            p.set_mem_at(1, inst as u8);
+            p.set_program_counter(0);
+        };
+        // FX0A waits for a key to be released
+        load_at_0(&mut p, 0xF00A);
+        p.step().unwrap();
+        p.step().unwrap();
+        assert_eq_hex!(p.get_program_counter(), 0);
+        let mut keys = [false; 0x10];
+        keys[5] = true;
+        p.update_inputs(keys);
+        p.update_inputs([false; 0x10]);
+        p.step().unwrap();
+        assert_eq_hex!(p.get_register_value(0), 5);
+        assert_eq_hex!(p.get_program_counter(), 2);
+        // DXYN waits for the vertical blank
+        load_at_0(&mut p, 0xD005);
+        p.step().unwrap();
+        assert_eq_hex!(p.get_program_counter(), 0);
+        p.on_v_blank();
+        p.step().unwrap();
+        assert_eq_hex!(p.get_program_counter(), 2);
+        // 00FD keeps running itself once exited
+        load_at_0(&mut p, 0x00FD);
+        p.step().unwrap();
+        p.step().unwrap();
+        assert!(p.has_exited());
+        assert_eq_hex!(p.get_program_counter(), 0);
+    }
+    #[test]
+    fn test_schip_hires() {
+        let mut emu = Processor::new_with_variant(Variant::SuperChip);
+        assert_eq!(emu.get_screen_width(), 64);
+        assert_eq!(emu.get_screen_height(), 32);
+        emu.execute(0x00FF).unwrap();
+        assert!(emu.is_hires());
+        assert_eq!(emu.get_screen_width(), 128);
+        assert_eq!(emu.get_screen_height(), 64);
+        emu.execute(0x00FE).unwrap();
+        assert!(!emu.is_hires());
+        assert_eq!(emu.get_screen_width(), 64);
+    }
+    #[test]
+    fn test_schip_draw_16x16() {
+        let mut emu = Processor::new_with_variant(Variant::SuperChip);
+        emu.execute(0x00FF).unwrap();
+        // Fill a 16x16 sprite with a checkerboard at 0x300
+        for row in 0..16 {
+            let b = if row % 2 == 0 { 0xAA } else { 0x55 };
+            emu.execute(0xA300 + 2 * row).unwrap();
+            emu.execute(0x6000 | b).unwrap();
+            emu.execute(0x6100 | b).unwrap();
+            emu.execute(0xF155).unwrap();
+        }
+        emu.execute(0xA300).unwrap();
+        emu.execute(0x6A70).unwrap();
+        emu.execute(0x6B30).unwrap();
+        emu.execute(0xDAB0).unwrap();
+        assert_eq_hex!(emu.get_register_value(0xF), 0);
+        for y in 0..16 {
+            for x in 0..16 {
+                assert_eq!(emu.get_pixel_at(0x70 + x, 0x30 + y), (x + y) % 2 == 0);
+            }
+        }
+        // Drawing it again should erase it and set VF
+        emu.execute(0xDAB0).unwrap();
+        assert_eq_hex!(emu.get_register_value(0xF), 1);
+        for y in 0..16 {
+            for x in 0..16 {
+                assert!(!emu.get_pixel_at(0x70 + x, 0x30 + y));
+            }
+        }
+    }
+    #[test]
+    fn test_schip_scroll() {
+        let mut emu = Processor::new_with_variant(Variant::SuperChip);
+        emu.execute(0x00FF).unwrap();
+        // Draw the 0 sprite at (8, 8)
+        emu.execute(0x6000).unwrap();
+        emu.execute(0xF029).unwrap();
+        emu.execute(0x6108).unwrap();
+        emu.execute(0xD115).unwrap();
+        // Check the sprite is drawn at (px, py)
+        let check = |emu: &Processor, px: u8, py: u8| {
+            (0..5).for_each(|y| {
+                (0..8).for_each(|x| {
+                    let pixel = (SPRITES[0][y as usize] >> (7 - x) & 0x1) == 0x1;
+                    assert_eq!(emu.get_pixel_at(px + x, py + y), pixel);
+                });
+            });
+        };
+        emu.execute(0x00C3).unwrap();
+        check(&emu, 8, 11);
+        emu.execute(0x00FB).unwrap();
+        check(&emu, 12, 11);
+        emu.execute(0x00FC).unwrap();
+        emu.execute(0x00FC).unwrap();
+        check(&emu, 4, 11);
+        assert!(!emu.get_pixel_at(8, 8));
+    }
+    #[test]
+    fn test_schip_exit() {
+        let mut emu = Processor::new_with_variant(Variant::SuperChip);
+        emu.load_program_u16(&[0x00FD]);
+        assert!(!emu.has_exited());
+        emu.step().unwrap();
+        assert!(emu.has_exited());
+        assert_eq_hex!(emu.get_program_counter(), 0x200);
+    }
+    #[test]
+    fn test_schip_ld_big_spr() {
+        let mut emu = Processor::new_with_variant(Variant::SuperChip);
+        for v in 0..0x10 {
+            emu.execute(build_inst(0x6, 0x3, 0x0, v)).unwrap();
+            emu.execute(0xF330).unwrap();
+            let i = emu.get_i() as usize;
+            for (j, b) in BIG_SPRITES[v as usize].iter().enumerate() {
+                assert_eq_hex!(emu.get_mem_at(i + j), *b);
+            }
+        }
+    }
+    #[test]
+    fn test_schip_rpl_flags() {
+        stress_test_variant(Variant::SuperChip, |emu, x, _y, _val_x, _val_y| {
+            let n = x % 8;
+            let vals: Vec<u8> = (0..=n).map(|r| emu.get_register_value(r)).collect();
+            emu.execute(build_inst(0xF, n, 0x7, 0x5)).unwrap();
+            (0..=n).for_each(|r| emu.execute(build_inst(0x6, r, 0, 0)).unwrap());
+            emu.execute(build_inst(0xF, n, 0x8, 0x5)).unwrap();
+            (0..=n).for_each(|r| assert_eq_hex!(emu.get_register_value(r), vals[r as usize]));
+        })
+    }
+    #[test]
+    fn test_schip_opcodes_invalid_on_chip8() {
+        let mut emu = Processor::new();
+        emu.execute(0xF030).unwrap_err();
+        emu.execute(0xF075).unwrap_err();
+        emu.execute(0xF085).unwrap_err();
+        let mut emu = Processor::new_with_variant(Variant::SuperChip);
+        // Only 8 RPL flags
+        emu.execute(0xF875).unwrap_err();
+        emu.execute(0xF885).unwrap_err();
+    }
+
+    #[test]
+    fn test_xo_chip_ld_i_long() {
+        let mut emu = Processor::new_with_variant(Variant::XoChip);
+        emu.load_program_u16(&[0xF000, 0xE123, 0x6042, 0xF055]);
+        emu.step().unwrap();
+        assert_eq_hex!(emu.get_i(), 0xE123);
+        assert_eq_hex!(emu.get_program_counter(), 0x204);
+        emu.step().unwrap();
+        emu.step().unwrap();
+        assert_eq_hex!(emu.get_mem_at(0xE123), 0x42);
+    }
+    #[test]
+    fn test_xo_chip_skip_long() {
+        let mut emu = Processor::new_with_variant(Variant::XoChip);
+        emu.load_program_u16(&[0x3000, 0xF000, 0x1234, 0x4000, 0xF000, 0x1234]);
+        emu.step().unwrap();
+        assert_eq_hex!(emu.get_program_counter(), 0x206);
+        emu.step().unwrap();
+        assert_eq_hex!(emu.get_program_counter(), 0x208);
+    }
+    #[test]
+    fn test_xo_chip_store_load_range() {
+        stress_test_variant(Variant::XoChip, |emu, x, y, _val_x, _val_y| {
+            let vals: Vec<u8> = (0..0x10).map(|r| emu.get_register_value(r)).collect();
+            emu.execute(0xA400).unwrap();
+            emu.execute(build_inst(0x5, x, y, 0x2)).unwrap();
+            assert_eq_hex!(emu.get_i(), 0x400);
+            let range: Vec<u8> = if x <= y {
+                (x..=y).collect()
+            } else {
+                (y..=x).rev().collect()
+            };
+            range.iter().enumerate().for_each(|(j, r)| {
+                assert_eq_hex!(emu.get_mem_at(0x400 + j), vals[*r as usize]);
+            });
+            // Load them back in the opposite order
+            emu.execute(build_inst(0x5, y, x, 0x3)).unwrap();
+            range.iter().rev().enumerate().for_each(|(j, r)| {
+                assert_eq_hex!(emu.get_register_value(*r), vals[range[j] as usize]);
+            });
+        })
+    }
+    #[test]
+    fn test_xo_chip_memory_out_of_bounds() {
+        // Ranges and audio patterns past the end of memory are errors rather than wrapping around
+        for inst in [0x5012, 0x5102, 0x5013, 0x5103, 0xF002] {
+            let mut emu = Processor::new_with_variant(Variant::XoChip);
+            emu.set_i(0xFFFF);
+            let e = emu.execute(inst).unwrap_err();
+            assert_eq!(e.get_address(), Some(0x10000));
+            assert_eq!(e.get_opcode(), Some(inst));
+        }
+        // Fits exactly
+        let mut emu = Processor::new_with_variant(Variant::XoChip);
+        emu.set_i(0xFFFE);
+        emu.execute(0x6042).unwrap();
+        emu.execute(0x5012).unwrap();
+        assert_eq_hex!(emu.get_mem_at(0xFFFE), 0x42);
+        emu.execute(0x5103).unwrap();
+        assert_eq_hex!(emu.get_register_value(1), 0x42);
+        emu.set_i(0xFFF0);
+        emu.execute(0xF002).unwrap();
+    }
+    #[test]
+    fn test_xo_chip_planes() {
+        let mut emu = Processor::new_with_variant(Variant::XoChip);
+        // Sprite for plane 1 followed by the sprite for plane 2
+        emu.execute(0xA300).unwrap();
+        emu.execute(0x60F0).unwrap();
+        emu.execute(0x613C).unwrap();
+        emu.execute(0xF155).unwrap();
+        emu.execute(0xA300).unwrap();
+        emu.execute(0x6000).unwrap();
+        emu.execute(0xF301).unwrap();
+        assert_eq!(emu.get_planes(), 3);
+        emu.execute(0xD001).unwrap();
+        let colors = [1, 1, 3, 3, 2, 2, 0, 0];
+        colors
+            .iter()
+            .enumerate()
+            .for_each(|(x, c)| assert_eq!(emu.get_pixel_color_at(x as u8, 0), *c));
+        // Clearing plane 1 should leave plane 2
+        emu.execute(0xF101).unwrap();
+        emu.execute(0x00E0).unwrap();
+        colors
+            .iter()
+            .enumerate()
+            .for_each(|(x, c)| assert_eq!(emu.get_pixel_color_at(x as u8, 0), c & 0x2));
+        // Scrolling plane 2 up should leave nothing
+        emu.execute(0xF201).unwrap();
+        emu.execute(0x00D1).unwrap();
+        (0..8).for_each(|x| assert!(!emu.get_pixel_at(x, 0)));
+    }
+    #[test]
+    fn test_xo_chip_audio() {
+        let mut emu = Processor::new_with_variant(Variant::XoChip);
+        assert_eq!(emu.get_audio_pattern(), None);
+        assert_eq!(emu.get_pitch(), 64);
+        emu.execute(0xA300).unwrap();
+        emu.execute(0x60AA).unwrap();
+        emu.execute(0x61FF).unwrap();
+        emu.execute(0xF155).unwrap();
+        emu.execute(0xA300).unwrap();
+        emu.execute(0xF002).unwrap();
+        let mut pattern = [0; 16];
+        pattern[0] = 0xAA;
+        pattern[1] = 0xFF;
+        assert_eq!(emu.get_audio_pattern(), Some(pattern));
+        emu.execute(0x6070).unwrap();
+        emu.execute(0xF03A).unwrap();
+        assert_eq!(emu.get_pitch(), 0x70);
+    }
+    #[test]
+    fn test_xo_chip_opcodes_invalid_on_schip() {
+        let mut emu = Processor::new_with_variant(Variant::SuperChip);
+        emu.execute(0x5012).unwrap_err();
+        emu.execute(0x5013).unwrap_err();
+        emu.execute(0xF000).unwrap_err();
+        emu.execute(0xF101).unwrap_err();
+        emu.execute(0xF002).unwrap_err();
+        emu.execute(0xF03A).unwrap_err();
+    }
+
+    #[test]
+    fn test_quirks_for_variant() {
+        assert_eq!(Processor::new().get_quirks(), Quirks::cosmac_vip());
+        assert_eq!(
+            Processor::new_with_variant(Variant::SuperChip).get_quirks(),
+            Quirks::schip()
+        );
+        assert_eq!(
+            Processor::new_with_variant(Variant::XoChip).get_quirks(),
+            Quirks::xo_chip()
+        );
+    }
+    #[test]
+    fn test_quirk_shift_in_place() {
+        let quirks = Quirks {
+            shift_uses_vy: false,
+            ..Quirks::cosmac_vip()
+        };
+        stress_test_quirks(quirks, |emu, x, y, val_x, val_y| {
+            emu.execute(build_inst(8, x, y, 6)).unwrap();
+            assert_eq_hex!(emu.get_register_value(x), val_x >> 1);
+            assert_eq_hex!(emu.get_register_value(0xF), val_x & 1);
+            assert_eq_hex!(emu.get_register_value(y), val_y);
+        });
+        stress_test_quirks(quirks, |emu, x, y, val_x, val_y| {
+            emu.execute(build_inst(8, x, y, 0xE)).unwrap();
+            assert_eq_hex!(emu.get_register_value(x), val_x << 1);
+            assert_eq_hex!(emu.get_register_value(0xF), val_x >> 7);
+            assert_eq_hex!(emu.get_register_value(y), val_y);
+        });
+    }
+    #[test]
+    fn test_quirk_shift_uses_vy() {
+        stress_test(|emu, x, y, _val_x, val_y| {
+            emu.execute(build_inst(8, x, y, 6)).unwrap();
+            assert_eq_hex!(emu.get_register_value(x), val_y >> 1);
+            assert_eq_hex!(emu.get_register_value(0xF), val_y & 1);
+        });
+    }
+    #[test]
+    fn test_quirk_load_store_without_increment() {
+        stress_test_quirks(Quirks::schip(), |emu, x, _y, _val_x, _val_y| {
+            emu.execute(0xA400).unwrap();
+            emu.execute(build_inst(0xF, x, 5, 5)).unwrap();
+            assert_eq_hex!(emu.get_i(), 0x400);
+            emu.execute(build_inst(0xF, x, 6, 5)).unwrap();
+            assert_eq_hex!(emu.get_i(), 0x400);
+        });
+    }
+    #[test]
+    fn test_quirk_logic_keeps_vf() {
+        stress_test_quirks(Quirks::schip(), |emu, x, y, _val_x, _val_y| {
+            for op in 1..=3 {
+                emu.execute(build_inst(6, 0xF, 0, 2)).unwrap();
+                emu.execute(build_inst(8, x, y, op)).unwrap();
+                assert_eq_hex!(emu.get_register_value(0xF), 2);
+            }
+        });
+    }
+    #[test]
+    fn test_quirk_jump_uses_vx() {
+        let mut emu = Processor::new_with_quirks(Variant::Chip8, Quirks::chip48());
+        emu.execute(0x6010).unwrap();
+        emu.execute(0x6320).unwrap();
+        emu.execute(0xB345).unwrap();
+        assert_eq_hex!(emu.get_program_counter(), 0x345 + 0x20 - 2);
+    }
+    #[test]
+    fn test_quirk_wrap_sprites() {
+        let quirks = Quirks {
+            clip_sprites: false,
+            ..Quirks::cosmac_vip()
+        };
+        let mut emu = Processor::new_with_quirks(Variant::Chip8, quirks);
+        emu.on_v_blank();
+        // Draw the 0 sprite in the bottom right corner
+        emu.execute(0x6000).unwrap();
+        emu.execute(0xF029).unwrap();
+        emu.execute(0x613C).unwrap();
+        emu.execute(0x621E).unwrap();
+        emu.execute(0xD125).unwrap();
+        (0..5).for_each(|y| {
+            (0..8).for_each(|x| {
+                let pixel = (SPRITES[0][y] >> (7 - x) & 0x1) == 0x1;
+                let (px, py) = ((0x3C + x) % 64, (0x1E + y) % 32);
+                assert_eq!(emu.get_pixel_at(px as u8, py as u8), pixel);
+            });
+        });
+    }
+    #[test]
+    fn test_quirk_no_display_wait() {
+        let mut emu = Processor::new_with_quirks(Variant::Chip8, Quirks::chip48());
+        emu.load_program_u16(&[0xD005, 0xD005]);
+        emu.step().unwrap();
+        emu.step().unwrap();
+        assert_eq_hex!(emu.get_program_counter(), 0x204);
+        // With the wait, the processor should stay on the draw instruction until vblank
+        let mut emu = Processor::new();
+        emu.load_program_u16(&[0xD005]);
+        emu.step().unwrap();
+        assert_eq_hex!(emu.get_program_counter(), 0x200);
+        emu.on_v_blank();
+        emu.step().unwrap();
+        assert_eq_hex!(emu.get_program_counter(), 0x202);
+    }
+
     /*
      * Run a block of tests on two random registers with 2 random values assigned to them
      * Used for basic tests
      * Value of register X is guaranteed to be larger than value of register y
      */
     fn stress_test(f: fn(&mut Processor, u8, u8, u8, u8)) {
-        let mut emu: Processor = Processor::new();
+        stress_test_variant(Variant::Chip8, f)
+    }
+    fn stress_test_variant(variant: Variant, f: fn(&mut Processor, u8, u8, u8, u8)) {
+        stress_test_with(|| Processor::new_with_variant(variant), f)
+    }
+    fn stress_test_quirks(quirks: Quirks, f: fn(&mut Processor, u8, u8, u8, u8)) {
+        stress_test_with(|| Processor::new_with_quirks(Variant::Chip8, quirks), f)
+    }
+    fn stress_test_with(new: impl Fn() -> Processor, f: fn(&mut Processor, u8, u8, u8, u8)) {
+        let mut emu: Processor = new();
         for vx in 0..15 {
             let mut vy = rand_byte(0xE - 1) as u8;
             if vy >= vx {